@@ -0,0 +1,71 @@
+//! 比较两种解析方式处理大value的开销:
+//!
+//! - copy: `Frame::check` + `Frame::parse`, bulk string 从读缓冲复制到新分配的 `Bytes`
+//! - zero_copy: `Frame::check` + `split_to(..).freeze()` + `Frame::parse_shared`,
+//!   bulk string 直接引用读缓冲
+//!
+//! `cargo bench --bench frame_parse`
+
+use std::io::Cursor;
+
+use bytes::{Buf, BufMut, BytesMut};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use hello_world::minis_redis::frame::{Frame, Limits};
+
+/// 一条 `SET key <value>` 命令的编码
+fn set_command(value_len: usize) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(value_len + 64);
+    buf.put_slice(b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n");
+    buf.put_slice(format!("${}\r\n", value_len).as_bytes());
+    buf.put_bytes(b'x', value_len);
+    buf.put_slice(b"\r\n");
+    buf
+}
+
+fn parse_copy(buffer: &mut BytesMut, limits: &Limits) -> Frame {
+    let mut cursor = Cursor::new(&buffer[..]);
+    Frame::check(&mut cursor, limits).unwrap();
+    let len = cursor.position() as usize;
+    cursor.set_position(0);
+    let frame = Frame::parse(&mut cursor).unwrap();
+    buffer.advance(len);
+    frame
+}
+
+fn parse_zero_copy(buffer: &mut BytesMut, limits: &Limits) -> Frame {
+    let mut cursor = Cursor::new(&buffer[..]);
+    Frame::check(&mut cursor, limits).unwrap();
+    let len = cursor.position() as usize;
+    let data = buffer.split_to(len).freeze();
+    Frame::parse_shared(&data).unwrap()
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let limits = Limits::default();
+    let mut group = c.benchmark_group("parse_set");
+
+    for size in [1 << 10, 16 << 10, 256 << 10, 1 << 20] {
+        let raw = set_command(size);
+        group.throughput(Throughput::Bytes(raw.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("copy", size), &raw, |b, raw| {
+            b.iter_batched(
+                || BytesMut::from(&raw[..]),
+                |mut buffer| parse_copy(&mut buffer, &limits),
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_with_input(BenchmarkId::new("zero_copy", size), &raw, |b, raw| {
+            b.iter_batched(
+                || BytesMut::from(&raw[..]),
+                |mut buffer| parse_zero_copy(&mut buffer, &limits),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
@@ -2,7 +2,7 @@ use std::time::Duration;
 
 use bytes::Bytes;
 use tokio::{sync::{ mpsc, oneshot }, time::sleep};
-use mini_redis::{ Connection, Frame, client };
+use hello_world::minis_redis::client;
 
 #[derive(Debug)]
 enum Command {
@@ -17,7 +17,7 @@ enum Command {
     }
 }
 
-type Responder<T> = oneshot::Sender<mini_redis::Result<T>>;
+type Responder<T> = oneshot::Sender<hello_world::minis_redis::Result<T>>;
 
 #[tokio::main]
 async fn main() {
@@ -1,7 +1,7 @@
 use tokio_stream::StreamExt;
-use mini_redis::client;
+use hello_world::minis_redis::client;
 
-async fn publish() -> mini_redis::Result<()> {
+async fn publish() -> hello_world::minis_redis::Result<()> {
     let mut client = client::connect("127.0.0.1:6379").await?;
 
     // Publish some data
@@ -14,7 +14,7 @@ async fn publish() -> mini_redis::Result<()> {
     Ok(())
 }
 
-async fn subscribe() -> mini_redis::Result<()> {
+async fn subscribe() -> hello_world::minis_redis::Result<()> {
     let client = client::connect("127.0.0.1:6379").await?;
     let subscriber = client.subscribe(vec!["numbers".to_string()]).await?;
     let messages = subscriber.into_stream();
@@ -29,7 +29,7 @@ async fn subscribe() -> mini_redis::Result<()> {
 }
 
 #[tokio::main]
-async fn main() -> mini_redis::Result<()> {
+async fn main() -> hello_world::minis_redis::Result<()> {
     tokio::spawn(async {
         publish().await
     });
@@ -1,79 +1,812 @@
 use bytes::Bytes;
-use std::collections::hash_map::{DefaultHasher, HashMap};
-use std::hash::{Hash, Hasher};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::future::Future;
+use std::net::SocketAddr;
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::signal;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time;
+use tracing::{debug, error, info, warn};
 
-use mini_redis::{Command, Connection, Frame};
+use hello_world::minis_redis::aof::{self, Aof, FsyncPolicy};
+use hello_world::minis_redis::blocked::Outcome;
+use hello_world::minis_redis::cmd::{Client, Exec, Info, Save, Session};
+use hello_world::minis_redis::connection::Timeouts;
+use hello_world::minis_redis::db::Db;
+use hello_world::minis_redis::frame::{self, Limits, Protocol};
+use hello_world::minis_redis::pool::BufferPool;
+use hello_world::minis_redis::pubsub::{PubSub, Subscriptions};
+use hello_world::minis_redis::snapshot::Snapshots;
+use hello_world::minis_redis::stats::{Snapshot, Stats};
+use hello_world::minis_redis::transaction::Transaction;
+use hello_world::minis_redis::{Command, Connection, Frame};
 
-// type Db = Arc<Mutex<HashMap<String, Bytes>>>;
+/// 客户端连上之后什么都不发, 或者两个命令之间空闲太久, 都会被断开
+const TIMEOUTS: Timeouts = Timeouts {
+    idle: Some(Duration::from_secs(300)),
+    read: Some(Duration::from_secs(30)),
+    write: Some(Duration::from_secs(30)),
+};
 
-// 分片
-type ShardedDb = Arc<Vec<Mutex<HashMap<String, Bytes>>>>;
+/// 关闭时最多等这么久让连接处理完手上的请求
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
 
-fn new_sharded_db() -> ShardedDb {
-    let num_sharded = 5;
-    let mut v = Vec::with_capacity(num_sharded);
-    for _ in 0..num_sharded {
-        v.push(Mutex::new(HashMap::new()));
+/// 分片个数
+const NUM_SHARDS: usize = 5;
+
+/// 所有连接的登记表, 用于 CLIENT LIST 和 INFO
+type Clients = Arc<Mutex<ClientRegistry>>;
+
+#[derive(Default)]
+struct ClientRegistry {
+    /// 下一个连接的id, 也就是一共接受过多少个连接
+    next_id: u64,
+    /// 按id排序, CLIENT LIST 按连接的先后输出
+    conns: BTreeMap<u64, ClientInfo>,
+    /// 已经断开的连接的统计, 加到INFO的总数里
+    closed: Snapshot,
+    /// 所有连接共用的读缓冲池
+    pool: BufferPool,
+}
+
+struct ClientInfo {
+    addr: SocketAddr,
+    stats: Arc<Stats>,
+}
+
+impl ClientRegistry {
+    fn register(&mut self, addr: SocketAddr, stats: Arc<Stats>) -> u64 {
+        self.next_id += 1;
+        self.conns.insert(self.next_id, ClientInfo { addr, stats });
+        self.next_id
+    }
+
+    fn unregister(&mut self, id: u64) {
+        if let Some(info) = self.conns.remove(&id) {
+            self.closed += info.stats.snapshot();
+        }
+    }
+
+    fn client_list(&self) -> String {
+        let mut out = String::new();
+        for (id, info) in &self.conns {
+            let stats = info.stats.snapshot();
+            let _ = writeln!(
+                out,
+                "id={} addr={} age={} idle={} tot-net-in={} tot-net-out={} tot-frames-in={} tot-frames-out={} parse-errors={}",
+                id,
+                info.addr,
+                stats.age.as_secs(),
+                stats.idle.as_secs(),
+                stats.bytes_read,
+                stats.bytes_written,
+                stats.frames_read,
+                stats.frames_written,
+                stats.parse_errors,
+            );
+        }
+        out
+    }
+
+    fn info(&self, cmd: &Info) -> String {
+        let mut sections = vec![];
+        if cmd.wants("clients") {
+            sections.push(format!("# Clients\r\nconnected_clients:{}\r\n", self.conns.len()));
+        }
+        if cmd.wants("memory") {
+            sections.push(format!(
+                "# Memory\r\n\
+                 mem_clients_input_buffers:{}\r\n\
+                 pooled_input_buffers:{}\r\n",
+                self.pool.buffered_bytes(),
+                self.pool.pooled(),
+            ));
+        }
+        if cmd.wants("stats") {
+            let mut total = self.closed;
+            for info in self.conns.values() {
+                total += info.stats.snapshot();
+            }
+            sections.push(format!(
+                "# Stats\r\n\
+                 total_connections_received:{}\r\n\
+                 total_net_input_bytes:{}\r\n\
+                 total_net_output_bytes:{}\r\n\
+                 total_frames_read:{}\r\n\
+                 total_frames_written:{}\r\n\
+                 total_parse_errors:{}\r\n",
+                self.next_id,
+                total.bytes_read,
+                total.bytes_written,
+                total.frames_read,
+                total.frames_written,
+                total.parse_errors,
+            ));
+        }
+        sections.join("\r\n")
     }
-    Arc::new(v)
 }
+
 #[tokio::main]
-async fn main() {
+async fn main() -> hello_world::minis_redis::Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let config = Config::from_args(std::env::args().skip(1))?;
     let port = 6377;
-    let listener = TcpListener::bind(format!("127.0.0.1:{}", port))
-        .await
-        .unwrap();
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port)).await?;
     // 根据经验来说，只要锁竞争比较弱，且不会跨await(across await)持有锁，则可以使用同步mutex(std mutex);
     // 如果必须跨await持有锁，则只能使用tokio::sync::Mutex(其内部也是使用同步mutex, 因此也会阻塞线程);
     // 尽量避免使用tokio::sync::Mutex(异步mutex), 因为其性能损耗相对于常规mutex来说比较大
-    let db = new_sharded_db();
-    println!("listening port {}", port);
+    let mut db = Db::new(NUM_SHARDS);
+    let snapshots = Snapshots::new(&config.dir);
+    // 和redis一样, 开启了AOF就只用AOF恢复: AOF里的数据总是比快照新
+    match &config.appendonly {
+        Some((path, policy)) => {
+            // 先回放再打开AOF, 回放的命令不能再写一遍
+            let replay = aof::replay(path, &db)?;
+            info!(path = %path, commands = replay.commands, truncated = replay.truncated, "loaded AOF");
+            let aof = Aof::open(path, *policy)?;
+            aof.spawn_fsync_task();
+            db = db.with_aof(aof);
+        }
+        None => {
+            if let Some((path, keys)) = snapshots.load_newest(&db)? {
+                info!(path = %path.display(), keys, "loaded snapshot");
+            }
+        }
+    }
+    db.spawn_purge_tasks();
+    info!("listening port {}", port);
 
-    loop {
-        let (socket, _) = listener.accept().await.unwrap();
-        let db = db.clone();
+    serve(listener, db, snapshots, shutdown_signal(), DRAIN_TIMEOUT).await;
+    Ok(())
+}
 
-        println!("new connection...");
-        tokio::spawn(async {
-            process(socket, db).await;
-        });
+/// 接受连接, 直到 `shutdown` 完成。
+///
+/// 然后关掉监听的socket, 通知所有连接处理完手上的请求就退出, 在 `drain_timeout` 之内等它们
+/// 和正在进行的BGSAVE结束, 最后把AOF还没落盘的部分fsync掉
+async fn serve(listener: TcpListener, db: Db, snapshots: Snapshots, shutdown: impl Future, drain_timeout: Duration) {
+    let (notify_shutdown, _) = broadcast::channel(1);
+    let (shutdown_complete_tx, mut shutdown_complete_rx) = mpsc::channel(1);
+    let mut server = Listener {
+        listener,
+        db,
+        clients: Clients::default(),
+        pubsub: PubSub::new(),
+        snapshots,
+        notify_shutdown,
+        shutdown_complete_tx,
+    };
+
+    tokio::select! {
+        res = server.run() => {
+            // 一直accept失败才会走到这里
+            if let Err(err) = res {
+                error!(%err, "failed to accept");
+            }
+        }
+        _ = shutdown => {
+            info!("shutting down");
+        }
+    }
+
+    let Listener {
+        listener,
+        db,
+        clients,
+        snapshots,
+        notify_shutdown,
+        shutdown_complete_tx,
+        ..
+    } = server;
+    // 先关掉监听的socket, 排水期间新的连接直接被拒绝, 而不是连上之后在退出时被reset
+    drop(listener);
+    // drop掉 `notify_shutdown` 通知所有连接: 处理完手上的请求就退出
+    drop(notify_shutdown);
+    drop(shutdown_complete_tx);
+
+    // 每个连接持有一个 `shutdown_complete_tx` 的clone, 全部退出之后recv才返回
+    let deadline = time::Instant::now() + drain_timeout;
+    match time::timeout_at(deadline, shutdown_complete_rx.recv()).await {
+        Ok(_) => info!("all connections closed"),
+        Err(_) => warn!(
+            "{} connections still open after {:?}, exiting anyway",
+            clients.lock().unwrap().conns.len(),
+            drain_timeout
+        ),
+    }
+
+    // 进程退出时后台线程会被直接杀掉, 留下写了一半的临时文件, 在同一个期限内等BGSAVE写完
+    if time::timeout_at(deadline, snapshots.wait_background()).await.is_err() {
+        warn!("background saving still running after {:?}, exiting anyway", drain_timeout);
+    }
+
+    // 连接都退出了, 不会再有新的记录, 把 `everysec` / `no` 还没落盘的部分fsync掉
+    if let Some(aof) = db.aof() {
+        if let Err(err) = aof.sync() {
+            error!(%err, "failed to fsync AOF");
+        }
+    }
+}
+
+/// 命令行参数, 和redis.conf里的同名配置一样:
+///
+/// ```text
+/// server [--dir <dir>] [--appendonly yes|no] [--appendfilename <file>] [--appendfsync always|everysec|no]
+/// ```
+struct Config {
+    /// 快照所在的目录
+    dir: String,
+    /// 开启AOF时为文件路径和fsync策略
+    appendonly: Option<(String, FsyncPolicy)>,
+}
+
+impl Config {
+    fn from_args(mut args: impl Iterator<Item = String>) -> hello_world::minis_redis::Result<Config> {
+        let mut dir = ".".to_string();
+        let mut appendonly = false;
+        let mut path = "appendonly.aof".to_string();
+        let mut policy = FsyncPolicy::EverySec;
+
+        while let Some(arg) = args.next() {
+            let Some(value) = args.next() else {
+                return Err(format!("missing value for '{}'", arg).into());
+            };
+            match &arg[..] {
+                "--appendonly" => {
+                    appendonly = match &value[..] {
+                        "yes" => true,
+                        "no" => false,
+                        _ => return Err(format!("invalid value for '--appendonly': '{}'", value).into()),
+                    }
+                }
+                "--dir" => dir = value,
+                "--appendfilename" => path = value,
+                "--appendfsync" => policy = value.parse()?,
+                _ => return Err(format!("unknown option '{}'", arg).into()),
+            }
+        }
+
+        Ok(Config {
+            dir,
+            appendonly: appendonly.then_some((path, policy)),
+        })
+    }
+}
+
+/// ctrl-c 或者 SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = signal::ctrl_c() => {}
+                    _ = sigterm.recv() => {}
+                }
+                return;
+            }
+            Err(err) => error!(%err, "failed to listen for SIGTERM"),
+        }
+    }
+
+    if let Err(err) = signal::ctrl_c().await {
+        error!(%err, "failed to listen for ctrl-c");
+        // 收不到信号就只能一直运行下去
+        std::future::pending::<()>().await;
+    }
+}
+
+/// 接受连接, 每个连接一个 `Handler`
+struct Listener {
+    listener: TcpListener,
+    db: Db,
+    clients: Clients,
+    pubsub: PubSub,
+    snapshots: Snapshots,
+    /// 关闭时drop, 通知所有连接退出
+    notify_shutdown: broadcast::Sender<()>,
+    /// 每个连接一个clone, 连接都退出之后main才知道可以结束了
+    shutdown_complete_tx: mpsc::Sender<()>,
+}
+
+impl Listener {
+    async fn run(&mut self) -> hello_world::minis_redis::Result<()> {
+        loop {
+            let (socket, addr) = self.accept().await?;
+
+            let pool = self.clients.lock().unwrap().pool.clone();
+            let conn = Connection::with_pool(socket, Limits::default(), pool);
+            let id = self.clients.lock().unwrap().register(addr, conn.stats().clone());
+
+            let mut handler = Handler {
+                id,
+                conn,
+                timeouts: TIMEOUTS,
+                db: self.db.clone(),
+                clients: self.clients.clone(),
+                pubsub: self.pubsub.clone(),
+                snapshots: self.snapshots.clone(),
+                transaction: Transaction::new(self.db.clone()),
+                shutdown: Shutdown::new(self.notify_shutdown.subscribe()),
+                _shutdown_complete: self.shutdown_complete_tx.clone(),
+            };
+
+            debug!(id, %addr, "new connection");
+            tokio::spawn(async move {
+                // 出错只关闭这一个连接
+                match handler.run().await {
+                    Ok(()) => debug!(id = handler.id, "connection closed"),
+                    Err(err) => warn!(id = handler.id, %err, "connection error"),
+                }
+                handler.clients.lock().unwrap().unregister(handler.id);
+            });
+        }
+    }
+
+    /// accept失败(比如文件描述符用完了)时等一会再重试, 等待时间每次翻倍, 超过64秒就放弃
+    async fn accept(&mut self) -> hello_world::minis_redis::Result<(TcpStream, SocketAddr)> {
+        let mut backoff = 1;
+
+        loop {
+            match self.listener.accept().await {
+                Ok(accepted) => return Ok(accepted),
+                Err(err) => {
+                    if backoff > 64 {
+                        return Err(err.into());
+                    }
+                    warn!(%err, "accept error, retrying in {}s", backoff);
+                }
+            }
+
+            time::sleep(Duration::from_secs(backoff)).await;
+            backoff *= 2;
+        }
     }
 }
 
-fn hash<T: Hash>(v: T) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    v.hash(&mut hasher);
-    hasher.finish()
+/// 监听关闭通知
+struct Shutdown {
+    is_shutdown: bool,
+    notify: broadcast::Receiver<()>,
 }
 
-async fn process(socket: TcpStream, db: ShardedDb) {
-    println!("processing...");
+impl Shutdown {
+    fn new(notify: broadcast::Receiver<()>) -> Shutdown {
+        Shutdown {
+            is_shutdown: false,
+            notify,
+        }
+    }
 
-    let mut conn = Connection::new(socket);
+    fn is_shutdown(&self) -> bool {
+        self.is_shutdown
+    }
 
-    while let Some(frame) = conn.read_frame().await.unwrap() {
-        let response = match Command::from_frame(frame).unwrap() {
-            Command::Get(cmd) => {
-                let shard = &db[hash(cmd.key()) as usize % db.len()];
-                let db = shard.lock().unwrap();
-                if let Some(x) = db.get(cmd.key()) {
-                    Frame::Bulk(x.clone())
-                } else {
-                    Frame::Null
+    /// 等到收到关闭通知, 已经收到过的话马上返回
+    async fn recv(&mut self) {
+        if self.is_shutdown {
+            return;
+        }
+        // sender被drop时返回错误, 这就是关闭通知
+        let _ = self.notify.recv().await;
+        self.is_shutdown = true;
+    }
+}
+
+/// 一个连接的处理状态
+struct Handler<S> {
+    id: u64,
+    conn: Connection<S>,
+    /// 普通模式下连接的超时, 订阅模式下不会因为空闲被断开
+    timeouts: Timeouts,
+    db: Db,
+    clients: Clients,
+    pubsub: PubSub,
+    snapshots: Snapshots,
+    /// MULTI之后排队的命令和WATCH的key
+    transaction: Transaction,
+    shutdown: Shutdown,
+    /// 从来不发送, 连接退出时随 `Handler` 一起drop, 告诉main少了一个连接
+    _shutdown_complete: mpsc::Sender<()>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Handler<S> {
+    async fn run(&mut self) -> hello_world::minis_redis::Result<()> {
+        self.conn.set_timeouts(self.timeouts);
+        while !self.shutdown.is_shutdown() {
+            // 只在等下一个请求的时候响应关闭, 已经读到的请求会处理完再退出
+            let res = tokio::select! {
+                res = self.conn.read_frame() => res,
+                _ = self.shutdown.recv() => return Ok(()),
+            };
+            let frame = match res {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return Ok(()),
+                Err(err) => return self.read_failed(err).await,
+            };
+            if !self.handle(frame).await? {
+                return Ok(());
+            }
+
+            // pipeline: 客户端可能一次发来多个请求, 先把已经读到缓冲里的请求都处理完,
+            // 回复都写进写缓冲, 最后只flush一次, 而不是每个回复一次syscall
+            loop {
+                match self.conn.try_read_frame() {
+                    Ok(Some(frame)) => {
+                        if !self.handle(frame).await? {
+                            return Ok(());
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(err) => return self.read_failed(err).await,
                 }
             }
-            Command::Set(cmd) => {
-                let shard = &db[hash(cmd.key()) as usize % db.len()];
-                let mut db = shard.lock().unwrap();
-                db.insert(cmd.key().to_string(), cmd.value().clone());
-                Frame::Simple("OK".to_string())
+
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// 执行一个请求, 回复写进写缓冲。订阅类的命令让连接进入订阅模式, 直到退订了所有的频道和模式。
+    ///
+    /// MULTI之后除了EXEC、DISCARD这几个控制事务的命令, 其他命令都只是排队, EXEC时才执行。
+    ///
+    /// 返回false表示连接要关闭, 缓冲里后面的请求不能再处理
+    async fn handle(&mut self, frame: Frame) -> hello_world::minis_redis::Result<bool> {
+        let cmd = match parse_command(self.id, frame) {
+            Ok(cmd) => cmd,
+            Err(reply) => {
+                // 事务中的命令解析失败, EXEC时放弃整个事务
+                self.transaction.fail();
+                self.conn.feed_frame(&reply).await?;
+                return Ok(true);
+            }
+        };
+        if is_pubsub(&cmd) && !self.transaction.is_active() {
+            let mut subscriptions = Subscriptions::new(self.pubsub.clone());
+            for reply in apply_subscribed(cmd, &mut subscriptions, self.conn.protocol()) {
+                self.conn.feed_frame(&reply).await?;
             }
-            cmd => panic!("unimplemented {:?}", cmd),
+            self.subscribed(subscriptions).await?;
+            return Ok(true);
+        }
+
+        let name = cmd.get_name().to_string();
+        let response = match cmd {
+            Command::Multi(cmd) => Some(cmd.apply(&mut self.transaction)),
+            Command::Exec(cmd) => Some(self.exec(cmd).await),
+            Command::Discard(cmd) => Some(cmd.apply(&mut self.transaction)),
+            Command::Watch(cmd) => Some(cmd.apply(&mut self.transaction)),
+            cmd if self.transaction.is_active() => Some(self.transaction.queue(cmd)),
+            Command::Unwatch(cmd) => Some(cmd.apply(&mut self.transaction)),
+            Command::BLPop(cmd) => self.block(cmd.apply(&self.db)).await?,
+            Command::BRPop(cmd) => self.block(cmd.apply(&self.db)).await?,
+            Command::BLMove(cmd) => self.block(cmd.apply(&self.db)).await?,
+            Command::Save(cmd) => Some(self.save(cmd).await),
+            cmd => Some(self.apply(cmd)),
+        };
+        // 阻塞期间客户端断开了。后面的请求要是照常执行, 它们的回复就会错位到这条命令上
+        let Some(response) = response else {
+            return Ok(false);
         };
+        if let Frame::Error(msg) = &response {
+            debug!(id = self.id, cmd = %name, error = %msg, "command failed");
+        }
+        self.conn.feed_frame(&response).await?;
+        Ok(true)
+    }
 
-        conn.write_frame(&response).await.unwrap();
+    /// 把写缓冲中的回复发出去。AOF是 `always` 时先等这些回复对应的记录fsync到磁盘,
+    /// 这时分片的锁早就放开了, 同时在等的连接共用一次fsync
+    async fn flush(&mut self) -> hello_world::minis_redis::Result<()> {
+        if let Some(aof) = self.db.aof() {
+            aof.commit().await;
+        }
+        self.conn.flush().await?;
+        Ok(())
+    }
+
+    /// 阻塞的命令没能马上拿到数据时, 等到拿到数据或者超时。
+    ///
+    /// 之前的回复先flush出去。等待期间客户端发来的请求先留在读缓冲里, 解除阻塞之后再处理。
+    /// 客户端断开(也可能只是关闭了写的一端)或者读出错就不再等待, 返回None, 连接随后关闭
+    async fn block(&mut self, outcome: Outcome) -> hello_world::minis_redis::Result<Option<Frame>> {
+        let mut blocked = match outcome {
+            Outcome::Ready(frame) => return Ok(Some(frame)),
+            Outcome::Blocked(blocked) => blocked,
+        };
+        self.flush().await?;
+
+        loop {
+            tokio::select! {
+                reply = blocked.reply() => return Ok(Some(reply)),
+                res = self.conn.fill_buffer() => {
+                    if !matches!(res, Ok(n) if n > 0) {
+                        debug!(id = self.id, "client gone while blocked");
+                        // 放弃之前已经被服务了: 元素已经弹出, 也写进了AOF, 回复尽量送到。
+                        // 半关闭的客户端还能收到, 写不出去也没关系, 连接马上就关了
+                        if let Some(reply) = blocked.cancel() {
+                            if self.conn.feed_frame(&reply).await.is_ok() {
+                                let _ = self.flush().await;
+                            }
+                        }
+                        return Ok(None);
+                    }
+                }
+                // 和处理中的请求一样, 回复之后再退出
+                _ = self.shutdown.recv() => return Ok(Some(blocked.cancel().unwrap_or(Frame::Null))),
+            }
+        }
+    }
+
+    /// 订阅模式: 一边把订阅到的消息推给客户端, 一边处理客户端新的(P)SUBSCRIBE/(P)UNSUBSCRIBE
+    async fn subscribed(&mut self, mut subscriptions: Subscriptions) -> hello_world::minis_redis::Result<()> {
+        // 订阅者可能很久都收不到消息, 也不会发命令, 不能因为空闲被断开
+        self.conn.set_timeouts(Timeouts {
+            idle: None,
+            ..self.timeouts
+        });
+
+        while !subscriptions.is_empty() {
+            self.flush().await?;
+
+            tokio::select! {
+                Some(message) = subscriptions.next_message() => {
+                    self.conn.feed_frame(&message).await?;
+                }
+                res = self.conn.read_frame() => {
+                    let frame = match res {
+                        Ok(Some(frame)) => frame,
+                        Ok(None) => return Ok(()),
+                        Err(err) => return self.read_failed(err).await,
+                    };
+                    self.handle_subscribed(frame, &mut subscriptions).await?;
+                    // 退订了所有的频道之后, 缓冲里剩下的请求回到普通模式处理
+                    while !subscriptions.is_empty() {
+                        match self.conn.try_read_frame() {
+                            Ok(Some(frame)) => self.handle_subscribed(frame, &mut subscriptions).await?,
+                            Ok(None) => break,
+                            Err(err) => return self.read_failed(err).await,
+                        }
+                    }
+                }
+                _ = self.shutdown.recv() => {
+                    // 已经写进缓冲的消息和回复还是要送出去
+                    self.flush().await?;
+                    return Ok(());
+                }
+            }
+        }
+
+        // 回到普通模式, 最后一个退订的回复由外面的循环flush
+        self.conn.set_timeouts(self.timeouts);
+        Ok(())
+    }
+
+    async fn handle_subscribed(
+        &mut self,
+        frame: Frame,
+        subscriptions: &mut Subscriptions,
+    ) -> hello_world::minis_redis::Result<()> {
+        let replies = match parse_command(self.id, frame) {
+            Ok(cmd) => apply_subscribed(cmd, subscriptions, self.conn.protocol()),
+            Err(reply) => vec![reply],
+        };
+        for reply in replies {
+            if let Frame::Error(msg) = &reply {
+                debug!(id = self.id, error = %msg, "command failed in subscribed mode");
+            }
+            self.conn.feed_frame(&reply).await?;
+        }
+        Ok(())
+    }
+
+    /// EXEC。队列中有SAVE时, 放开分片的锁之后再写快照, 写完才回复
+    async fn exec(&mut self, cmd: Exec) -> Frame {
+        let mut session = Keyless {
+            id: self.id,
+            conn: &mut self.conn,
+            db: &self.db,
+            clients: &self.clients,
+            pubsub: &self.pubsub,
+            snapshots: &self.snapshots,
+        };
+        let mut reply = cmd.apply(&mut self.transaction, &mut session);
+        for save in self.transaction.take_saves() {
+            let index = save.index();
+            let snapshots = self.snapshots.clone();
+            let saved = tokio::task::spawn_blocking(move || save.write(&snapshots))
+                .await
+                .unwrap_or_else(|err| Frame::Error(format!("ERR saving failed: {}", err)));
+            if let Frame::Array(replies) = &mut reply {
+                replies[index] = saved;
+            }
+        }
+        reply
+    }
+
+    /// SAVE要等文件写完, 放到专门跑阻塞任务的线程上, 不占tokio的工作线程
+    async fn save(&self, cmd: Save) -> Frame {
+        let db = self.db.clone();
+        let snapshots = self.snapshots.clone();
+        tokio::task::spawn_blocking(move || cmd.apply(&db, &snapshots))
+            .await
+            .unwrap_or_else(|err| Frame::Error(format!("ERR saving failed: {}", err)))
+    }
+
+    /// 读请求失败。协议错误先回一个错误再关闭连接, 客户端断开和超时直接关闭,
+    /// 其他的IO错误返回给上层记录
+    async fn read_failed(&mut self, err: frame::Error) -> hello_world::minis_redis::Result<()> {
+        match err {
+            frame::Error::ProtocolViolation(_) | frame::Error::FrameTooLarge(_) => {
+                warn!(id = self.id, %err, "protocol error, closing connection");
+                // 连接马上就要关闭, 错误回复写不出去也没关系
+                let _ = self.conn.write_frame(&Frame::Error(format!("ERR {}", err))).await;
+                Ok(())
+            }
+            frame::Error::ConnectionReset => {
+                debug!(id = self.id, "connection reset by peer");
+                Ok(())
+            }
+            frame::Error::Timeout(kind) => {
+                info!(id = self.id, %kind, "connection timed out");
+                Ok(())
+            }
+            err => Err(err.into()),
+        }
+    }
+
+    fn apply(&mut self, cmd: Command) -> Frame {
+        let db = &self.db;
+        match cmd {
+            Command::Get(cmd) => cmd.apply(db),
+            Command::Set(cmd) => cmd.apply(db),
+            Command::SetNx(cmd) => cmd.apply(db),
+            Command::GetSet(cmd) => cmd.apply(db),
+            Command::MGet(cmd) => cmd.apply(db),
+            Command::MSet(cmd) => cmd.apply(db),
+            Command::Del(cmd) => cmd.apply(db),
+            Command::Exists(cmd) => cmd.apply(db),
+            Command::Incr(cmd) => cmd.apply(db),
+            Command::Decr(cmd) => cmd.apply(db),
+            Command::IncrBy(cmd) => cmd.apply(db),
+            Command::DecrBy(cmd) => cmd.apply(db),
+            Command::Append(cmd) => cmd.apply(db),
+            Command::Strlen(cmd) => cmd.apply(db),
+            Command::Expire(cmd) => cmd.apply(db),
+            Command::PExpire(cmd) => cmd.apply(db),
+            Command::PExpireAt(cmd) => cmd.apply(db),
+            Command::Ttl(cmd) => cmd.apply(db),
+            Command::PTtl(cmd) => cmd.apply(db),
+            Command::Persist(cmd) => cmd.apply(db),
+            Command::LPush(cmd) => cmd.apply(db),
+            Command::RPush(cmd) => cmd.apply(db),
+            Command::LPop(cmd) => cmd.apply(db),
+            Command::RPop(cmd) => cmd.apply(db),
+            Command::LRange(cmd) => cmd.apply(db),
+            Command::LLen(cmd) => cmd.apply(db),
+            Command::LMove(cmd) => cmd.apply(db),
+            Command::HSet(cmd) => cmd.apply(db),
+            Command::HGet(cmd) => cmd.apply(db),
+            Command::HGetAll(cmd) => cmd.apply(db),
+            Command::HDel(cmd) => cmd.apply(db),
+            Command::HIncrBy(cmd) => cmd.apply(db),
+            Command::SAdd(cmd) => cmd.apply(db),
+            Command::SRem(cmd) => cmd.apply(db),
+            Command::SMembers(cmd) => cmd.apply(db),
+            Command::SInter(cmd) => cmd.apply(db),
+            Command::SUnion(cmd) => cmd.apply(db),
+            Command::ZAdd(cmd) => cmd.apply(db),
+            Command::ZRange(cmd) => cmd.apply(db),
+            Command::ZRangeByScore(cmd) => cmd.apply(db),
+            Command::ZRank(cmd) => cmd.apply(db),
+            Command::ZIncrBy(cmd) => cmd.apply(db),
+            Command::Ping(cmd) => cmd.response(),
+            Command::Unknown(cmd) => cmd.response(),
+            cmd => Keyless {
+                id: self.id,
+                conn: &mut self.conn,
+                db: &self.db,
+                clients: &self.clients,
+                pubsub: &self.pubsub,
+                snapshots: &self.snapshots,
+            }
+            .apply(cmd),
+        }
+    }
+}
+
+/// 不访问key的命令要用到的连接和服务器的状态。事务外由 `Handler::apply` 直接执行,
+/// 事务中排队的由EXEC交给它执行
+struct Keyless<'a, S> {
+    id: u64,
+    conn: &'a mut Connection<S>,
+    db: &'a Db,
+    clients: &'a Clients,
+    pubsub: &'a PubSub,
+    snapshots: &'a Snapshots,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Session for Keyless<'_, S> {
+    fn apply(&mut self, cmd: Command) -> Frame {
+        match cmd {
+            // HELLO 会切换连接的协议, 回复在切换之后才编码
+            Command::Hello(cmd) => cmd.apply(self.conn),
+            Command::Client(Client::Id) => Frame::Integer(self.id as i64),
+            Command::Client(Client::List) => text(self.clients.lock().unwrap().client_list()),
+            Command::Info(cmd) => text(self.clients.lock().unwrap().info(&cmd)),
+            Command::Publish(cmd) => cmd.apply(self.pubsub),
+            Command::BgSave(cmd) => cmd.apply(self.db, self.snapshots),
+            // 只有事务中的退订会走到这里, 这时连接没有订阅任何频道, 只回复确认
+            Command::Unsubscribe(cmd) => unsubscribed(cmd.apply(&mut Subscriptions::new(self.pubsub.clone()))),
+            Command::PUnsubscribe(cmd) => unsubscribed(cmd.apply(&mut Subscriptions::new(self.pubsub.clone()))),
+            // 订阅在 `handle` 里进入订阅模式, SAVE在 `handle` 和EXEC里执行, 不会走到这里
+            cmd => Frame::Error(format!("ERR '{}' is not allowed here", cmd.get_name())),
+        }
+    }
+}
+
+/// 事务中的退订每个频道一个确认, 只有一个时直接作为这条命令的回复
+fn unsubscribed(mut replies: Vec<Frame>) -> Frame {
+    if replies.len() == 1 {
+        replies.pop().unwrap()
+    } else {
+        Frame::Array(replies)
+    }
+}
+
+/// 解析请求, 失败时返回给客户端的错误回复
+fn parse_command(id: u64, frame: Frame) -> Result<Command, Frame> {
+    Command::from_frame(frame).map_err(|err| {
+        debug!(id, %err, "invalid command");
+        // 错误信息里已经带了错误类型
+        Frame::Error(err.to_string())
+    })
+}
+
+fn is_pubsub(cmd: &Command) -> bool {
+    matches!(
+        cmd,
+        Command::Subscribe(_) | Command::PSubscribe(_) | Command::Unsubscribe(_) | Command::PUnsubscribe(_)
+    )
+}
+
+/// 订阅模式下只能执行订阅相关的命令和PING
+fn apply_subscribed(cmd: Command, subscriptions: &mut Subscriptions, protocol: Protocol) -> Vec<Frame> {
+    match cmd {
+        Command::Subscribe(cmd) => cmd.apply(subscriptions),
+        Command::PSubscribe(cmd) => cmd.apply(subscriptions),
+        Command::Unsubscribe(cmd) => cmd.apply(subscriptions),
+        Command::PUnsubscribe(cmd) => cmd.apply(subscriptions),
+        // RESP2下订阅模式的PING回复和推送的消息格式一样
+        Command::Ping(cmd) if protocol == Protocol::Resp2 && !subscriptions.is_empty() => {
+            let msg = match cmd.response() {
+                Frame::Bulk(msg) => msg,
+                _ => Bytes::new(),
+            };
+            vec![Frame::Array(vec![Frame::Bulk(Bytes::from("pong")), Frame::Bulk(msg)])]
+        }
+        Command::Ping(cmd) => vec![cmd.response()],
+        cmd => vec![Frame::Error(format!(
+            "ERR Can't execute '{}': only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING are allowed in this context",
+            cmd.get_name()
+        ))],
+    }
+}
+
+/// 给人看的多行文本, RESP3下是verbatim string, RESP2下降级为bulk string
+fn text(s: String) -> Frame {
+    Frame::Verbatim {
+        format: *b"txt",
+        data: Bytes::from(s),
     }
 }
 
@@ -87,3 +820,353 @@ async fn increment_and_do_stuff(mutex: &Mutex<i32>) {
 }
 
 async fn do_something_async() {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hello_world::minis_redis::connection::FrameReader;
+    use std::fs;
+    use std::path::PathBuf;
+    use tokio::io::AsyncWriteExt;
+    use tokio::sync::oneshot;
+    use tokio::task::JoinHandle;
+
+    /// 测试用的超时, 空闲超时比线上的短得多
+    const TEST_TIMEOUTS: Timeouts = Timeouts {
+        idle: Some(Duration::from_millis(200)),
+        read: Some(Duration::from_secs(5)),
+        write: Some(Duration::from_secs(5)),
+    };
+
+    /// 一个连接的客户端一端
+    struct TestConn {
+        reader: FrameReader<Box<dyn AsyncRead + Send + Unpin>>,
+        writer: Box<dyn AsyncWrite + Send + Unpin>,
+        /// 内存管道上直接跑的 `Handler`: drop时通知它关闭, 以及它的task
+        handler: Option<(broadcast::Sender<()>, JoinHandle<hello_world::minis_redis::Result<()>>)>,
+    }
+
+    impl TestConn {
+        fn new(
+            reader: impl AsyncRead + Send + Unpin + 'static,
+            writer: impl AsyncWrite + Send + Unpin + 'static,
+        ) -> TestConn {
+            TestConn {
+                reader: FrameReader::new(Box::new(reader), Limits::default()),
+                writer: Box::new(writer),
+                handler: None,
+            }
+        }
+    }
+
+    fn connect(db: &Db, pubsub: &PubSub, timeouts: Timeouts) -> TestConn {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (notify_shutdown, _) = broadcast::channel(1);
+        let mut handler = Handler {
+            id: 1,
+            conn: Connection::new(server),
+            timeouts,
+            db: db.clone(),
+            clients: Clients::default(),
+            pubsub: pubsub.clone(),
+            snapshots: Snapshots::new(std::env::temp_dir()),
+            transaction: Transaction::new(db.clone()),
+            shutdown: Shutdown::new(notify_shutdown.subscribe()),
+            _shutdown_complete: mpsc::channel(1).0,
+        };
+        let task = tokio::spawn(async move { handler.run().await });
+        let (reader, writer) = tokio::io::split(client);
+        TestConn {
+            handler: Some((notify_shutdown, task)),
+            ..TestConn::new(reader, writer)
+        }
+    }
+
+    /// 在随机端口上跑 `serve`, 返回地址、触发关闭的sender和 `serve` 的task
+    async fn start(db: Db, snapshots: Snapshots, drain_timeout: Duration) -> (SocketAddr, oneshot::Sender<()>, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let task = tokio::spawn(serve(listener, db, snapshots, shutdown_rx, drain_timeout));
+        (addr, shutdown_tx, task)
+    }
+
+    async fn connect_tcp(addr: SocketAddr) -> TestConn {
+        let (reader, writer) = TcpStream::connect(addr).await.unwrap().into_split();
+        TestConn::new(reader, writer)
+    }
+
+    /// 每个测试一个空的快照目录
+    fn snapshot_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("minis-redis-server-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// 编码成RESP的请求
+    fn request(parts: &[&str]) -> Vec<u8> {
+        let mut out = format!("*{}\r\n", parts.len()).into_bytes();
+        for part in parts {
+            out.extend_from_slice(format!("${}\r\n{}\r\n", part.len(), part).as_bytes());
+        }
+        out
+    }
+
+    fn bulk(s: &str) -> Frame {
+        Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))
+    }
+
+    fn simple(s: &str) -> Frame {
+        Frame::Simple(s.to_string())
+    }
+
+    impl TestConn {
+        /// 一次写出所有的请求, 服务端一次读到整个pipeline
+        async fn send(&mut self, requests: &[&[&str]]) {
+            let data: Vec<u8> = requests.iter().flat_map(|parts| request(parts)).collect();
+            self.send_raw(&data).await;
+        }
+
+        async fn send_raw(&mut self, data: &[u8]) {
+            self.writer.write_all(data).await.unwrap();
+        }
+
+        async fn read(&mut self) -> Option<Frame> {
+            time::timeout(Duration::from_secs(5), self.reader.read_frame())
+                .await
+                .expect("no reply in time")
+                .unwrap()
+        }
+
+        async fn expect(&mut self, expected: Frame) {
+            assert_eq!(self.read().await, Some(expected));
+        }
+
+        /// 服务端关闭了连接, 之前没有多余的回复
+        async fn expect_closed(&mut self) {
+            assert_eq!(self.read().await, None);
+        }
+
+        /// 通知 `Handler` 关闭, 等它退出
+        async fn shutdown(&mut self) -> hello_world::minis_redis::Result<()> {
+            let (notify_shutdown, task) = self.handler.take().expect("not a handler connection");
+            drop(notify_shutdown);
+            time::timeout(Duration::from_secs(5), task)
+                .await
+                .expect("handler did not exit")
+                .unwrap()
+        }
+    }
+
+    fn confirm(kind: &str, name: Option<&str>, count: i64) -> Frame {
+        Frame::Array(vec![bulk(kind), name.map_or(Frame::Null, bulk), Frame::Integer(count)])
+    }
+
+    #[tokio::test]
+    async fn subscribed_mode_rejects_other_commands() {
+        let pubsub = PubSub::new();
+        let mut conn = connect(&Db::new(2), &pubsub, TEST_TIMEOUTS);
+
+        conn.send(&[&["subscribe", "c"], &["get", "a"], &["ping"]]).await;
+        conn.expect(confirm("subscribe", Some("c"), 1)).await;
+        conn.expect(Frame::Error(
+            "ERR Can't execute 'get': only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING are allowed in this context"
+                .to_string(),
+        ))
+        .await;
+        conn.expect(Frame::Array(vec![bulk("pong"), bulk("")])).await;
+
+        // 订阅还在, 消息照常推送
+        assert_eq!(pubsub.publish("c", Bytes::from("m")), 1);
+        conn.expect(Frame::Array(vec![bulk("message"), bulk("c"), bulk("m")])).await;
+        conn.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn last_unsubscribe_returns_to_normal_mode() {
+        let mut conn = connect(&Db::new(2), &PubSub::new(), TEST_TIMEOUTS);
+
+        // 退订之后缓冲里剩下的请求按普通模式处理
+        conn.send(&[
+            &["subscribe", "c", "d"],
+            &["unsubscribe"],
+            &["set", "a", "1"],
+            &["get", "a"],
+        ])
+        .await;
+        conn.expect(confirm("subscribe", Some("c"), 1)).await;
+        conn.expect(confirm("subscribe", Some("d"), 2)).await;
+        let mut unsubscribed = vec![conn.read().await.unwrap(), conn.read().await.unwrap()];
+        unsubscribed.sort_by_key(|frame| format!("{:?}", frame));
+        assert_eq!(unsubscribed, [confirm("unsubscribe", Some("c"), 1), confirm("unsubscribe", Some("d"), 0)]);
+        conn.expect(simple("OK")).await;
+        conn.expect(bulk("1")).await;
+        conn.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn subscribed_connection_is_never_idle() {
+        let pubsub = PubSub::new();
+        let mut conn = connect(&Db::new(2), &pubsub, TEST_TIMEOUTS);
+
+        conn.send(&[&["subscribe", "c"]]).await;
+        conn.expect(confirm("subscribe", Some("c"), 1)).await;
+        // 比空闲超时长得多
+        time::sleep(Duration::from_millis(600)).await;
+        assert_eq!(pubsub.publish("c", Bytes::from("m")), 1);
+        conn.expect(Frame::Array(vec![bulk("message"), bulk("c"), bulk("m")])).await;
+
+        // 回到普通模式之后空闲超时又生效了
+        conn.send(&[&["unsubscribe", "c"]]).await;
+        conn.expect(confirm("unsubscribe", Some("c"), 0)).await;
+        let started = time::Instant::now();
+        conn.expect_closed().await;
+        assert!(started.elapsed() < Duration::from_secs(2));
+        conn.handler.take().unwrap().1.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn command_errors_keep_the_connection() {
+        let mut conn = connect(&Db::new(2), &PubSub::new(), TEST_TIMEOUTS);
+
+        conn.send(&[
+            &["nosuch", "a"],
+            &["get"],
+            &["get", "a", "b"],
+            &["incrby", "n", "one"],
+            &["rpush", "l", "x"],
+            &["get", "l"],
+            &["ping"],
+        ])
+        .await;
+        conn.expect(Frame::Error("ERR unknown command 'nosuch'".to_string())).await;
+        conn.expect(Frame::Error("ERR wrong number of arguments for 'get' command".to_string())).await;
+        conn.expect(Frame::Error("ERR wrong number of arguments for 'get' command".to_string())).await;
+        conn.expect(Frame::Error("ERR value is not an integer or out of range".to_string())).await;
+        conn.expect(Frame::Integer(1)).await;
+        conn.expect(Frame::Error(
+            "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+        ))
+        .await;
+        conn.expect(simple("PONG")).await;
+        conn.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn malformed_frame_closes_the_connection() {
+        let mut conn = connect(&Db::new(2), &PubSub::new(), TEST_TIMEOUTS);
+
+        // 坏帧前面的请求照常回复, 坏帧回复错误后关闭连接, 后面的请求不再处理
+        let mut data = request(&["ping"]);
+        data.extend_from_slice(b"*1\r\n$x\r\n");
+        data.extend(request(&["ping"]));
+        conn.send_raw(&data).await;
+        conn.expect(simple("PONG")).await;
+        match conn.read().await {
+            Some(Frame::Error(msg)) => assert!(msg.starts_with("ERR protocol error"), "{}", msg),
+            reply => panic!("expected an error reply, got {:?}", reply),
+        }
+        conn.expect_closed().await;
+        conn.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn client_half_closes_while_blocked() {
+        let db = Db::new(2);
+        let mut conn = connect(&db, &PubSub::new(), TEST_TIMEOUTS);
+
+        // BLPOP后面还有排队的请求, 客户端发完就关闭了写的一端
+        conn.send(&[&["blpop", "q", "0"], &["ping"], &["rpush", "q", "x"]]).await;
+        conn.writer.shutdown().await.unwrap();
+
+        // BLPOP没有回复, 后面的请求也不能执行, 否则 +PONG 会被当成BLPOP的回复
+        conn.expect_closed().await;
+        conn.shutdown().await.unwrap();
+        assert_eq!(db.lock(["q"]).list("q").unwrap(), None);
+
+        // 放弃等待之后不会再从list里拿走元素
+        let mut other = connect(&db, &PubSub::new(), TEST_TIMEOUTS);
+        other.send(&[&["rpush", "q", "y"], &["lrange", "q", "0", "-1"]]).await;
+        other.expect(Frame::Integer(1)).await;
+        other.expect(Frame::Array(vec![bulk("y")])).await;
+        other.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn shutdown_finishes_pipelined_requests() {
+        let dir = snapshot_dir("finish");
+        let (addr, shutdown, server) = start(Db::new(2), Snapshots::new(&dir), Duration::from_secs(5)).await;
+        let mut conn = connect_tcp(addr).await;
+
+        // 收到PONG时连接已经阻塞在BLPOP上了, 后面的请求还在读缓冲里
+        conn.send(&[&["ping"], &["blpop", "q", "0"], &["set", "a", "1"], &["get", "a"]]).await;
+        conn.expect(simple("PONG")).await;
+        shutdown.send(()).unwrap();
+
+        conn.expect(Frame::Null).await;
+        conn.expect(simple("OK")).await;
+        conn.expect(bulk("1")).await;
+        conn.expect_closed().await;
+        time::timeout(Duration::from_secs(5), server).await.expect("drain did not return").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn drain_refuses_new_connections_and_gives_up_at_deadline() {
+        let db = Db::new(2);
+        db.lock(["big"]).insert("big".to_string(), Bytes::from(vec![b'x'; 1 << 20]));
+        let dir = snapshot_dir("deadline");
+        let drain_timeout = Duration::from_millis(500);
+        let (addr, shutdown, server) = start(db, Snapshots::new(&dir), drain_timeout).await;
+
+        // 客户端一直不读, 服务端写满socket的缓冲之后卡在写回复上, 不会在期限内退出
+        let mut stuck = connect_tcp(addr).await;
+        let gets: Vec<&[&str]> = vec![&["get", "big"]; 64];
+        stuck.send(&gets).await;
+        time::sleep(Duration::from_millis(200)).await;
+        shutdown.send(()).unwrap();
+        let signalled = time::Instant::now();
+
+        // 排水期间监听的socket已经关了, 新的连接被拒绝
+        let refused = time::timeout(Duration::from_secs(2), async {
+            while TcpStream::connect(addr).await.is_ok() {
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+        refused.await.expect("still accepting connections while draining");
+        assert!(!server.is_finished());
+
+        time::timeout(Duration::from_secs(5), server).await.expect("drain did not give up").unwrap();
+        assert!(signalled.elapsed() >= drain_timeout);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn drain_waits_for_background_save() {
+        let db = Db::new(2);
+        for i in 0..50_000 {
+            let key = format!("key:{}", i);
+            db.lock([&key]).insert(key.clone(), Bytes::from("value"));
+        }
+        let dir = snapshot_dir("bgsave");
+        let snapshots = Snapshots::new(&dir);
+        let (addr, shutdown, server) = start(db, snapshots.clone(), Duration::from_secs(10)).await;
+        let mut conn = connect_tcp(addr).await;
+
+        conn.send(&[&["bgsave"]]).await;
+        conn.expect(simple("Background saving started")).await;
+        shutdown.send(()).unwrap();
+        time::timeout(Duration::from_secs(10), server).await.expect("drain did not return").unwrap();
+
+        // `serve` 返回时快照已经写完了, 没有留下临时文件
+        assert!(!snapshots.is_saving());
+        let names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(names.len(), 1);
+        assert!(names[0].starts_with("dump-"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
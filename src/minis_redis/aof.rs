@@ -0,0 +1,436 @@
+//! AOF(append only file)持久化。
+//!
+//! 和rocksdb的WAL一样, 每个修改数据的命令在执行时追加写进日志文件, 重启时按顺序
+//! 重新执行一遍就恢复了内存中的数据。日志里每条记录就是一个RESP命令(由bulk string
+//! 组成的array), 和redis的AOF格式相同:
+//!
+//! * 相对的过期时间(EX、EXPIRE ...)都记成绝对的 `PEXPIREAT`, 重启之后才不会变长
+//! * INCR这类命令记成执行之后的结果(`SET key value KEEPTTL`)
+//!
+//! `always` 时记录先写进文件, 不在持有分片锁的时候fsync: 连接在回复客户端之前调用
+//! `Aof::commit`, 在blocking线程里fsync, 同时在等的连接共用一次fsync(group commit)。
+//!
+//! 进程崩溃时最后一条记录可能只写了一半(torn tail), 文件系统也可能在结尾留下预分配的0。
+//! 回放时最后一条完整的记录之后解析不了的数据都当作写坏的尾巴, 把文件截断到最后一条完整
+//! 的记录; 后面还有完整记录的损坏是在文件中间, 不会自动修复, 直接报错。
+//!
+//! EXEC执行的多条记录用MULTI和EXEC包起来, 和redis一样。回放时读到EXEC才执行这个事务,
+//! 文件结尾没有EXEC的事务是写了一半的, 和不完整的记录一样被截掉。
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+use tracing::{error, warn};
+
+use super::cmd::{Command, Exec, Multi};
+use super::db::Db;
+use super::frame::{self, Frame, Limits};
+
+/// 什么时候把AOF fsync到磁盘
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// 每条记录写完都fsync, 最安全也最慢
+    Always,
+    /// 每秒fsync一次, 掉电最多丢一秒的数据
+    EverySec,
+    /// 只写进操作系统, 什么时候落盘由操作系统决定
+    No,
+}
+
+/// 追加写的AOF文件, clone之后共享同一个文件
+#[derive(Clone, Debug)]
+pub struct Aof {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    path: PathBuf,
+    policy: FsyncPolicy,
+    file: Mutex<File>,
+    /// 同一个文件的另一个句柄, fsync时不拿 `file` 的锁, 不耽误其他连接写
+    sync_file: File,
+    /// 一共写了多少次
+    written: AtomicU64,
+    /// 前多少次写已经fsync到了磁盘
+    synced: AtomicU64,
+    /// 同一时间只有一个fsync, 排队等锁的fsync可能已经被前一个包含了
+    sync_lock: Mutex<()>,
+}
+
+/// 回放的结果
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Replay {
+    /// 重新执行了多少条命令
+    pub commands: usize,
+    /// 文件末尾被截掉的不完整记录的字节数
+    pub truncated: u64,
+}
+
+impl FromStr for FsyncPolicy {
+    type Err = crate::minis_redis::Error;
+
+    /// 和redis的 `appendfsync` 配置一样: always、everysec、no
+    fn from_str(s: &str) -> crate::minis_redis::Result<FsyncPolicy> {
+        match &s.to_lowercase()[..] {
+            "always" => Ok(FsyncPolicy::Always),
+            "everysec" => Ok(FsyncPolicy::EverySec),
+            "no" => Ok(FsyncPolicy::No),
+            _ => Err(format!("invalid fsync policy '{}'", s).into()),
+        }
+    }
+}
+
+impl Aof {
+    /// 打开(不存在就创建)AOF文件, 新的记录追加到文件末尾
+    pub fn open(path: impl AsRef<Path>, policy: FsyncPolicy) -> io::Result<Aof> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let sync_file = file.try_clone()?;
+
+        Ok(Aof {
+            inner: Arc::new(Inner {
+                path,
+                policy,
+                file: Mutex::new(file),
+                sync_file,
+                written: AtomicU64::new(0),
+                synced: AtomicU64::new(0),
+                sync_lock: Mutex::new(()),
+            }),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.inner.path
+    }
+
+    pub fn policy(&self) -> FsyncPolicy {
+        self.inner.policy
+    }
+
+    /// 追加一条记录, 只写进操作系统; `always` 时由 `commit` 等fsync完成。
+    ///
+    /// 写失败不会让命令失败(数据已经改了), 只记录错误
+    pub fn append(&self, record: &Frame) {
+        let mut buf = Vec::new();
+        encode(record, &mut buf);
+        self.write(&buf);
+    }
+
+    /// 追加一个事务的所有记录。多于一条时用MULTI/EXEC包起来一次写入,
+    /// 回放时要么全部执行, 要么都不执行
+    pub fn append_transaction(&self, records: &[Frame]) {
+        match records {
+            [] => {}
+            [record] => self.append(record),
+            records => {
+                let mut buf = Vec::new();
+                encode(&Multi::new().into_frame(), &mut buf);
+                for record in records {
+                    encode(record, &mut buf);
+                }
+                encode(&Exec::new().into_frame(), &mut buf);
+                self.write(&buf);
+            }
+        }
+    }
+
+    fn write(&self, buf: &[u8]) {
+        let mut file = self.inner.file.lock().unwrap();
+        match file.write_all(buf) {
+            Ok(()) => {
+                self.inner.written.fetch_add(1, Ordering::Release);
+            }
+            Err(err) => error!(%err, path = %self.inner.path.display(), "failed to write AOF"),
+        }
+    }
+
+    /// 已经写的记录是不是都fsync过了
+    fn is_synced(&self) -> bool {
+        self.inner.synced.load(Ordering::Acquire) >= self.inner.written.load(Ordering::Acquire)
+    }
+
+    /// 把已经写的记录fsync到磁盘, 会阻塞当前线程
+    pub fn sync(&self) -> io::Result<()> {
+        let _lock = self.inner.sync_lock.lock().unwrap();
+        // 等锁的时候, 前一个fsync可能已经包含了这些记录
+        let written = self.inner.written.load(Ordering::Acquire);
+        if self.inner.synced.load(Ordering::Acquire) >= written {
+            return Ok(());
+        }
+        self.inner.sync_file.sync_data()?;
+        self.inner.synced.store(written, Ordering::Release);
+        Ok(())
+    }
+
+    /// `always` 时等到目前写进去的记录都fsync到磁盘, 回复客户端之前调用, 其他策略直接返回。
+    ///
+    /// fsync在blocking线程里做, 不持有分片的锁, 也不占用runtime的工作线程。同时在等的连接
+    /// 共用一次fsync。必须在tokio runtime中调用
+    pub async fn commit(&self) {
+        if self.inner.policy != FsyncPolicy::Always || self.is_synced() {
+            return;
+        }
+        let aof = self.clone();
+        match tokio::task::spawn_blocking(move || aof.sync()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => error!(%err, path = %self.inner.path.display(), "failed to fsync AOF"),
+            Err(err) => error!(%err, "AOF fsync task failed"),
+        }
+    }
+
+    /// `everysec` 时启动每秒fsync一次的后台task, 必须在tokio runtime中调用
+    pub fn spawn_fsync_task(&self) {
+        if self.inner.policy != FsyncPolicy::EverySec {
+            return;
+        }
+
+        let aof = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                // fsync可能很慢, 不要阻塞runtime的工作线程
+                let synced = aof.clone();
+                match tokio::task::spawn_blocking(move || synced.sync()).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(err)) => error!(%err, "failed to fsync AOF"),
+                    Err(err) => error!(%err, "AOF fsync task failed"),
+                }
+            }
+        });
+    }
+}
+
+/// 把 `path` 中的命令重新执行到 `db` 上, 文件不存在时什么都不做。
+///
+/// `db` 不应该开启AOF, 否则回放的命令又会被写一遍
+pub fn replay(path: impl AsRef<Path>, db: &Db) -> crate::minis_redis::Result<Replay> {
+    let path = path.as_ref();
+    let data = match fs::read(path) {
+        Ok(data) => Bytes::from(data),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Replay::default()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let limits = Limits::default();
+    let mut replay = Replay::default();
+    // 读到了MULTI还没读到EXEC: MULTI的位置, 以及事务中的命令和它们的位置
+    let mut transaction: Option<(usize, Vec<(usize, Command)>)> = None;
+    let mut pos = 0;
+    while pos < data.len() {
+        // 每条记录都是一个array, 不能当成inline命令解析
+        let mut cursor = Cursor::new(&data[pos..]);
+        let res = match data[pos] {
+            b'*' => Frame::check(&mut cursor, &limits),
+            byte => Err(format!("unexpected byte {:#04x}", byte).into()),
+        };
+        match res {
+            Ok(()) => {}
+            // 最后一条记录没写完进程就挂了
+            Err(frame::Error::Incomplete) => break,
+            // 后面已经没有完整的记录了, 是结尾写坏的部分(比如预分配的0), 和不完整的记录一样截掉
+            Err(_) if !contains_record(&data[pos + 1..], &limits) => break,
+            Err(err) => return Err(format!("corrupted AOF {} at offset {}: {}", path.display(), pos, err).into()),
+        }
+
+        let len = cursor.position() as usize;
+        let record = Frame::parse_shared(&data.slice(pos..pos + len))?;
+        let commands = match (Command::from_frame(record)?, &mut transaction) {
+            (Command::Multi(_), None) => {
+                transaction = Some((pos, vec![]));
+                vec![]
+            }
+            (Command::Exec(_), Some(_)) => transaction.take().unwrap().1,
+            (cmd @ Command::Multi(_), _) | (cmd @ Command::Exec(_), _) => {
+                return Err(format!("unexpected '{}' at offset {} of {}", cmd.get_name(), pos, path.display()).into());
+            }
+            (cmd, Some((_, queued))) => {
+                queued.push((pos, cmd));
+                vec![]
+            }
+            (cmd, None) => vec![(pos, cmd)],
+        };
+        for (offset, cmd) in commands {
+            apply(cmd, db).map_err(|err| format!("bad AOF record at offset {} of {}: {}", offset, path.display(), err))?;
+            replay.commands += 1;
+        }
+        pos += len;
+    }
+
+    // 截掉不完整的记录, 以及没有EXEC的事务
+    let end = transaction.map_or(pos, |(start, _)| start);
+    if end < data.len() {
+        replay.truncated = (data.len() - end) as u64;
+        warn!(path = %path.display(), offset = end, bytes = replay.truncated, "truncating torn AOF tail");
+        OpenOptions::new().write(true).open(path)?.set_len(end as u64)?;
+    }
+
+    Ok(replay)
+}
+
+/// `data` 中是否还有一条完整的记录, 用来区分文件中间的损坏和结尾写坏的部分
+fn contains_record(data: &[u8], limits: &Limits) -> bool {
+    (0..data.len())
+        .filter(|&pos| data[pos] == b'*')
+        .any(|pos| Frame::check(&mut Cursor::new(&data[pos..]), limits).is_ok())
+}
+
+/// 执行一条AOF中的命令, 只有会写进AOF的命令才是合法的
+fn apply(cmd: Command, db: &Db) -> crate::minis_redis::Result<()> {
+    let reply = match cmd {
+        Command::Set(cmd) => cmd.apply(db),
+        Command::MSet(cmd) => cmd.apply(db),
+        Command::Del(cmd) => cmd.apply(db),
+        Command::Append(cmd) => cmd.apply(db),
+        Command::PExpireAt(cmd) => cmd.apply(db),
+        Command::Persist(cmd) => cmd.apply(db),
+        Command::LPush(cmd) => cmd.apply(db),
+        Command::RPush(cmd) => cmd.apply(db),
+        Command::LPop(cmd) => cmd.apply(db),
+        Command::RPop(cmd) => cmd.apply(db),
+        Command::LMove(cmd) => cmd.apply(db),
+        Command::HSet(cmd) => cmd.apply(db),
+        Command::HDel(cmd) => cmd.apply(db),
+        Command::SAdd(cmd) => cmd.apply(db),
+        Command::SRem(cmd) => cmd.apply(db),
+        Command::ZAdd(cmd) => cmd.apply(db),
+        cmd => return Err(format!("unexpected command '{}'", cmd.get_name()).into()),
+    };
+    match reply {
+        Frame::Error(err) => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// 把由bulk string组成的array编码成RESP
+fn encode(record: &Frame, buf: &mut Vec<u8>) {
+    match record {
+        Frame::Array(parts) => {
+            buf.extend_from_slice(format!("*{}\r\n", parts.len()).as_bytes());
+            for part in parts {
+                encode(part, buf);
+            }
+        }
+        Frame::Bulk(data) => {
+            buf.extend_from_slice(format!("${}\r\n", data.len()).as_bytes());
+            buf.extend_from_slice(data);
+            buf.extend_from_slice(b"\r\n");
+        }
+        frame => unreachable!("AOF records only contain arrays and bulk strings, got {:?}", frame),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use bytes::Bytes;
+
+    use super::{replay, FsyncPolicy};
+    use crate::minis_redis::cmd::{Del, Set};
+    use crate::minis_redis::db::Db;
+    use crate::minis_redis::test_util::TempAof;
+
+    #[test]
+    fn replay_and_truncate_torn_tail() {
+        let temp = TempAof::new("torn");
+        let path = temp.path();
+        let aof = temp.open(FsyncPolicy::Always).unwrap();
+        aof.append(&Set::new("a", Bytes::from("1")).into_frame());
+        aof.append(&Set::new("b", Bytes::from("2")).into_frame());
+        aof.append(&Del::new(&["a".to_string()]).into_frame());
+        drop(aof);
+
+        // 模拟写到一半崩溃
+        let complete = fs::metadata(path).unwrap().len();
+        let mut data = fs::read(path).unwrap();
+        let torn = b"*3\r\n$3\r\nset\r\n$1\r\nc";
+        data.extend_from_slice(torn);
+        fs::write(path, &data).unwrap();
+
+        let db = Db::new(2);
+        let res = replay(path, &db).unwrap();
+        assert_eq!(res.commands, 3);
+        assert_eq!(res.truncated, torn.len() as u64);
+        assert_eq!(fs::metadata(path).unwrap().len(), complete);
+        assert!(!db.lock(["a"]).contains_key("a"));
+        assert_eq!(db.lock(["b"]).get("b"), Ok(Some(&Bytes::from("2"))));
+        assert!(!db.lock(["c"]).contains_key("c"));
+
+        // 截断之后可以接着追加
+        let aof = temp.open(FsyncPolicy::No).unwrap();
+        aof.append(&Set::new("c", Bytes::from("3")).into_frame());
+        let db = Db::new(2);
+        assert_eq!(replay(path, &db).unwrap().commands, 4);
+        assert_eq!(db.lock(["c"]).get("c"), Ok(Some(&Bytes::from("3"))));
+    }
+
+    #[test]
+    fn truncate_zero_filled_tail() {
+        let temp = TempAof::new("zeros");
+        let path = temp.path();
+        temp.open(FsyncPolicy::No)
+            .unwrap()
+            .append(&Set::new("a", Bytes::from("1")).into_frame());
+        let complete = fs::read(path).unwrap();
+
+        // 崩溃时文件系统在结尾留下了预分配的0
+        let mut data = complete.clone();
+        data.extend_from_slice(b"\0\0\0\0");
+        fs::write(path, &data).unwrap();
+
+        let db = Db::new(2);
+        let res = replay(path, &db).unwrap();
+        assert_eq!(res.commands, 1);
+        assert_eq!(res.truncated, 4);
+        assert_eq!(fs::read(path).unwrap(), complete);
+        assert_eq!(db.lock(["a"]).get("a"), Ok(Some(&Bytes::from("1"))));
+    }
+
+    #[test]
+    fn corrupted_aof() {
+        let temp = TempAof::new("corrupted");
+        let path = temp.path();
+        // 损坏的数据后面还有完整的记录, 不是写坏的尾巴
+        fs::write(path, b"*2\r\n$3\r\ndel\r\n$1\r\na\r\ngarbage\r\n*2\r\n$3\r\ndel\r\n$1\r\nb\r\n").unwrap();
+        assert!(replay(path, &Db::new(2)).is_err());
+        fs::write(path, b"*2\r\n$3\r\ndel\r\n$1\r\na\r\n*2\r\n:1\r\n*1\r\n$4\r\nping\r\n").unwrap();
+        assert!(replay(path, &Db::new(2)).is_err());
+
+        // 不该出现在AOF里的命令
+        fs::write(path, b"*2\r\n$3\r\nget\r\n$1\r\na\r\n").unwrap();
+        assert!(replay(path, &Db::new(2)).is_err());
+
+        fs::remove_file(path).unwrap();
+        assert_eq!(replay(path, &Db::new(2)).unwrap().commands, 0);
+    }
+
+    #[tokio::test]
+    async fn commit_waits_for_fsync() {
+        let temp = TempAof::new("commit");
+        let aof = temp.open(FsyncPolicy::Always).unwrap();
+        assert!(aof.is_synced());
+
+        aof.append(&Set::new("a", Bytes::from("1")).into_frame());
+        aof.append(&Set::new("b", Bytes::from("2")).into_frame());
+        assert!(!aof.is_synced());
+        // 两条记录共用一次fsync
+        aof.commit().await;
+        assert!(aof.is_synced());
+
+        // 其他策略不在回复之前fsync
+        let temp = TempAof::new("commit-no");
+        let aof = temp.open(FsyncPolicy::No).unwrap();
+        aof.append(&Set::new("a", Bytes::from("1")).into_frame());
+        aof.commit().await;
+        assert!(!aof.is_synced());
+    }
+}
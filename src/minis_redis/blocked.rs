@@ -0,0 +1,297 @@
+//! 阻塞在list上的客户端(BLPOP、BRPOP、BLMOVE)。
+//!
+//! 命令执行时所有的key都是空的, 就在持有锁的时候把一个 `Waiter` 排进每个key的等待队列,
+//! 不会漏掉加锁和排队之间的push。之后连接等待 `Blocked::reply`, 直到拿到数据或者超时。
+//!
+//! push之类的命令放入数据后调用 `Guard::signal_ready`, `Guard` 释放锁时调用 `serve`:
+//! 按排队的顺序, 把list中的元素一个一个交给等待的客户端, 每次都在锁下弹出元素、写AOF,
+//! 再通过oneshot channel把回复发给客户端。key分布在不同的分片上也没有关系, 同一个
+//! `Waiter` 排在多个队列里, 谁先服务它都可以, 另外几个队列里的就作废了。
+//!
+//! `Waiter` 的sender放在一个mutex里, 服务时一直持有它直到回复发出; 客户端超时或者放弃
+//! 等待时也要先拿到这个mutex取走sender。所以一个元素要么交到了客户端手里, 要么还留在list中。
+
+use std::future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::sync::oneshot;
+use tokio::time::{self, Instant};
+
+use super::cmd::{move_element, pop_one, End};
+use super::db::{Db, Guard};
+use super::frame::Frame;
+
+/// 阻塞命令的执行结果
+#[derive(Debug)]
+pub enum Outcome {
+    /// 马上就有了回复
+    Ready(Frame),
+    /// 所有的key都是空的, 需要等待
+    Blocked(Blocked),
+}
+
+/// 一个阻塞的客户端, drop时退出所有的等待队列
+#[derive(Debug)]
+pub struct Blocked {
+    db: Db,
+    keys: Vec<String>,
+    waiter: Arc<Waiter>,
+    rx: oneshot::Receiver<Frame>,
+    /// `None` 表示一直等
+    deadline: Option<Instant>,
+}
+
+/// 等待队列中的一项
+#[derive(Debug)]
+pub(crate) struct Waiter {
+    /// 从list的哪一端弹出
+    from: End,
+    /// BLMOVE: 弹出的元素放进哪个key的哪一端
+    to: Option<(String, End)>,
+    /// 服务过或者放弃等待之后是None
+    tx: Mutex<Option<oneshot::Sender<Frame>>>,
+}
+
+impl Blocked {
+    /// 在持有 `keys` 的锁时调用, 把客户端排进每个key的等待队列
+    pub(crate) fn new(
+        guard: &mut Guard<'_>,
+        keys: Vec<String>,
+        from: End,
+        to: Option<(String, End)>,
+        timeout: Option<Duration>,
+    ) -> Blocked {
+        let (tx, rx) = oneshot::channel();
+        let waiter = Arc::new(Waiter {
+            from,
+            to,
+            tx: Mutex::new(Some(tx)),
+        });
+        for key in &keys {
+            guard.block(key, waiter.clone());
+        }
+
+        Blocked {
+            db: guard.db().clone(),
+            keys,
+            waiter,
+            rx,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+        }
+    }
+
+    /// 等到拿到数据, 超时的话回复nil。
+    ///
+    /// 可以在 `select!` 中反复调用, 超时的时间点不变
+    pub async fn reply(&mut self) -> Frame {
+        let deadline = self.deadline;
+        let timeout = async move {
+            match deadline {
+                Some(deadline) => time::sleep_until(deadline).await,
+                None => future::pending().await,
+            }
+        };
+
+        let served = tokio::select! {
+            reply = &mut self.rx => Some(reply),
+            _ = timeout => None,
+        };
+        match served {
+            // sender只有发出回复之后才会drop
+            Some(reply) => reply.unwrap_or(Frame::Null),
+            None => self.cancel().unwrap_or(Frame::Null),
+        }
+    }
+
+    /// 放弃等待。如果在这之前已经被服务了, 元素已经从list中弹出, 返回它的回复
+    pub fn cancel(&mut self) -> Option<Frame> {
+        if self.waiter.tx.lock().unwrap().take().is_some() {
+            return None;
+        }
+        // 服务方在释放mutex之前已经把回复发出来了
+        self.rx.try_recv().ok()
+    }
+}
+
+impl Drop for Blocked {
+    fn drop(&mut self) {
+        self.waiter.tx.lock().unwrap().take();
+        let mut guard = self.db.lock(&self.keys);
+        for key in &self.keys {
+            guard.unblock(key, &self.waiter);
+        }
+    }
+}
+
+impl Waiter {
+    /// 在持有锁的 `guard` 下从 `key` 取出一个元素, 返回给客户端的回复
+    fn take(&self, guard: &mut Guard<'_>, key: &str) -> Frame {
+        match &self.to {
+            None => match pop_one(guard, key, self.from == End::Left) {
+                Ok(Some(value)) => Frame::Array(vec![Frame::Bulk(Bytes::from(key.to_string())), Frame::Bulk(value)]),
+                Ok(None) => Frame::Null,
+                Err(err) => err.into(),
+            },
+            Some((destination, to)) => match move_element(guard, key, destination, self.from, *to) {
+                Ok(Some(value)) => Frame::Bulk(value),
+                Ok(None) => Frame::Null,
+                Err(err) => err.into(),
+            },
+        }
+    }
+}
+
+/// 把 `key` 的list中的元素按排队顺序交给阻塞在它上面的客户端, 直到list空了或者没有人在等
+pub(crate) fn serve(db: &Db, key: &str) {
+    loop {
+        // BLMOVE还要锁住目标key所在的分片, 先看一眼排在最前面的是谁
+        let waiter = match db.lock([key]).first_waiter(key) {
+            Some(waiter) => waiter,
+            None => return,
+        };
+        let mut keys = vec![key];
+        if let Some((destination, _)) = &waiter.to {
+            keys.push(destination);
+        }
+
+        let mut guard = db.lock(&keys);
+        // 重新加锁的间隙里队列可能变了
+        if !guard
+            .first_waiter(key)
+            .is_some_and(|first| Arc::ptr_eq(&first, &waiter))
+        {
+            continue;
+        }
+        // 已经被别的命令弹空了, 或者被SET成了别的类型
+        if !matches!(guard.list(key), Ok(Some(_))) {
+            return;
+        }
+        guard.unblock(key, &waiter);
+
+        let mut sender = waiter.tx.lock().unwrap();
+        // 已经超时, 或者已经在它等待的另一个key上被服务了
+        let Some(tx) = sender.take() else {
+            continue;
+        };
+        let reply = waiter.take(&mut guard, key);
+        // 客户端只有先取走sender才会放弃等待, 发送不会失败
+        let _ = tx.send(reply);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::{Blocked, Outcome};
+    use crate::minis_redis::aof::{self, FsyncPolicy};
+    use crate::minis_redis::cmd::{BLMove, BLPop, BRPop, End, LRange, RPush};
+    use crate::minis_redis::db::Db;
+    use crate::minis_redis::frame::Frame;
+    use crate::minis_redis::test_util::TempAof;
+
+    fn blocked(outcome: Outcome) -> Blocked {
+        match outcome {
+            Outcome::Blocked(blocked) => blocked,
+            Outcome::Ready(frame) => panic!("not blocked, replied {:?}", frame),
+        }
+    }
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    fn push(db: &Db, key: &str, values: &[&'static str]) {
+        let values: Vec<Bytes> = values.iter().map(|value| Bytes::from(*value)).collect();
+        RPush::new(key, &values).apply(db);
+    }
+
+    fn list(db: &Db, key: &str) -> Frame {
+        LRange::new(key, 0, -1).apply(db)
+    }
+
+    fn popped(key: &'static str, value: &'static str) -> Frame {
+        Frame::Array(vec![Frame::Bulk(Bytes::from(key)), Frame::Bulk(Bytes::from(value))])
+    }
+
+    #[tokio::test]
+    async fn served_in_order() {
+        let db = Db::new(4);
+        let mut first = blocked(BLPop::new(&keys(&["q"]), None).apply(&db));
+        let mut second = blocked(BRPop::new(&keys(&["q"]), None).apply(&db));
+
+        push(&db, "q", &["a", "b", "c"]);
+        assert_eq!(first.reply().await, popped("q", "a"));
+        assert_eq!(second.reply().await, popped("q", "c"));
+        assert_eq!(list(&db, "q"), Frame::Array(vec![Frame::Bulk(Bytes::from("b"))]));
+
+        // 有数据时不阻塞
+        match BLPop::new(&keys(&["missing", "q"]), None).apply(&db) {
+            Outcome::Ready(frame) => assert_eq!(frame, popped("q", "b")),
+            Outcome::Blocked(_) => panic!("should not block"),
+        }
+    }
+
+    #[tokio::test]
+    async fn keys_in_different_shards() {
+        let db = Db::new(4);
+        let names = ["a", "b", "c", "d", "e", "f"];
+        assert!(names.iter().any(|key| db.shard_index(key) != db.shard_index("a")));
+        let mut waiter = blocked(BLPop::new(&keys(&names), None).apply(&db));
+
+        push(&db, "e", &["1"]);
+        assert_eq!(waiter.reply().await, popped("e", "1"));
+        drop(waiter);
+
+        // 已经被服务过的客户端不会再拿走其他key上的数据
+        push(&db, "a", &["2"]);
+        assert_eq!(list(&db, "a"), Frame::Array(vec![Frame::Bulk(Bytes::from("2"))]));
+    }
+
+    #[tokio::test]
+    async fn timeout_and_cancel() {
+        let db = Db::new(4);
+        let mut waiter = blocked(BLPop::new(&keys(&["q"]), Some(Duration::from_millis(20))).apply(&db));
+        assert_eq!(waiter.reply().await, Frame::Null);
+
+        // 放弃等待之后push的数据留在list里
+        let mut cancelled = blocked(BLPop::new(&keys(&["q"]), None).apply(&db));
+        assert_eq!(cancelled.cancel(), None);
+        push(&db, "q", &["a"]);
+        assert_eq!(list(&db, "q"), Frame::Array(vec![Frame::Bulk(Bytes::from("a"))]));
+
+        // 被服务之后才放弃, 拿到的数据不会丢
+        drop(waiter);
+        let mut waiter = blocked(BLPop::new(&keys(&["p"]), None).apply(&db));
+        push(&db, "p", &["b"]);
+        assert_eq!(waiter.cancel(), Some(popped("p", "b")));
+    }
+
+    #[tokio::test]
+    async fn blmove_chain_is_logged() {
+        let temp = TempAof::new("blocked");
+        let db = Db::new(4).with_aof(temp.open(FsyncPolicy::Always).unwrap());
+
+        // src -> dst -> 另一个在dst上等待的客户端
+        let mut mover = blocked(BLMove::new("src", "dst", End::Left, End::Right, None).apply(&db));
+        let mut popper = blocked(BLPop::new(&keys(&["dst"]), None).apply(&db));
+        push(&db, "src", &["a", "b"]);
+        assert_eq!(mover.reply().await, Frame::Bulk(Bytes::from("a")));
+        assert_eq!(popper.reply().await, popped("dst", "a"));
+
+        let mut mover = blocked(BLMove::new("src2", "dst", End::Right, End::Left, None).apply(&db));
+        push(&db, "src2", &["c", "d"]);
+        assert_eq!(mover.reply().await, Frame::Bulk(Bytes::from("d")));
+
+        let restored = Db::new(4);
+        aof::replay(temp.path(), &restored).unwrap();
+        for key in ["src", "src2", "dst"] {
+            assert_eq!(list(&restored, key), list(&db, key));
+        }
+        assert_eq!(list(&db, "dst"), Frame::Array(vec![Frame::Bulk(Bytes::from("d"))]));
+    }
+}
@@ -0,0 +1,332 @@
+//! 阻塞版本的 `Connection`, 给跑在普通线程上、不想引入tokio运行时的代码使用。
+//!
+//! 分帧和解析与异步版本共用同一套代码(`Frame::check` / `Frame::parse_shared`),
+//! 编码规则(包括RESP3到RESP2的降级)也保持一致。
+
+use std::io::{self, BufWriter, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+use bytes::BytesMut;
+
+use super::connection::{depth, parse_buffered};
+use super::frame::{format_double, Error, Frame, Limits, Protocol, TimeoutKind};
+
+/// 每次从socket读取时至少预留的空间
+const READ_CHUNK: usize = 4 * 1024;
+
+/// 在任意实现了 `Read + Write` 的阻塞字节流上收发frame, 默认是 `std::net::TcpStream`
+pub struct Connection<S: Write = TcpStream> {
+    /// 和异步版本一样, 写操作先进入缓冲, `flush` 时才真正写到socket
+    stream: BufWriter<S>,
+    buffer: BytesMut,
+    protocol: Protocol,
+    limits: Limits,
+}
+
+impl Connection<TcpStream> {
+    /// 连接到 `addr` 并在这个tcp连接上创建Connection
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Connection> {
+        let stream = TcpStream::connect(addr)?;
+        Ok(Connection::new(stream))
+    }
+}
+
+impl<S: Read + Write> Connection<S> {
+    pub fn new(stream: S) -> Connection<S> {
+        Connection::with_limits(stream, Limits::default())
+    }
+
+    /// 使用自定义的上限创建Connection
+    pub fn with_limits(stream: S, limits: Limits) -> Connection<S> {
+        Connection {
+            stream: BufWriter::new(stream),
+            buffer: BytesMut::with_capacity(1024),
+            protocol: Protocol::default(),
+            limits,
+        }
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// 当前连接使用的协议版本
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// 切换协议版本, 之后写出的frame都按新协议编码
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+
+    /// 底层的字节流, 例如用来调用 `TcpStream::set_read_timeout`
+    pub fn get_ref(&self) -> &S {
+        self.stream.get_ref()
+    }
+
+    /// 读取一个完整的frame, 对端正常关闭连接时返回 `None`。
+    ///
+    /// 错误和异步版本的 `read_frame` 相同; 底层socket设置了读超时并且超时了,
+    /// 返回 `Error::Timeout(TimeoutKind::Read)`
+    pub fn read_frame(&mut self) -> Result<Option<Frame>, Error> {
+        loop {
+            if let Some(frame) = self.try_read_frame()? {
+                return Ok(Some(frame));
+            }
+
+            if self.buffer.len() >= self.limits.max_buffer {
+                return Err(Error::FrameTooLarge(format!(
+                    "buffered {} bytes exceeds limit {}",
+                    self.buffer.len(),
+                    self.limits.max_buffer
+                )));
+            }
+
+            let n = match self.read_chunk() {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) if e.kind() == io::ErrorKind::ConnectionReset => {
+                    return Err(Error::ConnectionReset)
+                }
+                // 不同平台上读超时返回的错误类型不一样
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                    return Err(Error::Timeout(TimeoutKind::Read))
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if n == 0 {
+                if !self.buffer.is_empty() {
+                    return Err(Error::ConnectionReset);
+                } else {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    /// 只从已经读到缓冲中的数据里解析frame, 不会读socket
+    pub fn try_read_frame(&mut self) -> Result<Option<Frame>, Error> {
+        parse_buffered(&mut self.buffer, &self.limits)
+    }
+
+    /// 写入一个frame并flush到socket
+    pub fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.feed_frame(frame)?;
+        self.stream.flush()
+    }
+
+    /// 把多个frame编码进写缓冲, 最后只flush一次; 有不合法的frame就一个都不写
+    pub fn write_frames(&mut self, frames: &[Frame]) -> io::Result<()> {
+        for frame in frames {
+            self.check_depth(frame)?;
+        }
+        for frame in frames {
+            self.encode(frame)?;
+        }
+        self.stream.flush()
+    }
+
+    /// 只把frame编码进写缓冲, 不flush
+    pub fn feed_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        // 同步代码可以直接递归编码, 深度先检查过, 不会栈溢出
+        self.check_depth(frame)?;
+        self.encode(frame)
+    }
+
+    /// 把写缓冲中的数据全部写到socket
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+
+    /// 在读缓冲的末尾读入一块数据, 返回读到的字节数
+    fn read_chunk(&mut self) -> io::Result<usize> {
+        let len = self.buffer.len();
+        self.buffer.resize(len + READ_CHUNK, 0);
+        let res = self.stream.get_mut().read(&mut self.buffer[len..]);
+        self.buffer.truncate(len + *res.as_ref().unwrap_or(&0));
+        res
+    }
+
+    fn check_depth(&self, frame: &Frame) -> io::Result<()> {
+        if depth(frame) > self.limits.max_depth {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("frame nesting exceeds max depth {}", self.limits.max_depth),
+            ));
+        }
+        Ok(())
+    }
+
+    fn encode(&mut self, frame: &Frame) -> io::Result<()> {
+        let resp3 = self.protocol == Protocol::Resp3;
+        match frame {
+            Frame::Array(val) => {
+                self.write_header(b'*', val.len())?;
+                self.encode_all(val)?;
+            }
+            // RESP2 没有set和push类型, 降级为数组
+            Frame::Set(val) => {
+                self.write_header(if resp3 { b'~' } else { b'*' }, val.len())?;
+                self.encode_all(val)?;
+            }
+            Frame::Push(val) => {
+                self.write_header(if resp3 { b'>' } else { b'*' }, val.len())?;
+                self.encode_all(val)?;
+            }
+            // RESP2 没有map类型, 降级为 key value 交替出现的数组
+            Frame::Map(val) => {
+                if resp3 {
+                    self.write_header(b'%', val.len())?;
+                } else {
+                    self.write_header(b'*', val.len() * 2)?;
+                }
+                self.encode_pairs(val)?;
+            }
+            // 属性写在被修饰的frame之前; RESP2 客户端不认识属性, 直接丢弃
+            Frame::Attribute { attributes, data } => {
+                if resp3 {
+                    self.write_header(b'|', attributes.len())?;
+                    self.encode_pairs(attributes)?;
+                }
+                self.encode(data)?;
+            }
+            Frame::Simple(val) => write!(self.stream, "+{}\r\n", val)?,
+            Frame::Error(val) => write!(self.stream, "-{}\r\n", val)?,
+            Frame::Integer(val) => write!(self.stream, ":{}\r\n", val)?,
+            Frame::Null if resp3 => self.stream.write_all(b"_\r\n")?,
+            Frame::Null => self.stream.write_all(b"$-1\r\n")?,
+            Frame::Bulk(val) => self.write_bulk(val)?,
+            Frame::Double(val) if resp3 => write!(self.stream, ",{}\r\n", format_double(*val))?,
+            Frame::Double(val) => self.write_bulk(format_double(*val).as_bytes())?,
+            Frame::Boolean(val) if resp3 => {
+                self.stream.write_all(if *val { b"#t\r\n" } else { b"#f\r\n" })?
+            }
+            Frame::Boolean(val) => write!(self.stream, ":{}\r\n", *val as i64)?,
+            Frame::BigNumber(val) if resp3 => write!(self.stream, "({}\r\n", val)?,
+            Frame::BigNumber(val) => self.write_bulk(val.as_bytes())?,
+            Frame::Verbatim { format, data } if resp3 => {
+                write!(self.stream, "={}\r\n", data.len() + 4)?;
+                self.stream.write_all(format)?;
+                self.stream.write_all(b":")?;
+                self.stream.write_all(data)?;
+                self.stream.write_all(b"\r\n")?;
+            }
+            Frame::Verbatim { data, .. } => self.write_bulk(data)?,
+        }
+        Ok(())
+    }
+
+    fn encode_all(&mut self, frames: &[Frame]) -> io::Result<()> {
+        for frame in frames {
+            self.encode(frame)?;
+        }
+        Ok(())
+    }
+
+    fn encode_pairs(&mut self, pairs: &[(Frame, Frame)]) -> io::Result<()> {
+        for (key, value) in pairs {
+            self.encode(key)?;
+            self.encode(value)?;
+        }
+        Ok(())
+    }
+
+    /// Write a `$<len>\r\n<data>\r\n` bulk string
+    fn write_bulk(&mut self, val: &[u8]) -> io::Result<()> {
+        write!(self.stream, "${}\r\n", val.len())?;
+        self.stream.write_all(val)?;
+        self.stream.write_all(b"\r\n")
+    }
+
+    /// Write the `<prefix><len>\r\n` header of an aggregate frame
+    fn write_header(&mut self, prefix: u8, len: usize) -> io::Result<()> {
+        self.stream.write_all(&[prefix])?;
+        write!(self.stream, "{}\r\n", len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::net::TcpListener;
+    use std::thread;
+
+    use bytes::Bytes;
+    use tokio::io::{self, AsyncReadExt};
+
+    use super::Connection;
+    use crate::minis_redis::frame::{Frame, Protocol};
+
+    fn frames() -> Vec<Frame> {
+        let bulk = |s: &'static str| Frame::Bulk(Bytes::from(s));
+        vec![
+            Frame::Array(vec![bulk("SET"), bulk("key"), bulk("value")]),
+            Frame::Map(vec![(bulk("a"), Frame::Integer(-1)), (bulk("b"), Frame::Null)]),
+            Frame::Set(vec![Frame::Double(1.5), Frame::Double(f64::INFINITY)]),
+            Frame::Push(vec![Frame::Boolean(true), Frame::BigNumber("12345678901234567890".into())]),
+            Frame::Attribute {
+                attributes: vec![(bulk("ttl"), Frame::Integer(10))],
+                data: Box::new(Frame::Verbatim { format: *b"txt", data: Bytes::from("hi") }),
+            },
+            Frame::Simple("OK".into()),
+            Frame::Error("ERR bad".into()),
+        ]
+    }
+
+    /// 异步版本编码出来的字节
+    fn async_encoding(protocol: Protocol) -> Vec<u8> {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        rt.block_on(async {
+            let (client, mut server) = io::duplex(64 * 1024);
+            let mut conn = crate::minis_redis::Connection::new(client);
+            conn.set_protocol(protocol);
+            conn.write_frames(&frames()).await.unwrap();
+            drop(conn);
+            let mut out = vec![];
+            server.read_to_end(&mut out).await.unwrap();
+            out
+        })
+    }
+
+    #[test]
+    fn same_encoding_as_async() {
+        for protocol in [Protocol::Resp2, Protocol::Resp3] {
+            let mut conn = Connection::new(Cursor::new(vec![]));
+            conn.set_protocol(protocol);
+            conn.write_frames(&frames()).unwrap();
+            assert_eq!(conn.get_ref().get_ref(), &async_encoding(protocol), "{:?}", protocol);
+        }
+    }
+
+    #[test]
+    fn round_trip_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // 服务端线程把收到的frame原样发回去
+        let server = thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            let mut conn = Connection::new(socket);
+            conn.set_protocol(Protocol::Resp3);
+            while let Some(frame) = conn.read_frame().unwrap() {
+                conn.write_frame(&frame).unwrap();
+            }
+        });
+
+        let mut conn = Connection::connect(addr).unwrap();
+        conn.set_protocol(Protocol::Resp3);
+        for frame in frames() {
+            conn.write_frame(&frame).unwrap();
+            assert_eq!(conn.read_frame().unwrap().unwrap(), frame);
+        }
+
+        // 大的bulk string需要多次读取才能收完
+        let big = Frame::Bulk(Bytes::from(vec![b'x'; 100 * 1024]));
+        conn.write_frame(&big).unwrap();
+        assert_eq!(conn.read_frame().unwrap().unwrap(), big);
+
+        drop(conn);
+        server.join().unwrap();
+    }
+}
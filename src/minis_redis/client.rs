@@ -0,0 +1,270 @@
+//! Minimal Redis client implementation
+//!
+//! Provides an async connect and methods for issuing the supported commands.
+
+use super::cmd::{Get, Ping, Publish, Set, Subscribe, Unsubscribe};
+use super::connection::Connection;
+use super::frame::Frame;
+
+use bytes::Bytes;
+use futures::stream::{self, Stream};
+use std::io::{Error, ErrorKind};
+use tokio::net::ToSocketAddrs;
+use tracing::{debug, instrument};
+
+/// Established connection with a Redis server.
+///
+/// Requests are issued using the various methods of `Client`.
+pub struct Client {
+    connection: Connection,
+}
+
+/// A client that has entered pub/sub mode.
+///
+/// Once clients subscribe to a channel, they may only perform pub/sub related
+/// commands. The `Client` type is transitioned to a `Subscriber` type in order
+/// to prevent non-pub/sub methods from being called.
+pub struct Subscriber {
+    /// The subscribed client.
+    client: Client,
+
+    /// The set of channels to which the `Subscriber` is currently subscribed.
+    subscribed_channels: Vec<String>,
+}
+
+/// A message received on a subscribed channel.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub channel: String,
+    pub content: Bytes,
+}
+
+/// Establish a connection with the Redis server located at `addr`.
+pub async fn connect<T: ToSocketAddrs>(addr: T) -> crate::minis_redis::Result<Client> {
+    let connection = Connection::connect(addr).await?;
+
+    Ok(Client { connection })
+}
+
+impl Client {
+    /// Ping the server, returns `PONG` or a copy of `msg`.
+    #[instrument(skip(self))]
+    pub async fn ping(&mut self, msg: Option<Bytes>) -> crate::minis_redis::Result<Bytes> {
+        let frame = Ping::new(msg).into_frame();
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(value) => Ok(value.into()),
+            Frame::Bulk(value) => Ok(value),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Get the value of key.
+    ///
+    /// If the key does not exist the special value `None` is returned.
+    #[instrument(skip(self))]
+    pub async fn get(&mut self, key: &str) -> crate::minis_redis::Result<Option<Bytes>> {
+        let frame = Get::new(key).into_frame();
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(value) => Ok(Some(value.into())),
+            Frame::Bulk(value) => Ok(Some(value)),
+            Frame::Null => Ok(None),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Set `key` to hold the given `value`.
+    #[instrument(skip(self))]
+    pub async fn set(&mut self, key: &str, value: Bytes) -> crate::minis_redis::Result<()> {
+        let frame = Set::new(key, value).into_frame();
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Posts `message` to the given `channel`.
+    ///
+    /// Returns the number of subscribers currently listening on the channel.
+    #[instrument(skip(self))]
+    pub async fn publish(&mut self, channel: &str, message: Bytes) -> crate::minis_redis::Result<i64> {
+        let frame = Publish::new(channel, message).into_frame();
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(response) => Ok(response),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Subscribes the client to the specified channels.
+    ///
+    /// Once a client issues a subscribe command, it may no longer issue any
+    /// non-pub/sub commands. The function consumes `self` and returns a
+    /// `Subscriber`.
+    #[instrument(skip(self))]
+    pub async fn subscribe(mut self, channels: Vec<String>) -> crate::minis_redis::Result<Subscriber> {
+        self.subscribe_cmd(&channels).await?;
+
+        Ok(Subscriber {
+            client: self,
+            subscribed_channels: channels,
+        })
+    }
+
+    /// The core `SUBSCRIBE` logic, used by misc subscribe fns
+    async fn subscribe_cmd(&mut self, channels: &[String]) -> crate::minis_redis::Result<()> {
+        let frame = Subscribe::new(channels).into_frame();
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        // For each channel being subscribed to, the server responds with a
+        // message confirming subscription to that channel.
+        for channel in channels {
+            let response = self.read_response().await?;
+
+            match push_parts(&response) {
+                Some([subscribe, schannel, ..]) if *subscribe == "subscribe" && *schannel == channel.as_str() => {}
+                _ => return Err(response.to_error()),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads a response frame from the socket.
+    ///
+    /// If an `Error` frame is received, it is converted to `Err`.
+    async fn read_response(&mut self) -> crate::minis_redis::Result<Frame> {
+        let response = self.connection.read_frame().await?;
+
+        debug!(?response);
+
+        match response {
+            // Error frames are converted to `Err`
+            Some(Frame::Error(msg)) => Err(msg.into()),
+            Some(frame) => Ok(frame),
+            None => {
+                // Receiving `None` here indicates the server has closed the
+                // connection without sending a frame. This is unexpected and is
+                // represented as a "connection reset by peer" error.
+                let err = Error::new(ErrorKind::ConnectionReset, "connection reset by server");
+
+                Err(err.into())
+            }
+        }
+    }
+}
+
+impl Subscriber {
+    /// Returns the set of channels currently subscribed to.
+    pub fn get_subscribed(&self) -> &[String] {
+        &self.subscribed_channels
+    }
+
+    /// Receive the next message published on a subscribed channel, waiting if
+    /// necessary.
+    ///
+    /// `None` indicates the subscription has been terminated.
+    pub async fn next_message(&mut self) -> crate::minis_redis::Result<Option<Message>> {
+        match self.client.connection.read_frame().await? {
+            Some(mframe) => {
+                debug!(?mframe);
+
+                match push_parts(&mframe) {
+                    Some([message, channel, content]) if *message == "message" => Ok(Some(Message {
+                        channel: channel.to_string(),
+                        content: Bytes::from(content.to_string()),
+                    })),
+                    _ => Err(mframe.to_error()),
+                }
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Convert the subscriber into a `Stream` yielding new messages published
+    /// on subscribed channels.
+    pub fn into_stream(self) -> impl Stream<Item = crate::minis_redis::Result<Message>> {
+        stream::unfold(Some(self), |subscriber| async move {
+            let mut subscriber = subscriber?;
+            match subscriber.next_message().await {
+                Ok(Some(message)) => Some((Ok(message), Some(subscriber))),
+                Ok(None) => None,
+                // 出错之后不再继续读
+                Err(err) => Some((Err(err), None)),
+            }
+        })
+    }
+
+    /// Subscribe to a list of new channels
+    #[instrument(skip(self))]
+    pub async fn subscribe(&mut self, channels: &[String]) -> crate::minis_redis::Result<()> {
+        self.client.subscribe_cmd(channels).await?;
+
+        self.subscribed_channels.extend(channels.iter().cloned());
+
+        Ok(())
+    }
+
+    /// Unsubscribe to a list of new channels
+    #[instrument(skip(self))]
+    pub async fn unsubscribe(&mut self, channels: &[String]) -> crate::minis_redis::Result<()> {
+        let frame = Unsubscribe::new(channels).into_frame();
+        debug!(request = ?frame);
+        self.client.connection.write_frame(&frame).await?;
+
+        // if the input channel list is empty, server acknowledges as unsubscribing
+        // from all subscribed channels, so we assert that the unsubscribe list received
+        // matches the client subscribed one
+        let num = if channels.is_empty() {
+            self.subscribed_channels.len()
+        } else {
+            channels.len()
+        };
+
+        // Read the response
+        for _ in 0..num {
+            let response = self.client.read_response().await?;
+
+            match push_parts(&response) {
+                Some([unsubscribe, channel, ..]) if *unsubscribe == "unsubscribe" => {
+                    let len = self.subscribed_channels.len();
+
+                    if len == 0 {
+                        return Err(response.to_error());
+                    }
+
+                    // unsubscribed channel should exist in the subscribed list at this point
+                    self.subscribed_channels.retain(|c| *channel != c.as_str());
+
+                    // Only a single channel should be removed from the
+                    // list of subscribed channels.
+                    if self.subscribed_channels.len() != len - 1 {
+                        return Err(response.to_error());
+                    }
+                }
+                _ => return Err(response.to_error()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// pub/sub 的消息在RESP2中是数组, 在RESP3中是push
+fn push_parts(frame: &Frame) -> Option<&[Frame]> {
+    match frame {
+        Frame::Array(parts) | Frame::Push(parts) => Some(parts),
+        _ => None,
+    }
+}
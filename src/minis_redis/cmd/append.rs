@@ -0,0 +1,98 @@
+use crate::minis_redis::db::{Db, Guard};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+
+use bytes::{Bytes, BytesMut};
+
+/// Appends `value` at the end of the string stored at `key`.
+///
+/// A missing key is created as an empty string first. The reply is the length
+/// of the string after the append.
+#[derive(Debug)]
+pub struct Append {
+    key: String,
+    value: Bytes,
+}
+
+impl Append {
+    /// Create a new `Append` command which appends `value` to `key`.
+    pub fn new(key: impl ToString, value: Bytes) -> Append {
+        Append {
+            key: key.to_string(),
+            value,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn value(&self) -> &Bytes {
+        &self.value
+    }
+
+    /// Parse an `Append` instance from a received frame.
+    ///
+    /// The `APPEND` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// APPEND key value
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<Append> {
+        let key = parse.next_string()?;
+        let value = parse.next_bytes()?;
+
+        Ok(Append { key, value })
+    }
+
+    /// Apply the `Append` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `Append` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        let value = match guard.get(&self.key) {
+            Ok(Some(old)) => {
+                let mut value = BytesMut::with_capacity(old.len() + self.value.len());
+                value.extend_from_slice(old);
+                value.extend_from_slice(&self.value);
+                value.freeze()
+            }
+            Ok(None) => self.value.clone(),
+            Err(err) => return err.into(),
+        };
+        let len = value.len();
+        guard.log(|| Append::new(&self.key, self.value.clone()).into_frame());
+        guard.update(self.key, value);
+        Frame::Integer(len as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("append".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(self.value);
+        frame
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::minis_redis::db::Db;
+    use crate::minis_redis::frame::Frame;
+    use crate::minis_redis::test_util::run;
+
+    #[test]
+    fn append_and_strlen() {
+        let db = Db::new(4);
+        assert_eq!(run(&db, &["append", "s", "abc"]), Frame::Integer(3));
+        assert_eq!(run(&db, &["append", "s", "de"]), Frame::Integer(5));
+        assert_eq!(run(&db, &["strlen", "s"]), Frame::Integer(5));
+        assert_eq!(run(&db, &["strlen", "missing"]), Frame::Integer(0));
+    }
+}
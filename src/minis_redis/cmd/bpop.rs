@@ -0,0 +1,198 @@
+use crate::minis_redis::blocked::{Blocked, Outcome};
+use crate::minis_redis::cmd::lmove::End;
+use crate::minis_redis::cmd::pop::pop_one;
+use crate::minis_redis::db::{Db, Guard};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::{Parse, ParseError};
+
+use bytes::Bytes;
+use std::time::Duration;
+
+/// Pop the first element of the first non-empty list among `keys`.
+///
+/// When all the lists are empty the connection blocks until an element is
+/// pushed to one of them, or `timeout` elapses. Clients blocked on the same
+/// key are served in the order they blocked. A timeout of zero blocks
+/// forever.
+///
+/// The reply is a two-element array with the key and the popped element, or
+/// nil on timeout.
+#[derive(Debug)]
+pub struct BLPop {
+    keys: Vec<String>,
+    timeout: Option<Duration>,
+}
+
+/// Like `BLPop`, but the element is popped from the tail of the list.
+#[derive(Debug)]
+pub struct BRPop {
+    keys: Vec<String>,
+    timeout: Option<Duration>,
+}
+
+impl BLPop {
+    /// Create a new `BLPop` command which waits at most `timeout` on `keys`,
+    /// `None` waits forever.
+    pub fn new(keys: &[String], timeout: Option<Duration>) -> BLPop {
+        BLPop {
+            keys: keys.to_vec(),
+            timeout,
+        }
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse a `BLPop` instance from a received frame.
+    ///
+    /// The `BLPOP` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// BLPOP key [key ...] timeout
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<BLPop> {
+        let (keys, timeout) = parse_keys_and_timeout(parse)?;
+
+        Ok(BLPop { keys, timeout })
+    }
+
+    /// Apply the `BLPop` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Outcome {
+        bpop(db, self.keys, self.timeout, End::Left)
+    }
+
+    /// Apply the `BLPop` command while the shards of its keys are already locked.
+    ///
+    /// It never blocks here: inside a transaction the reply is nil right away
+    /// when all the lists are empty.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        pop_first(guard, &self.keys, End::Left).unwrap_or(Frame::Null)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        into_frame("blpop", self.keys, self.timeout)
+    }
+}
+
+impl BRPop {
+    /// Create a new `BRPop` command which waits at most `timeout` on `keys`,
+    /// `None` waits forever.
+    pub fn new(keys: &[String], timeout: Option<Duration>) -> BRPop {
+        BRPop {
+            keys: keys.to_vec(),
+            timeout,
+        }
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse a `BRPop` instance from a received frame.
+    ///
+    /// The `BRPOP` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// BRPOP key [key ...] timeout
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<BRPop> {
+        let (keys, timeout) = parse_keys_and_timeout(parse)?;
+
+        Ok(BRPop { keys, timeout })
+    }
+
+    /// Apply the `BRPop` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Outcome {
+        bpop(db, self.keys, self.timeout, End::Right)
+    }
+
+    /// Apply the `BRPop` command while the shards of its keys are already locked.
+    ///
+    /// It never blocks here: inside a transaction the reply is nil right away
+    /// when all the lists are empty.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        pop_first(guard, &self.keys, End::Right).unwrap_or(Frame::Null)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        into_frame("brpop", self.keys, self.timeout)
+    }
+}
+
+/// 超时时间, 单位是秒, 可以有小数。0表示一直等
+pub(crate) fn parse_timeout(parse: &mut Parse) -> crate::minis_redis::Result<Option<Duration>> {
+    parse_seconds(&parse.next_string()?)
+}
+
+fn parse_seconds(src: &str) -> crate::minis_redis::Result<Option<Duration>> {
+    let secs = match src.parse::<f64>() {
+        Ok(secs) if secs.is_finite() => secs,
+        _ => return Err("timeout is not a float or out of range".into()),
+    };
+    if secs < 0.0 {
+        return Err("timeout is negative".into());
+    }
+    match Duration::try_from_secs_f64(secs) {
+        Ok(timeout) if timeout.is_zero() => Ok(None),
+        Ok(timeout) => Ok(Some(timeout)),
+        Err(_) => Err("timeout is not a float or out of range".into()),
+    }
+}
+
+/// 至少一个key, 最后一个参数是超时时间
+fn parse_keys_and_timeout(parse: &mut Parse) -> crate::minis_redis::Result<(Vec<String>, Option<Duration>)> {
+    let mut keys = vec![parse.next_string()?, parse.next_string()?];
+
+    loop {
+        match parse.next_string() {
+            Ok(key) => keys.push(key),
+            Err(ParseError::EndOfStream) => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    let timeout = keys.pop().unwrap();
+    Ok((keys, parse_seconds(&timeout)?))
+}
+
+/// BLPOP 和 BRPOP 共用: 按顺序找第一个不空的list, 都是空的就在这些key上排队
+fn bpop(db: &Db, keys: Vec<String>, timeout: Option<Duration>, from: End) -> Outcome {
+    let mut guard = db.lock(&keys);
+    match pop_first(&mut guard, &keys, from) {
+        Some(reply) => Outcome::Ready(reply),
+        None => Outcome::Blocked(Blocked::new(&mut guard, keys, from, None, timeout)),
+    }
+}
+
+/// 从第一个不空的list弹出一个元素, 回复key和元素; 都是空的时返回None
+fn pop_first(guard: &mut Guard<'_>, keys: &[String], from: End) -> Option<Frame> {
+    for key in keys {
+        match pop_one(guard, key, from == End::Left) {
+            Ok(Some(value)) => {
+                let key = Frame::Bulk(Bytes::from(key.clone().into_bytes()));
+                return Some(Frame::Array(vec![key, Frame::Bulk(value)]));
+            }
+            Ok(None) => {}
+            Err(err) => return Some(err.into()),
+        }
+    }
+    None
+}
+
+fn into_frame(name: &'static str, keys: Vec<String>, timeout: Option<Duration>) -> Frame {
+    let timeout = timeout.unwrap_or_default().as_secs_f64();
+    let mut frame = Frame::array();
+    frame.push_bulk(Bytes::from(name.as_bytes()));
+    for key in keys {
+        frame.push_bulk(Bytes::from(key.into_bytes()));
+    }
+    frame.push_bulk(Bytes::from(timeout.to_string()));
+    frame
+}
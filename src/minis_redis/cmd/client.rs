@@ -0,0 +1,47 @@
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+
+use bytes::Bytes;
+
+/// Inspect the connections of the server.
+///
+/// Only the subcommands that report on connections are supported: `LIST`
+/// returns one line per connection with its traffic statistics, `ID` returns
+/// the id of the current connection.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Client {
+    List,
+    Id,
+}
+
+impl Client {
+    /// Parse a `Client` instance from a received frame.
+    ///
+    /// The `CLIENT` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// CLIENT LIST
+    /// CLIENT ID
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<Client> {
+        let subcommand = parse.next_string()?.to_lowercase();
+        match &subcommand[..] {
+            "list" => Ok(Client::List),
+            "id" => Ok(Client::Id),
+            _ => Err(format!("unknown subcommand '{}'", subcommand).into()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("client".as_bytes()));
+        frame.push_bulk(Bytes::from(match self {
+            Client::List => "list".as_bytes(),
+            Client::Id => "id".as_bytes(),
+        }));
+        frame
+    }
+}
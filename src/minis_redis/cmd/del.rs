@@ -0,0 +1,67 @@
+use crate::minis_redis::db::{Db, Guard};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+
+use bytes::Bytes;
+
+/// Removes the specified keys. A key is ignored if it does not exist.
+///
+/// The reply is the number of keys that were removed.
+#[derive(Debug)]
+pub struct Del {
+    keys: Vec<String>,
+}
+
+impl Del {
+    /// Create a new `Del` command which removes `keys`.
+    pub fn new(keys: &[String]) -> Del {
+        Del { keys: keys.to_vec() }
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse a `Del` instance from a received frame.
+    ///
+    /// The `DEL` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// DEL key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<Del> {
+        Ok(Del {
+            keys: parse.next_strings()?,
+        })
+    }
+
+    /// Apply the `Del` command to the specified `Db` instance.
+    ///
+    /// All the shards involved are locked together, so the keys are removed
+    /// atomically.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock(&self.keys);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `Del` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        let removed = self.keys.iter().filter(|key| guard.remove(key).is_some()).count();
+        if removed > 0 {
+            guard.log(|| Del::new(&self.keys).into_frame());
+        }
+        Frame::Integer(removed as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("del".as_bytes()));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key.into_bytes()));
+        }
+        frame
+    }
+}
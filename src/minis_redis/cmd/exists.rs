@@ -0,0 +1,61 @@
+use crate::minis_redis::db::{Db, Guard};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+
+use bytes::Bytes;
+
+/// Returns how many of the specified keys exist.
+///
+/// A key that is mentioned multiple times is counted multiple times.
+#[derive(Debug)]
+pub struct Exists {
+    keys: Vec<String>,
+}
+
+impl Exists {
+    /// Create a new `Exists` command which checks `keys`.
+    pub fn new(keys: &[String]) -> Exists {
+        Exists { keys: keys.to_vec() }
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse an `Exists` instance from a received frame.
+    ///
+    /// The `EXISTS` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// EXISTS key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<Exists> {
+        Ok(Exists {
+            keys: parse.next_strings()?,
+        })
+    }
+
+    /// Apply the `Exists` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock(&self.keys);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `Exists` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        let count = self.keys.iter().filter(|key| guard.contains_key(key)).count();
+        Frame::Integer(count as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("exists".as_bytes()));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key.into_bytes()));
+        }
+        frame
+    }
+}
@@ -0,0 +1,251 @@
+use crate::minis_redis::db::{self, Db, Guard};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+
+use bytes::Bytes;
+use std::convert::TryFrom;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Set a timeout on `key`, in seconds. After the timeout has expired, the key
+/// will automatically be deleted.
+///
+/// The reply is 1 if the timeout was set, 0 if `key` does not exist. A timeout
+/// that is not positive deletes the key right away.
+#[derive(Debug)]
+pub struct Expire {
+    key: String,
+    seconds: i64,
+}
+
+/// Like `Expire`, but the timeout is in milliseconds.
+#[derive(Debug)]
+pub struct PExpire {
+    key: String,
+    millis: i64,
+}
+
+/// Set `key` to expire at an absolute unix timestamp, in milliseconds.
+///
+/// This is what the AOF records for every relative timeout, so that a restart
+/// does not extend it. A timestamp in the past deletes the key right away.
+#[derive(Debug)]
+pub struct PExpireAt {
+    key: String,
+    unix_millis: i64,
+}
+
+impl Expire {
+    /// Create a new `Expire` command which expires `key` after `seconds`.
+    pub fn new(key: impl ToString, seconds: i64) -> Expire {
+        Expire {
+            key: key.to_string(),
+            seconds,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn seconds(&self) -> i64 {
+        self.seconds
+    }
+
+    /// Parse an `Expire` instance from a received frame.
+    ///
+    /// The `EXPIRE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// EXPIRE key seconds
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<Expire> {
+        let key = parse.next_string()?;
+        let seconds = parse.next_int()?;
+
+        Ok(Expire { key, seconds })
+    }
+
+    /// Apply the `Expire` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `Expire` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        expire(guard, &self.key, self.seconds.saturating_mul(1000))
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("expire".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.seconds.to_string()));
+        frame
+    }
+}
+
+impl PExpire {
+    /// Create a new `PExpire` command which expires `key` after `millis`.
+    pub fn new(key: impl ToString, millis: i64) -> PExpire {
+        PExpire {
+            key: key.to_string(),
+            millis,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn millis(&self) -> i64 {
+        self.millis
+    }
+
+    /// Parse a `PExpire` instance from a received frame.
+    ///
+    /// The `PEXPIRE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// PEXPIRE key milliseconds
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<PExpire> {
+        let key = parse.next_string()?;
+        let millis = parse.next_int()?;
+
+        Ok(PExpire { key, millis })
+    }
+
+    /// Apply the `PExpire` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `PExpire` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        expire(guard, &self.key, self.millis)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("pexpire".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.millis.to_string()));
+        frame
+    }
+}
+
+impl PExpireAt {
+    /// Create a new `PExpireAt` command which expires `key` at `unix_millis`.
+    pub fn new(key: impl ToString, unix_millis: i64) -> PExpireAt {
+        PExpireAt {
+            key: key.to_string(),
+            unix_millis,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn unix_millis(&self) -> i64 {
+        self.unix_millis
+    }
+
+    /// Parse a `PExpireAt` instance from a received frame.
+    ///
+    /// The `PEXPIREAT` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// PEXPIREAT key unix-time-milliseconds
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<PExpireAt> {
+        let key = parse.next_string()?;
+        let unix_millis = parse.next_int()?;
+
+        Ok(PExpireAt { key, unix_millis })
+    }
+
+    /// Apply the `PExpireAt` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `PExpireAt` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        match db::instant_at(self.unix_millis) {
+            Some(when) => set_expiry(guard, &self.key, when),
+            None => Frame::Error("ERR invalid expire time in 'pexpireat' command".to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("pexpireat".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.unix_millis.to_string()));
+        frame
+    }
+}
+
+/// EXPIRE 和 PEXPIRE 共用, 不是正数的超时时间会马上删除key
+fn expire(guard: &mut Guard<'_>, key: &str, millis: i64) -> Frame {
+    let now = Instant::now();
+    let when = match u64::try_from(millis) {
+        Ok(millis) => now.checked_add(Duration::from_millis(millis)),
+        Err(_) => Some(now),
+    };
+    let Some(when) = when else {
+        return Frame::Error("ERR invalid expire time in 'expire' command".to_string());
+    };
+
+    set_expiry(guard, key, when)
+}
+
+/// 设置过期时间并且记进AOF, AOF里总是记绝对时间
+fn set_expiry(guard: &mut Guard<'_>, key: &str, when: Instant) -> Frame {
+    if !guard.set_expiry(key, Some(when)) {
+        return Frame::Integer(0);
+    }
+    // 已经过去的时间回放时同样会删除key
+    guard.log(|| PExpireAt::new(key, db::unix_millis(when)).into_frame());
+    Frame::Integer(1)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::minis_redis::db::Db;
+    use crate::minis_redis::frame::Frame;
+    use crate::minis_redis::test_util::run;
+
+    #[test]
+    fn expire_ttl_and_persist() {
+        let db = Db::new(4);
+        assert_eq!(run(&db, &["ttl", "k"]), Frame::Integer(-2));
+        assert_eq!(run(&db, &["expire", "k", "10"]), Frame::Integer(0));
+
+        run(&db, &["set", "k", "v"]);
+        assert_eq!(run(&db, &["ttl", "k"]), Frame::Integer(-1));
+        assert_eq!(run(&db, &["persist", "k"]), Frame::Integer(0));
+        assert_eq!(run(&db, &["pexpire", "k", "20000"]), Frame::Integer(1));
+        assert!(matches!(run(&db, &["pttl", "k"]), Frame::Integer(ms) if ms > 19000 && ms <= 20000));
+        assert_eq!(run(&db, &["persist", "k"]), Frame::Integer(1));
+        assert_eq!(run(&db, &["ttl", "k"]), Frame::Integer(-1));
+
+        // 不是正数的超时时间直接删除key
+        assert_eq!(run(&db, &["expire", "k", "-1"]), Frame::Integer(1));
+        assert_eq!(run(&db, &["ttl", "k"]), Frame::Integer(-2));
+    }
+}
@@ -0,0 +1,69 @@
+use crate::minis_redis::db::{Db, Guard};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+
+use bytes::Bytes;
+
+/// Get the value of key.
+///
+/// If the key does not exist the special value nil is returned.
+#[derive(Debug)]
+pub struct Get {
+    /// Name of the key to get
+    key: String,
+}
+
+impl Get {
+    /// Create a new `Get` command which fetches `key`.
+    pub fn new(key: impl ToString) -> Get {
+        Get {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `Get` instance from a received frame.
+    ///
+    /// The `GET` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// GET key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<Get> {
+        let key = parse.next_string()?;
+
+        Ok(Get { key })
+    }
+
+    /// Apply the `Get` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `Get` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        match guard.get(&self.key) {
+            Ok(Some(value)) => Frame::Bulk(value.clone()),
+            Ok(None) => Frame::Null,
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Get` command to send to
+    /// the server.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("get".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
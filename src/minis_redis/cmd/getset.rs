@@ -0,0 +1,75 @@
+use crate::minis_redis::cmd::Set;
+use crate::minis_redis::db::{Db, Guard};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+
+use bytes::Bytes;
+
+/// Atomically sets `key` to `value` and returns the old value stored at `key`.
+///
+/// The reply is nil when `key` did not exist.
+#[derive(Debug)]
+pub struct GetSet {
+    key: String,
+    value: Bytes,
+}
+
+impl GetSet {
+    /// Create a new `GetSet` command which sets `key` to `value`.
+    pub fn new(key: impl ToString, value: Bytes) -> GetSet {
+        GetSet {
+            key: key.to_string(),
+            value,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn value(&self) -> &Bytes {
+        &self.value
+    }
+
+    /// Parse a `GetSet` instance from a received frame.
+    ///
+    /// The `GETSET` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// GETSET key value
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<GetSet> {
+        let key = parse.next_string()?;
+        let value = parse.next_bytes()?;
+
+        Ok(GetSet { key, value })
+    }
+
+    /// Apply the `GetSet` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `GetSet` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        let old = match guard.get(&self.key) {
+            Ok(old) => old.cloned(),
+            Err(err) => return err.into(),
+        };
+        guard.log(|| Set::new(&self.key, self.value.clone()).into_frame());
+        guard.insert(self.key, self.value);
+        old.map_or(Frame::Null, Frame::Bulk)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("getset".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(self.value);
+        frame
+    }
+}
@@ -0,0 +1,409 @@
+use crate::minis_redis::db::{Db, Guard};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::{Parse, ParseError};
+
+use bytes::Bytes;
+
+/// Sets the given fields of the hash stored at `key`, creating the hash if
+/// `key` does not exist.
+///
+/// The reply is the number of fields that were added, fields that already
+/// existed are overwritten but not counted.
+#[derive(Debug)]
+pub struct HSet {
+    key: String,
+    pairs: Vec<(Bytes, Bytes)>,
+}
+
+/// Returns the value of `field` in the hash stored at `key`, nil if either
+/// does not exist.
+#[derive(Debug)]
+pub struct HGet {
+    key: String,
+    field: Bytes,
+}
+
+/// Returns all the fields and values of the hash stored at `key`.
+///
+/// The reply is a map, which RESP2 clients receive as an array of
+/// alternating fields and values.
+#[derive(Debug)]
+pub struct HGetAll {
+    key: String,
+}
+
+/// Removes the given fields from the hash stored at `key`.
+///
+/// The reply is the number of fields that were removed. The key is deleted
+/// once its hash becomes empty.
+#[derive(Debug)]
+pub struct HDel {
+    key: String,
+    fields: Vec<Bytes>,
+}
+
+/// Increments the integer stored in `field` of the hash at `key` by
+/// `increment`. A missing field counts as 0.
+#[derive(Debug)]
+pub struct HIncrBy {
+    key: String,
+    field: Bytes,
+    increment: i64,
+}
+
+impl HSet {
+    /// Create a new `HSet` command which sets every field to its value.
+    pub fn new(key: impl ToString, pairs: &[(Bytes, Bytes)]) -> HSet {
+        HSet {
+            key: key.to_string(),
+            pairs: pairs.to_vec(),
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `HSet` instance from a received frame.
+    ///
+    /// The `HSET` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// HSET key field value [field value ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<HSet> {
+        let key = parse.next_string()?;
+        let mut pairs = vec![(parse.next_bytes()?, parse.next_bytes()?)];
+
+        loop {
+            match parse.next_bytes() {
+                Ok(field) => pairs.push((field, parse.next_bytes()?)),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(HSet { key, pairs })
+    }
+
+    /// Apply the `HSet` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `HSet` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        let hash = match guard.hash_or_default(&self.key) {
+            Ok(hash) => hash,
+            Err(err) => return err.into(),
+        };
+        let added = self
+            .pairs
+            .iter()
+            .filter(|(field, value)| hash.insert(field.clone(), value.clone()).is_none())
+            .count();
+
+        guard.log(|| self.into_frame());
+        Frame::Integer(added as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hset".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        for (field, value) in self.pairs {
+            frame.push_bulk(field);
+            frame.push_bulk(value);
+        }
+        frame
+    }
+}
+
+impl HGet {
+    /// Create a new `HGet` command which fetches `field` of `key`.
+    pub fn new(key: impl ToString, field: Bytes) -> HGet {
+        HGet {
+            key: key.to_string(),
+            field,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `HGet` instance from a received frame.
+    ///
+    /// The `HGET` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// HGET key field
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<HGet> {
+        let key = parse.next_string()?;
+        let field = parse.next_bytes()?;
+
+        Ok(HGet { key, field })
+    }
+
+    /// Apply the `HGet` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `HGet` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        match guard.hash(&self.key) {
+            Ok(hash) => match hash.and_then(|hash| hash.get(&self.field)) {
+                Some(value) => Frame::Bulk(value.clone()),
+                None => Frame::Null,
+            },
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hget".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(self.field);
+        frame
+    }
+}
+
+impl HGetAll {
+    /// Create a new `HGetAll` command for `key`.
+    pub fn new(key: impl ToString) -> HGetAll {
+        HGetAll { key: key.to_string() }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `HGetAll` instance from a received frame.
+    ///
+    /// The `HGETALL` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// HGETALL key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<HGetAll> {
+        let key = parse.next_string()?;
+
+        Ok(HGetAll { key })
+    }
+
+    /// Apply the `HGetAll` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `HGetAll` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        match guard.hash(&self.key) {
+            Ok(hash) => Frame::Map(
+                hash.into_iter()
+                    .flatten()
+                    .map(|(field, value)| (Frame::Bulk(field.clone()), Frame::Bulk(value.clone())))
+                    .collect(),
+            ),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hgetall".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl HDel {
+    /// Create a new `HDel` command which removes `fields` from `key`.
+    pub fn new(key: impl ToString, fields: &[Bytes]) -> HDel {
+        HDel {
+            key: key.to_string(),
+            fields: fields.to_vec(),
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `HDel` instance from a received frame.
+    ///
+    /// The `HDEL` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// HDEL key field [field ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<HDel> {
+        let key = parse.next_string()?;
+        let fields = parse.next_bytes_list()?;
+
+        Ok(HDel { key, fields })
+    }
+
+    /// Apply the `HDel` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `HDel` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        let hash = match guard.hash_mut(&self.key) {
+            Ok(Some(hash)) => hash,
+            Ok(None) => return Frame::Integer(0),
+            Err(err) => return err.into(),
+        };
+        let removed = self.fields.iter().filter(|field| hash.remove(*field).is_some()).count();
+        if hash.is_empty() {
+            guard.remove(&self.key);
+        }
+
+        if removed > 0 {
+            guard.log(|| self.into_frame());
+        }
+        Frame::Integer(removed as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hdel".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        for field in self.fields {
+            frame.push_bulk(field);
+        }
+        frame
+    }
+}
+
+impl HIncrBy {
+    /// Create a new `HIncrBy` command which adds `increment` to `field` of
+    /// `key`.
+    pub fn new(key: impl ToString, field: Bytes, increment: i64) -> HIncrBy {
+        HIncrBy {
+            key: key.to_string(),
+            field,
+            increment,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `HIncrBy` instance from a received frame.
+    ///
+    /// The `HINCRBY` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// HINCRBY key field increment
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<HIncrBy> {
+        let key = parse.next_string()?;
+        let field = parse.next_bytes()?;
+        let increment = parse.next_int()?;
+
+        Ok(HIncrBy { key, field, increment })
+    }
+
+    /// Apply the `HIncrBy` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `HIncrBy` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        let current = match guard.hash(&self.key) {
+            Ok(hash) => match hash.and_then(|hash| hash.get(&self.field)) {
+                Some(value) => match std::str::from_utf8(value).ok().and_then(|s| s.parse::<i64>().ok()) {
+                    Some(current) => current,
+                    None => return Frame::Error("ERR hash value is not an integer".to_string()),
+                },
+                None => 0,
+            },
+            Err(err) => return err.into(),
+        };
+        let Some(new) = current.checked_add(self.increment) else {
+            return Frame::Error("ERR increment or decrement would overflow".to_string());
+        };
+
+        let value = Bytes::from(new.to_string());
+        // 和INCR一样, 记成执行之后的结果
+        guard.log(|| HSet::new(&self.key, &[(self.field.clone(), value.clone())]).into_frame());
+        guard.hash_or_default(&self.key).unwrap().insert(self.field, value);
+        Frame::Integer(new)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hincrby".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(self.field);
+        frame.push_bulk(Bytes::from(self.increment.to_string()));
+        frame
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::minis_redis::cmd::Command;
+    use crate::minis_redis::db::Db;
+    use crate::minis_redis::frame::Frame;
+    use crate::minis_redis::test_util::{command, run, sorted};
+
+    #[test]
+    fn hash_commands() {
+        let db = Db::new(4);
+        let bulk = |s: &'static str| Frame::Bulk(Bytes::from(s));
+
+        assert_eq!(run(&db, &["hset", "h", "a", "1", "b", "2"]), Frame::Integer(2));
+        assert_eq!(run(&db, &["hset", "h", "a", "3", "c", "4"]), Frame::Integer(1));
+        assert_eq!(run(&db, &["hget", "h", "a"]), bulk("3"));
+        assert_eq!(run(&db, &["hget", "h", "missing"]), Frame::Null);
+        assert_eq!(run(&db, &["hincrby", "h", "a", "-5"]), Frame::Integer(-2));
+        assert_eq!(run(&db, &["hincrby", "h", "n", "7"]), Frame::Integer(7));
+        run(&db, &["hset", "h", "s", "abc"]);
+        assert_eq!(
+            run(&db, &["hincrby", "h", "s", "1"]),
+            Frame::Error("ERR hash value is not an integer".to_string())
+        );
+        assert_eq!(sorted(run(&db, &["hgetall", "h"])), ["a=-2", "b=2", "c=4", "n=7", "s=abc"]);
+        assert_eq!(run(&db, &["hdel", "h", "a", "b", "x"]), Frame::Integer(2));
+        assert_eq!(run(&db, &["hdel", "h", "c", "n", "s"]), Frame::Integer(3));
+        assert_eq!(run(&db, &["exists", "h"]), Frame::Integer(0));
+        assert!(Command::from_frame(command(&["hset", "h", "a"])).is_err());
+
+        run(&db, &["set", "str", "v"]);
+        assert_eq!(
+            run(&db, &["hget", "str", "a"]),
+            Frame::Error("WRONGTYPE Operation against a key holding the wrong kind of value".to_string())
+        );
+    }
+}
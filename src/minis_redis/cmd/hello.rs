@@ -0,0 +1,87 @@
+use crate::minis_redis::connection::Connection;
+use crate::minis_redis::frame::{Frame, Protocol};
+use crate::minis_redis::parse::{Parse, ParseError};
+
+use bytes::Bytes;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Switch the protocol spoken on the connection and return server info.
+///
+/// `HELLO` without arguments only returns the info in the current protocol.
+/// `HELLO 3` switches the connection to RESP3 so that maps, sets, doubles,
+/// push frames etc. are sent with their native encoding, `HELLO 2` switches
+/// back. Unsupported versions are answered with a `NOPROTO` error and the
+/// protocol is left unchanged.
+#[derive(Debug)]
+pub struct Hello {
+    /// Requested protocol version
+    protover: Option<i64>,
+}
+
+impl Hello {
+    /// Create a new `Hello` command requesting `protover`
+    pub fn new(protover: Option<i64>) -> Hello {
+        Hello { protover }
+    }
+
+    /// Get the requested protocol version
+    pub fn protover(&self) -> Option<i64> {
+        self.protover
+    }
+
+    /// Parse a `Hello` instance from a received frame.
+    ///
+    /// The `HELLO` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// HELLO [protover]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<Hello> {
+        match parse.next_int() {
+            Ok(protover) => Ok(Hello::new(Some(protover))),
+            Err(ParseError::EndOfStream) => Ok(Hello::new(None)),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Apply the `Hello` command to the connection and return the reply.
+    ///
+    /// The protocol is switched right away, the reply must be written after
+    /// this call so that it is already encoded in the negotiated protocol.
+    pub fn apply<S>(self, dst: &mut Connection<S>) -> Frame
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        if let Some(protover) = self.protover {
+            match Protocol::from_version(protover) {
+                Some(protocol) => dst.set_protocol(protocol),
+                None => return Frame::Error("NOPROTO unsupported protocol version".to_string()),
+            }
+        }
+
+        Frame::Map(vec![
+            (bulk("server"), bulk("minis_redis")),
+            (bulk("version"), bulk(env!("CARGO_PKG_VERSION"))),
+            (bulk("proto"), Frame::Integer(dst.protocol().version())),
+            (bulk("mode"), bulk("standalone")),
+            (bulk("role"), bulk("master")),
+            (bulk("modules"), Frame::Array(vec![])),
+        ])
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hello".as_bytes()));
+        if let Some(protover) = self.protover {
+            frame.push_bulk(Bytes::from(protover.to_string()));
+        }
+        frame
+    }
+}
+
+fn bulk(s: &'static str) -> Frame {
+    Frame::Bulk(Bytes::from_static(s.as_bytes()))
+}
@@ -0,0 +1,282 @@
+use crate::minis_redis::cmd::Set;
+use crate::minis_redis::db::{Db, Guard};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+
+use bytes::Bytes;
+
+/// Increments the number stored at `key` by one.
+///
+/// A missing key is set to 0 before the operation. An error is returned if the
+/// value is not a string that can be represented as a 64 bit signed integer.
+#[derive(Debug)]
+pub struct Incr {
+    key: String,
+}
+
+/// Decrements the number stored at `key` by one.
+#[derive(Debug)]
+pub struct Decr {
+    key: String,
+}
+
+/// Increments the number stored at `key` by `increment`.
+#[derive(Debug)]
+pub struct IncrBy {
+    key: String,
+    increment: i64,
+}
+
+/// Decrements the number stored at `key` by `decrement`.
+#[derive(Debug)]
+pub struct DecrBy {
+    key: String,
+    decrement: i64,
+}
+
+impl Incr {
+    /// Create a new `Incr` command for `key`.
+    pub fn new(key: impl ToString) -> Incr {
+        Incr { key: key.to_string() }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `Incr` instance from a received frame.
+    ///
+    /// The `INCR` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// INCR key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<Incr> {
+        Ok(Incr {
+            key: parse.next_string()?,
+        })
+    }
+
+    /// Apply the `Incr` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `Incr` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        incr_by(guard, self.key, 1)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("incr".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl Decr {
+    /// Create a new `Decr` command for `key`.
+    pub fn new(key: impl ToString) -> Decr {
+        Decr { key: key.to_string() }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `Decr` instance from a received frame.
+    ///
+    /// The `DECR` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// DECR key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<Decr> {
+        Ok(Decr {
+            key: parse.next_string()?,
+        })
+    }
+
+    /// Apply the `Decr` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `Decr` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        incr_by(guard, self.key, -1)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("decr".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl IncrBy {
+    /// Create a new `IncrBy` command which adds `increment` to `key`.
+    pub fn new(key: impl ToString, increment: i64) -> IncrBy {
+        IncrBy {
+            key: key.to_string(),
+            increment,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn increment(&self) -> i64 {
+        self.increment
+    }
+
+    /// Parse an `IncrBy` instance from a received frame.
+    ///
+    /// The `INCRBY` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// INCRBY key increment
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<IncrBy> {
+        let key = parse.next_string()?;
+        let increment = parse.next_int()?;
+
+        Ok(IncrBy { key, increment })
+    }
+
+    /// Apply the `IncrBy` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `IncrBy` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        incr_by(guard, self.key, self.increment)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("incrby".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.increment.to_string()));
+        frame
+    }
+}
+
+impl DecrBy {
+    /// Create a new `DecrBy` command which subtracts `decrement` from `key`.
+    pub fn new(key: impl ToString, decrement: i64) -> DecrBy {
+        DecrBy {
+            key: key.to_string(),
+            decrement,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn decrement(&self) -> i64 {
+        self.decrement
+    }
+
+    /// Parse a `DecrBy` instance from a received frame.
+    ///
+    /// The `DECRBY` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// DECRBY key decrement
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<DecrBy> {
+        let key = parse.next_string()?;
+        let decrement = parse.next_int()?;
+
+        Ok(DecrBy { key, decrement })
+    }
+
+    /// Apply the `DecrBy` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `DecrBy` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        match self.decrement.checked_neg() {
+            Some(delta) => incr_by(guard, self.key, delta),
+            None => Frame::Error("ERR decrement would overflow".to_string()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("decrby".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.decrement.to_string()));
+        frame
+    }
+}
+
+/// INCR 系列命令共用: 把 `key` 的值当作i64加上 `delta`, 返回新的值
+fn incr_by(guard: &mut Guard<'_>, key: String, delta: i64) -> Frame {
+    let current = match guard.get(&key) {
+        Ok(Some(value)) => match std::str::from_utf8(value).ok().and_then(|s| s.parse::<i64>().ok()) {
+            Some(current) => current,
+            None => return Frame::Error("ERR value is not an integer or out of range".to_string()),
+        },
+        Ok(None) => 0,
+        Err(err) => return err.into(),
+    };
+
+    match current.checked_add(delta) {
+        Some(new) => {
+            let value = Bytes::from(new.to_string());
+            // 记成执行之后的结果, 回放时不用再算一遍
+            guard.log(|| Set::new(&key, value.clone()).with_keep_ttl().into_frame());
+            guard.update(key, value);
+            Frame::Integer(new)
+        }
+        None => Frame::Error("ERR increment or decrement would overflow".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::minis_redis::db::Db;
+    use crate::minis_redis::frame::Frame;
+    use crate::minis_redis::test_util::run;
+
+    #[test]
+    fn incr_family() {
+        let db = Db::new(4);
+        run(&db, &["set", "k", "3"]);
+        assert_eq!(run(&db, &["incr", "k"]), Frame::Integer(4));
+        assert_eq!(run(&db, &["incrby", "k", "-10"]), Frame::Integer(-6));
+        // 不存在的key当作0
+        assert_eq!(run(&db, &["decrby", "n", "2"]), Frame::Integer(-2));
+        assert_eq!(run(&db, &["decr", "n"]), Frame::Integer(-3));
+
+        run(&db, &["set", "max", "9223372036854775807"]);
+        assert!(matches!(run(&db, &["incr", "max"]), Frame::Error(_)));
+        run(&db, &["set", "s", "abc"]);
+        assert!(matches!(run(&db, &["incr", "s"]), Frame::Error(_)));
+    }
+}
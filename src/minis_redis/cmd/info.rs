@@ -0,0 +1,63 @@
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::{Parse, ParseError};
+
+use bytes::Bytes;
+
+/// Return information and statistics about the server.
+///
+/// Without a section all sections are returned. Section names are case
+/// insensitive, unknown sections produce an empty report.
+#[derive(Debug, Default)]
+pub struct Info {
+    /// Requested section, lower case
+    section: Option<String>,
+}
+
+impl Info {
+    /// Create a new `Info` command returning `section` or everything.
+    pub fn new(section: Option<&str>) -> Info {
+        Info {
+            section: section.map(str::to_lowercase),
+        }
+    }
+
+    /// Get the requested section
+    pub fn section(&self) -> Option<&str> {
+        self.section.as_deref()
+    }
+
+    /// Returns true if the section `name` should be part of the report.
+    pub fn wants(&self, name: &str) -> bool {
+        match &self.section {
+            None => true,
+            Some(section) => section == "all" || section == "everything" || section == name,
+        }
+    }
+
+    /// Parse an `Info` instance from a received frame.
+    ///
+    /// The `INFO` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// INFO [section]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<Info> {
+        match parse.next_string() {
+            Ok(section) => Ok(Info::new(Some(&section))),
+            Err(ParseError::EndOfStream) => Ok(Info::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("info".as_bytes()));
+        if let Some(section) = self.section {
+            frame.push_bulk(Bytes::from(section.into_bytes()));
+        }
+        frame
+    }
+}
@@ -0,0 +1,59 @@
+use crate::minis_redis::db::{Db, Guard};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+
+use bytes::Bytes;
+
+/// Returns the length of the list stored at `key`, 0 if it does not exist.
+#[derive(Debug)]
+pub struct LLen {
+    key: String,
+}
+
+impl LLen {
+    /// Create a new `LLen` command for `key`.
+    pub fn new(key: impl ToString) -> LLen {
+        LLen { key: key.to_string() }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `LLen` instance from a received frame.
+    ///
+    /// The `LLEN` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// LLEN key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<LLen> {
+        let key = parse.next_string()?;
+
+        Ok(LLen { key })
+    }
+
+    /// Apply the `LLen` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `LLen` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        match guard.list(&self.key) {
+            Ok(list) => Frame::Integer(list.map_or(0, |list| list.len()) as i64),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("llen".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
@@ -0,0 +1,291 @@
+use crate::minis_redis::blocked::{Blocked, Outcome};
+use crate::minis_redis::cmd::bpop::parse_timeout;
+use crate::minis_redis::db::{Db, Guard, WrongType};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+
+use bytes::Bytes;
+use std::time::Duration;
+
+/// One end of a list, `LEFT` is the head and `RIGHT` the tail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum End {
+    Left,
+    Right,
+}
+
+/// Atomically pops an element from one end of the list stored at `source`
+/// and pushes it onto one end of the list stored at `destination`.
+///
+/// The reply is the moved element, or nil if `source` does not exist.
+/// `source` and `destination` may be the same key, which rotates the list.
+#[derive(Debug)]
+pub struct LMove {
+    source: String,
+    destination: String,
+    from: End,
+    to: End,
+}
+
+/// The blocking variant of `LMove`: when `source` is empty the connection
+/// waits until an element is pushed to it, or `timeout` elapses.
+///
+/// A timeout of zero blocks forever. The reply is nil on timeout.
+#[derive(Debug)]
+pub struct BLMove {
+    source: String,
+    destination: String,
+    from: End,
+    to: End,
+    timeout: Option<Duration>,
+}
+
+impl LMove {
+    /// Create a new `LMove` command which moves an element from `source` to
+    /// `destination`.
+    pub fn new(source: impl ToString, destination: impl ToString, from: End, to: End) -> LMove {
+        LMove {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            from,
+            to,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn destination(&self) -> &str {
+        &self.destination
+    }
+
+    /// Parse an `LMove` instance from a received frame.
+    ///
+    /// The `LMOVE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// LMOVE source destination LEFT|RIGHT LEFT|RIGHT
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<LMove> {
+        let source = parse.next_string()?;
+        let destination = parse.next_string()?;
+        let from = parse_end(parse)?;
+        let to = parse_end(parse)?;
+
+        Ok(LMove {
+            source,
+            destination,
+            from,
+            to,
+        })
+    }
+
+    /// Apply the `LMove` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.source, &self.destination]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `LMove` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        match move_element(guard, &self.source, &self.destination, self.from, self.to) {
+            Ok(Some(value)) => Frame::Bulk(value),
+            Ok(None) => Frame::Null,
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        into_frame("lmove", self.source, self.destination, self.from, self.to)
+    }
+}
+
+impl BLMove {
+    /// Create a new `BLMove` command which waits at most `timeout` for an
+    /// element to move, `None` waits forever.
+    pub fn new(
+        source: impl ToString,
+        destination: impl ToString,
+        from: End,
+        to: End,
+        timeout: Option<Duration>,
+    ) -> BLMove {
+        BLMove {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            from,
+            to,
+            timeout,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn destination(&self) -> &str {
+        &self.destination
+    }
+
+    /// Parse a `BLMove` instance from a received frame.
+    ///
+    /// The `BLMOVE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// BLMOVE source destination LEFT|RIGHT LEFT|RIGHT timeout
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<BLMove> {
+        let source = parse.next_string()?;
+        let destination = parse.next_string()?;
+        let from = parse_end(parse)?;
+        let to = parse_end(parse)?;
+        let timeout = parse_timeout(parse)?;
+
+        Ok(BLMove {
+            source,
+            destination,
+            from,
+            to,
+            timeout,
+        })
+    }
+
+    /// Apply the `BLMove` command to the specified `Db` instance.
+    ///
+    /// If `source` is empty the client is queued on it before the locks are
+    /// released, the returned `Blocked` waits for the element.
+    pub fn apply(self, db: &Db) -> Outcome {
+        let mut guard = db.lock([&self.source, &self.destination]);
+        match move_element(&mut guard, &self.source, &self.destination, self.from, self.to) {
+            Ok(Some(value)) => Outcome::Ready(Frame::Bulk(value)),
+            Ok(None) => Outcome::Blocked(Blocked::new(
+                &mut guard,
+                vec![self.source],
+                self.from,
+                Some((self.destination, self.to)),
+                self.timeout,
+            )),
+            Err(err) => Outcome::Ready(err.into()),
+        }
+    }
+
+    /// Apply the `BLMove` command while the shards of its keys are already
+    /// locked. Like `LMove`, the reply is nil right away if `source` is empty.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        LMove::new(self.source, self.destination, self.from, self.to).execute(guard)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let timeout = self.timeout.unwrap_or_default().as_secs_f64();
+        let mut frame = into_frame("blmove", self.source, self.destination, self.from, self.to);
+        frame.push_bulk(Bytes::from(timeout.to_string()));
+        frame
+    }
+}
+
+impl End {
+    fn as_str(self) -> &'static str {
+        match self {
+            End::Left => "left",
+            End::Right => "right",
+        }
+    }
+}
+
+/// 在已经持有的锁下把 `source` 一端的元素移到 `destination` 的一端, 记为LMOVE。
+///
+/// `source` 不存在时返回None, 两个key中有一个不是list时什么都不做
+pub(crate) fn move_element(
+    guard: &mut Guard<'_>,
+    source: &str,
+    destination: &str,
+    from: End,
+    to: End,
+) -> Result<Option<Bytes>, WrongType> {
+    guard.list(destination)?;
+    let list = match guard.list_mut(source)? {
+        Some(list) => list,
+        None => return Ok(None),
+    };
+    let value = match from {
+        End::Left => list.pop_front(),
+        End::Right => list.pop_back(),
+    }
+    .unwrap();
+
+    let list = guard.list_or_default(destination)?;
+    match to {
+        End::Left => list.push_front(value.clone()),
+        End::Right => list.push_back(value.clone()),
+    }
+    // 放进去之后再检查, source和destination是同一个key时list不会被删掉
+    if guard.list(source)?.is_some_and(|list| list.is_empty()) {
+        guard.remove(source);
+    }
+    guard.signal_ready(destination);
+
+    guard.log(|| into_frame("lmove", source.to_string(), destination.to_string(), from, to));
+    Ok(Some(value))
+}
+
+fn parse_end(parse: &mut Parse) -> crate::minis_redis::Result<End> {
+    let end = parse.next_string()?;
+    if end.eq_ignore_ascii_case("left") {
+        Ok(End::Left)
+    } else if end.eq_ignore_ascii_case("right") {
+        Ok(End::Right)
+    } else {
+        Err("syntax error".into())
+    }
+}
+
+fn into_frame(name: &'static str, source: String, destination: String, from: End, to: End) -> Frame {
+    let mut frame = Frame::array();
+    frame.push_bulk(Bytes::from(name.as_bytes()));
+    frame.push_bulk(Bytes::from(source.into_bytes()));
+    frame.push_bulk(Bytes::from(destination.into_bytes()));
+    frame.push_bulk(Bytes::from(from.as_str().as_bytes()));
+    frame.push_bulk(Bytes::from(to.as_str().as_bytes()));
+    frame
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::minis_redis::db::Db;
+    use crate::minis_redis::frame::Frame;
+    use crate::minis_redis::test_util::run;
+
+    #[test]
+    fn move_between_lists() {
+        let db = Db::new(4);
+        let bulk = |s: &'static str| Frame::Bulk(Bytes::from(s));
+        let array = |items: &[&'static str]| Frame::Array(items.iter().map(|s| bulk(s)).collect());
+
+        // 同一个key时是把list转一圈
+        run(&db, &["rpush", "m", "a", "b"]);
+        assert_eq!(run(&db, &["lmove", "m", "m", "left", "right"]), bulk("a"));
+        assert_eq!(run(&db, &["lrange", "m", "0", "-1"]), array(&["b", "a"]));
+        assert_eq!(run(&db, &["lmove", "m", "n", "RIGHT", "LEFT"]), bulk("a"));
+        assert_eq!(run(&db, &["lmove", "m", "n", "right", "left"]), bulk("b"));
+        assert_eq!(run(&db, &["lrange", "n", "0", "-1"]), array(&["b", "a"]));
+        assert_eq!(run(&db, &["exists", "m"]), Frame::Integer(0));
+        assert_eq!(run(&db, &["lmove", "m", "n", "left", "left"]), Frame::Null);
+
+        // 类型不对时什么都不移动
+        run(&db, &["set", "s", "v"]);
+        assert_eq!(
+            run(&db, &["lmove", "n", "s", "left", "left"]),
+            Frame::Error("WRONGTYPE Operation against a key holding the wrong kind of value".to_string())
+        );
+        assert_eq!(run(&db, &["llen", "n"]), Frame::Integer(2));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::minis_redis::db::{Db, Guard};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+
+use bytes::Bytes;
+
+/// Returns the elements of the list stored at `key` between `start` and
+/// `stop`, both inclusive.
+///
+/// Negative offsets count from the end of the list, `-1` is the last element.
+/// Offsets out of range are not an error: they are clamped to the list, and a
+/// `key` that does not exist is an empty list.
+#[derive(Debug)]
+pub struct LRange {
+    key: String,
+    start: i64,
+    stop: i64,
+}
+
+impl LRange {
+    /// Create a new `LRange` command returning `start..=stop` of `key`.
+    pub fn new(key: impl ToString, start: i64, stop: i64) -> LRange {
+        LRange {
+            key: key.to_string(),
+            start,
+            stop,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn start(&self) -> i64 {
+        self.start
+    }
+
+    pub fn stop(&self) -> i64 {
+        self.stop
+    }
+
+    /// Parse an `LRange` instance from a received frame.
+    ///
+    /// The `LRANGE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// LRANGE key start stop
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<LRange> {
+        let key = parse.next_string()?;
+        let start = parse.next_int()?;
+        let stop = parse.next_int()?;
+
+        Ok(LRange { key, start, stop })
+    }
+
+    /// Apply the `LRange` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `LRange` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        let list = match guard.list(&self.key) {
+            Ok(Some(list)) => list,
+            Ok(None) => return Frame::Array(vec![]),
+            Err(err) => return err.into(),
+        };
+
+        let Some((start, stop)) = index_range(self.start, self.stop, list.len()) else {
+            return Frame::Array(vec![]);
+        };
+        let items = list.range(start..=stop).map(|item| Frame::Bulk(item.clone())).collect();
+        Frame::Array(items)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("lrange".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.start.to_string()));
+        frame.push_bulk(Bytes::from(self.stop.to_string()));
+        frame
+    }
+}
+
+/// 把LRANGE、ZRANGE的 `start`、`stop` 换算成 `len` 个元素中的下标范围, 范围为空时返回None。
+///
+/// 负数从末尾开始数, 然后截到 `[0, len)` 的范围内
+pub(crate) fn index_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+    (start <= stop).then_some((start as usize, stop as usize))
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::minis_redis::db::Db;
+    use crate::minis_redis::frame::Frame;
+    use crate::minis_redis::test_util::run;
+
+    #[test]
+    fn ranges() {
+        let db = Db::new(4);
+        let array = |items: &[&'static str]| Frame::Array(items.iter().map(|s| Frame::Bulk(Bytes::from(*s))).collect());
+
+        run(&db, &["rpush", "l", "0", "a", "b", "c"]);
+        assert_eq!(run(&db, &["lrange", "l", "0", "-1"]), array(&["0", "a", "b", "c"]));
+        assert_eq!(run(&db, &["lrange", "l", "-3", "1"]), array(&["a"]));
+        assert_eq!(run(&db, &["lrange", "l", "2", "100"]), array(&["b", "c"]));
+        assert_eq!(run(&db, &["lrange", "l", "3", "1"]), array(&[]));
+        assert_eq!(run(&db, &["lrange", "missing", "0", "-1"]), array(&[]));
+
+        run(&db, &["set", "s", "v"]);
+        assert_eq!(
+            run(&db, &["lrange", "s", "0", "-1"]),
+            Frame::Error("WRONGTYPE Operation against a key holding the wrong kind of value".to_string())
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use crate::minis_redis::db::{Db, Guard};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+
+use bytes::Bytes;
+
+/// Returns the values of all the specified keys.
+///
+/// Nil is returned in place of every key that does not exist.
+#[derive(Debug)]
+pub struct MGet {
+    keys: Vec<String>,
+}
+
+impl MGet {
+    /// Create a new `MGet` command which fetches `keys`.
+    pub fn new(keys: &[String]) -> MGet {
+        MGet { keys: keys.to_vec() }
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse an `MGet` instance from a received frame.
+    ///
+    /// The `MGET` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// MGET key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<MGet> {
+        Ok(MGet {
+            keys: parse.next_strings()?,
+        })
+    }
+
+    /// Apply the `MGet` command to the specified `Db` instance.
+    ///
+    /// The values are read under the locks of all the shards involved, so the
+    /// reply is a consistent view even when the keys live on several shards.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock(&self.keys);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `MGet` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        let values = self
+            .keys
+            .iter()
+            .map(|key| match guard.get(key) {
+                Ok(Some(value)) => Frame::Bulk(value.clone()),
+                // 和redis一样, 不是string的key当作不存在, 不报错
+                _ => Frame::Null,
+            })
+            .collect();
+        Frame::Array(values)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("mget".as_bytes()));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key.into_bytes()));
+        }
+        frame
+    }
+}
@@ -0,0 +1,816 @@
+mod append;
+pub use append::Append;
+
+mod bpop;
+pub use bpop::{BLPop, BRPop};
+
+mod client;
+pub use client::Client;
+
+mod del;
+pub use del::Del;
+
+mod exists;
+pub use exists::Exists;
+
+mod expire;
+pub use expire::{Expire, PExpire, PExpireAt};
+
+mod get;
+pub use get::Get;
+
+mod getset;
+pub use getset::GetSet;
+
+mod hash;
+pub use hash::{HDel, HGet, HGetAll, HIncrBy, HSet};
+
+mod hello;
+pub use hello::Hello;
+
+mod incr;
+pub use incr::{Decr, DecrBy, Incr, IncrBy};
+
+mod info;
+pub use info::Info;
+
+mod llen;
+pub use llen::LLen;
+
+mod lmove;
+pub(crate) use lmove::move_element;
+pub use lmove::{BLMove, End, LMove};
+
+mod lrange;
+pub use lrange::LRange;
+
+mod mget;
+pub use mget::MGet;
+
+mod mset;
+pub use mset::MSet;
+
+mod multi;
+pub use multi::{Discard, Exec, Multi};
+
+mod persist;
+pub use persist::Persist;
+
+mod ping;
+pub use ping::Ping;
+
+mod pop;
+pub(crate) use pop::pop_one;
+pub use pop::{LPop, RPop};
+
+mod publish;
+pub use publish::Publish;
+
+mod push;
+pub use push::{LPush, RPush};
+
+mod save;
+pub use save::{BgSave, Save};
+
+mod set;
+pub use set::{Set, SetCondition};
+
+mod setnx;
+pub use setnx::SetNx;
+
+mod sets;
+pub use sets::{SAdd, SInter, SMembers, SRem, SUnion};
+
+mod strlen;
+pub use strlen::Strlen;
+
+mod subscribe;
+pub use subscribe::{PSubscribe, PUnsubscribe, Subscribe, Unsubscribe};
+
+mod ttl;
+pub use ttl::{PTtl, Ttl};
+
+mod unknown;
+pub use unknown::Unknown;
+
+mod watch;
+pub use watch::{Unwatch, Watch};
+
+mod zset;
+pub use zset::{ZAdd, ZIncrBy, ZRange, ZRangeByScore, ZRank};
+
+use bytes::Bytes;
+use std::fmt;
+
+use super::db::Guard;
+use super::frame::Frame;
+use super::parse::{Parse, ParseError};
+
+/// Runs the commands that do not access any key but need the state of the
+/// connection or the server, such as `PUBLISH`, `INFO` and `CLIENT`.
+///
+/// The server implements it so that these commands can be queued in a
+/// transaction, `Command::execute` hands them over when EXEC runs.
+pub trait Session {
+    /// Apply a keyless command, `cmd.keys()` is `Some` and empty.
+    fn apply(&mut self, cmd: Command) -> Frame;
+}
+
+/// Enumeration of supported Redis commands.
+#[derive(Debug)]
+pub enum Command {
+    Append(Append),
+    BgSave(BgSave),
+    BLMove(BLMove),
+    BLPop(BLPop),
+    BRPop(BRPop),
+    Client(Client),
+    Decr(Decr),
+    DecrBy(DecrBy),
+    Del(Del),
+    Discard(Discard),
+    Exec(Exec),
+    Exists(Exists),
+    Expire(Expire),
+    Get(Get),
+    GetSet(GetSet),
+    HDel(HDel),
+    Hello(Hello),
+    HGet(HGet),
+    HGetAll(HGetAll),
+    HIncrBy(HIncrBy),
+    HSet(HSet),
+    Incr(Incr),
+    IncrBy(IncrBy),
+    Info(Info),
+    LLen(LLen),
+    LMove(LMove),
+    LPop(LPop),
+    LPush(LPush),
+    LRange(LRange),
+    MGet(MGet),
+    MSet(MSet),
+    Multi(Multi),
+    PExpire(PExpire),
+    PExpireAt(PExpireAt),
+    Persist(Persist),
+    Ping(Ping),
+    PTtl(PTtl),
+    PSubscribe(PSubscribe),
+    Publish(Publish),
+    PUnsubscribe(PUnsubscribe),
+    RPop(RPop),
+    RPush(RPush),
+    SAdd(SAdd),
+    Save(Save),
+    Set(Set),
+    SetNx(SetNx),
+    SInter(SInter),
+    SMembers(SMembers),
+    SRem(SRem),
+    Strlen(Strlen),
+    Subscribe(Subscribe),
+    SUnion(SUnion),
+    Ttl(Ttl),
+    Unsubscribe(Unsubscribe),
+    Unwatch(Unwatch),
+    Watch(Watch),
+    ZAdd(ZAdd),
+    ZIncrBy(ZIncrBy),
+    ZRange(ZRange),
+    ZRangeByScore(ZRangeByScore),
+    ZRank(ZRank),
+    Unknown(Unknown),
+}
+
+impl Command {
+    /// Parse a command from a received frame.
+    ///
+    /// The `Frame` must represent a Redis command supported by `minis_redis`
+    /// and be the array variant.
+    ///
+    /// # Returns
+    ///
+    /// On success, the command value is returned, otherwise, `Err` is returned.
+    pub fn from_frame(frame: Frame) -> Result<Command, CommandError> {
+        // The frame value is decorated with `Parse`. `Parse` provides a
+        // "cursor" like API which makes parsing the command easier.
+        let mut parse = Parse::new(frame).map_err(|err| CommandError::Protocol(err.to_string()))?;
+
+        // All redis commands begin with the command name as a string. The name
+        // is read and converted to lower cases in order to do case sensitive
+        // matching.
+        let command_name = match parse.next_string() {
+            Ok(name) => name.to_lowercase(),
+            Err(ParseError::EndOfStream) => return Err(CommandError::Protocol("empty command".to_string())),
+            Err(err) => return Err(CommandError::Protocol(err.to_string())),
+        };
+
+        Command::parse_args(&command_name, &mut parse).map_err(|err| match err.downcast::<ParseError>() {
+            // 参数不够
+            Ok(err) if matches!(*err, ParseError::EndOfStream) => CommandError::WrongArity(command_name),
+            Ok(err) => CommandError::InvalidArgument(err.to_string()),
+            Err(err) => CommandError::InvalidArgument(err.to_string()),
+        })
+    }
+
+    /// Parse the arguments of the command `command_name`, the name has already
+    /// been consumed.
+    fn parse_args(command_name: &str, parse: &mut Parse) -> crate::minis_redis::Result<Command> {
+        let command = match command_name {
+            "append" => Command::Append(Append::parse_frames(parse)?),
+            "bgsave" => Command::BgSave(BgSave::parse_frames(parse)?),
+            "blmove" => Command::BLMove(BLMove::parse_frames(parse)?),
+            "blpop" => Command::BLPop(BLPop::parse_frames(parse)?),
+            "brpop" => Command::BRPop(BRPop::parse_frames(parse)?),
+            "client" => Command::Client(Client::parse_frames(parse)?),
+            "decr" => Command::Decr(Decr::parse_frames(parse)?),
+            "decrby" => Command::DecrBy(DecrBy::parse_frames(parse)?),
+            "del" => Command::Del(Del::parse_frames(parse)?),
+            "discard" => Command::Discard(Discard::parse_frames(parse)?),
+            "exec" => Command::Exec(Exec::parse_frames(parse)?),
+            "exists" => Command::Exists(Exists::parse_frames(parse)?),
+            "expire" => Command::Expire(Expire::parse_frames(parse)?),
+            "get" => Command::Get(Get::parse_frames(parse)?),
+            "getset" => Command::GetSet(GetSet::parse_frames(parse)?),
+            "hdel" => Command::HDel(HDel::parse_frames(parse)?),
+            "hello" => Command::Hello(Hello::parse_frames(parse)?),
+            "hget" => Command::HGet(HGet::parse_frames(parse)?),
+            "hgetall" => Command::HGetAll(HGetAll::parse_frames(parse)?),
+            "hincrby" => Command::HIncrBy(HIncrBy::parse_frames(parse)?),
+            "hset" => Command::HSet(HSet::parse_frames(parse)?),
+            "incr" => Command::Incr(Incr::parse_frames(parse)?),
+            "incrby" => Command::IncrBy(IncrBy::parse_frames(parse)?),
+            "info" => Command::Info(Info::parse_frames(parse)?),
+            "llen" => Command::LLen(LLen::parse_frames(parse)?),
+            "lmove" => Command::LMove(LMove::parse_frames(parse)?),
+            "lpop" => Command::LPop(LPop::parse_frames(parse)?),
+            "lpush" => Command::LPush(LPush::parse_frames(parse)?),
+            "lrange" => Command::LRange(LRange::parse_frames(parse)?),
+            "mget" => Command::MGet(MGet::parse_frames(parse)?),
+            "mset" => Command::MSet(MSet::parse_frames(parse)?),
+            "multi" => Command::Multi(Multi::parse_frames(parse)?),
+            "pexpire" => Command::PExpire(PExpire::parse_frames(parse)?),
+            "pexpireat" => Command::PExpireAt(PExpireAt::parse_frames(parse)?),
+            "persist" => Command::Persist(Persist::parse_frames(parse)?),
+            "ping" => Command::Ping(Ping::parse_frames(parse)?),
+            "pttl" => Command::PTtl(PTtl::parse_frames(parse)?),
+            "psubscribe" => Command::PSubscribe(PSubscribe::parse_frames(parse)?),
+            "publish" => Command::Publish(Publish::parse_frames(parse)?),
+            "punsubscribe" => Command::PUnsubscribe(PUnsubscribe::parse_frames(parse)?),
+            "rpop" => Command::RPop(RPop::parse_frames(parse)?),
+            "rpush" => Command::RPush(RPush::parse_frames(parse)?),
+            "sadd" => Command::SAdd(SAdd::parse_frames(parse)?),
+            "save" => Command::Save(Save::parse_frames(parse)?),
+            "set" => Command::Set(Set::parse_frames(parse)?),
+            "setnx" => Command::SetNx(SetNx::parse_frames(parse)?),
+            "sinter" => Command::SInter(SInter::parse_frames(parse)?),
+            "smembers" => Command::SMembers(SMembers::parse_frames(parse)?),
+            "srem" => Command::SRem(SRem::parse_frames(parse)?),
+            "strlen" => Command::Strlen(Strlen::parse_frames(parse)?),
+            "subscribe" => Command::Subscribe(Subscribe::parse_frames(parse)?),
+            "sunion" => Command::SUnion(SUnion::parse_frames(parse)?),
+            "ttl" => Command::Ttl(Ttl::parse_frames(parse)?),
+            "unsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(parse)?),
+            "unwatch" => Command::Unwatch(Unwatch::parse_frames(parse)?),
+            "watch" => Command::Watch(Watch::parse_frames(parse)?),
+            "zadd" => Command::ZAdd(ZAdd::parse_frames(parse)?),
+            "zincrby" => Command::ZIncrBy(ZIncrBy::parse_frames(parse)?),
+            "zrange" => Command::ZRange(ZRange::parse_frames(parse)?),
+            "zrangebyscore" => Command::ZRangeByScore(ZRangeByScore::parse_frames(parse)?),
+            "zrank" => Command::ZRank(ZRank::parse_frames(parse)?),
+            _ => {
+                // The command is not recognized and an Unknown command is
+                // returned.
+                //
+                // `return` is called here to skip the `finish()` call below. As
+                // the command is not recognized, there is most likely
+                // unconsumed fields remaining in the `Parse` instance.
+                return Ok(Command::Unknown(Unknown::new(command_name)));
+            }
+        };
+
+        // Check if there is any remaining unconsumed fields in the `Parse`
+        // value. If fields remain, this indicates an unexpected frame format
+        // and an error is returned. 多了参数和少了参数一样, `from_frame`会把
+        // `EndOfStream`转成参数个数不对的错误
+        if parse.finish().is_err() {
+            return Err(ParseError::EndOfStream.into());
+        }
+
+        Ok(command)
+    }
+
+    /// Converts the command into an equivalent `Frame`, the inverse of
+    /// `from_frame`.
+    ///
+    /// `Unknown` only remembers the command name, so its arguments are lost.
+    pub fn into_frame(self) -> Frame {
+        match self {
+            Command::Append(cmd) => cmd.into_frame(),
+            Command::BgSave(cmd) => cmd.into_frame(),
+            Command::BLMove(cmd) => cmd.into_frame(),
+            Command::BLPop(cmd) => cmd.into_frame(),
+            Command::BRPop(cmd) => cmd.into_frame(),
+            Command::Client(cmd) => cmd.into_frame(),
+            Command::Decr(cmd) => cmd.into_frame(),
+            Command::DecrBy(cmd) => cmd.into_frame(),
+            Command::Del(cmd) => cmd.into_frame(),
+            Command::Discard(cmd) => cmd.into_frame(),
+            Command::Exec(cmd) => cmd.into_frame(),
+            Command::Exists(cmd) => cmd.into_frame(),
+            Command::Expire(cmd) => cmd.into_frame(),
+            Command::Get(cmd) => cmd.into_frame(),
+            Command::GetSet(cmd) => cmd.into_frame(),
+            Command::HDel(cmd) => cmd.into_frame(),
+            Command::Hello(cmd) => cmd.into_frame(),
+            Command::HGet(cmd) => cmd.into_frame(),
+            Command::HGetAll(cmd) => cmd.into_frame(),
+            Command::HIncrBy(cmd) => cmd.into_frame(),
+            Command::HSet(cmd) => cmd.into_frame(),
+            Command::Incr(cmd) => cmd.into_frame(),
+            Command::IncrBy(cmd) => cmd.into_frame(),
+            Command::Info(cmd) => cmd.into_frame(),
+            Command::LLen(cmd) => cmd.into_frame(),
+            Command::LMove(cmd) => cmd.into_frame(),
+            Command::LPop(cmd) => cmd.into_frame(),
+            Command::LPush(cmd) => cmd.into_frame(),
+            Command::LRange(cmd) => cmd.into_frame(),
+            Command::MGet(cmd) => cmd.into_frame(),
+            Command::MSet(cmd) => cmd.into_frame(),
+            Command::Multi(cmd) => cmd.into_frame(),
+            Command::PExpire(cmd) => cmd.into_frame(),
+            Command::PExpireAt(cmd) => cmd.into_frame(),
+            Command::Persist(cmd) => cmd.into_frame(),
+            Command::Ping(cmd) => cmd.into_frame(),
+            Command::PTtl(cmd) => cmd.into_frame(),
+            Command::PSubscribe(cmd) => cmd.into_frame(),
+            Command::Publish(cmd) => cmd.into_frame(),
+            Command::PUnsubscribe(cmd) => cmd.into_frame(),
+            Command::RPop(cmd) => cmd.into_frame(),
+            Command::RPush(cmd) => cmd.into_frame(),
+            Command::SAdd(cmd) => cmd.into_frame(),
+            Command::Save(cmd) => cmd.into_frame(),
+            Command::Set(cmd) => cmd.into_frame(),
+            Command::SetNx(cmd) => cmd.into_frame(),
+            Command::SInter(cmd) => cmd.into_frame(),
+            Command::SMembers(cmd) => cmd.into_frame(),
+            Command::SRem(cmd) => cmd.into_frame(),
+            Command::Strlen(cmd) => cmd.into_frame(),
+            Command::Subscribe(cmd) => cmd.into_frame(),
+            Command::SUnion(cmd) => cmd.into_frame(),
+            Command::Ttl(cmd) => cmd.into_frame(),
+            Command::Unsubscribe(cmd) => cmd.into_frame(),
+            Command::Unwatch(cmd) => cmd.into_frame(),
+            Command::Watch(cmd) => cmd.into_frame(),
+            Command::ZAdd(cmd) => cmd.into_frame(),
+            Command::ZIncrBy(cmd) => cmd.into_frame(),
+            Command::ZRange(cmd) => cmd.into_frame(),
+            Command::ZRangeByScore(cmd) => cmd.into_frame(),
+            Command::ZRank(cmd) => cmd.into_frame(),
+            Command::Unknown(cmd) => {
+                let mut frame = Frame::array();
+                frame.push_bulk(Bytes::from(cmd.get_name().to_string()));
+                frame
+            }
+        }
+    }
+
+    /// Returns the command name
+    pub fn get_name(&self) -> &str {
+        match self {
+            Command::Append(_) => "append",
+            Command::BgSave(_) => "bgsave",
+            Command::BLMove(_) => "blmove",
+            Command::BLPop(_) => "blpop",
+            Command::BRPop(_) => "brpop",
+            Command::Client(_) => "client",
+            Command::Decr(_) => "decr",
+            Command::DecrBy(_) => "decrby",
+            Command::Del(_) => "del",
+            Command::Discard(_) => "discard",
+            Command::Exec(_) => "exec",
+            Command::Exists(_) => "exists",
+            Command::Expire(_) => "expire",
+            Command::Get(_) => "get",
+            Command::GetSet(_) => "getset",
+            Command::HDel(_) => "hdel",
+            Command::Hello(_) => "hello",
+            Command::HGet(_) => "hget",
+            Command::HGetAll(_) => "hgetall",
+            Command::HIncrBy(_) => "hincrby",
+            Command::HSet(_) => "hset",
+            Command::Incr(_) => "incr",
+            Command::IncrBy(_) => "incrby",
+            Command::Info(_) => "info",
+            Command::LLen(_) => "llen",
+            Command::LMove(_) => "lmove",
+            Command::LPop(_) => "lpop",
+            Command::LPush(_) => "lpush",
+            Command::LRange(_) => "lrange",
+            Command::MGet(_) => "mget",
+            Command::MSet(_) => "mset",
+            Command::Multi(_) => "multi",
+            Command::PExpire(_) => "pexpire",
+            Command::PExpireAt(_) => "pexpireat",
+            Command::Persist(_) => "persist",
+            Command::Ping(_) => "ping",
+            Command::PTtl(_) => "pttl",
+            Command::PSubscribe(_) => "psubscribe",
+            Command::Publish(_) => "publish",
+            Command::PUnsubscribe(_) => "punsubscribe",
+            Command::RPop(_) => "rpop",
+            Command::RPush(_) => "rpush",
+            Command::SAdd(_) => "sadd",
+            Command::Save(_) => "save",
+            Command::Set(_) => "set",
+            Command::SetNx(_) => "setnx",
+            Command::SInter(_) => "sinter",
+            Command::SMembers(_) => "smembers",
+            Command::SRem(_) => "srem",
+            Command::Strlen(_) => "strlen",
+            Command::Subscribe(_) => "subscribe",
+            Command::SUnion(_) => "sunion",
+            Command::Ttl(_) => "ttl",
+            Command::Unsubscribe(_) => "unsubscribe",
+            Command::Unwatch(_) => "unwatch",
+            Command::Watch(_) => "watch",
+            Command::ZAdd(_) => "zadd",
+            Command::ZIncrBy(_) => "zincrby",
+            Command::ZRange(_) => "zrange",
+            Command::ZRangeByScore(_) => "zrangebyscore",
+            Command::ZRank(_) => "zrank",
+            Command::Unknown(cmd) => cmd.get_name(),
+        }
+    }
+
+    /// The keys the command accesses, EXEC locks their shards before running
+    /// the queued commands.
+    ///
+    /// Commands that access no key return an empty list. Returns `None` for
+    /// the commands that can not be queued in a transaction: `SUBSCRIBE` and
+    /// `PSUBSCRIBE` switch the connection to subscribed mode, the transaction
+    /// commands themselves, and unknown commands.
+    pub fn keys(&self) -> Option<Vec<&str>> {
+        let keys = match self {
+            Command::Append(cmd) => vec![cmd.key()],
+            Command::BLMove(cmd) => vec![cmd.source(), cmd.destination()],
+            Command::BLPop(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Command::BRPop(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Command::Decr(cmd) => vec![cmd.key()],
+            Command::DecrBy(cmd) => vec![cmd.key()],
+            Command::Del(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Command::Exists(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Command::Expire(cmd) => vec![cmd.key()],
+            Command::Get(cmd) => vec![cmd.key()],
+            Command::GetSet(cmd) => vec![cmd.key()],
+            Command::HDel(cmd) => vec![cmd.key()],
+            Command::HGet(cmd) => vec![cmd.key()],
+            Command::HGetAll(cmd) => vec![cmd.key()],
+            Command::HIncrBy(cmd) => vec![cmd.key()],
+            Command::HSet(cmd) => vec![cmd.key()],
+            Command::Incr(cmd) => vec![cmd.key()],
+            Command::IncrBy(cmd) => vec![cmd.key()],
+            Command::LLen(cmd) => vec![cmd.key()],
+            Command::LMove(cmd) => vec![cmd.source(), cmd.destination()],
+            Command::LPop(cmd) => vec![cmd.key()],
+            Command::LPush(cmd) => vec![cmd.key()],
+            Command::LRange(cmd) => vec![cmd.key()],
+            Command::MGet(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Command::MSet(cmd) => cmd.pairs().iter().map(|(key, _)| key.as_str()).collect(),
+            Command::Persist(cmd) => vec![cmd.key()],
+            Command::PExpire(cmd) => vec![cmd.key()],
+            Command::PExpireAt(cmd) => vec![cmd.key()],
+            Command::PTtl(cmd) => vec![cmd.key()],
+            Command::RPop(cmd) => vec![cmd.key()],
+            Command::RPush(cmd) => vec![cmd.key()],
+            Command::SAdd(cmd) => vec![cmd.key()],
+            Command::Set(cmd) => vec![cmd.key()],
+            Command::SetNx(cmd) => vec![cmd.key()],
+            Command::SInter(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Command::SMembers(cmd) => vec![cmd.key()],
+            Command::SRem(cmd) => vec![cmd.key()],
+            Command::Strlen(cmd) => vec![cmd.key()],
+            Command::SUnion(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Command::Ttl(cmd) => vec![cmd.key()],
+            Command::ZAdd(cmd) => vec![cmd.key()],
+            Command::ZIncrBy(cmd) => vec![cmd.key()],
+            Command::ZRange(cmd) => vec![cmd.key()],
+            Command::ZRangeByScore(cmd) => vec![cmd.key()],
+            Command::ZRank(cmd) => vec![cmd.key()],
+            Command::BgSave(_)
+            | Command::Client(_)
+            | Command::Hello(_)
+            | Command::Info(_)
+            | Command::Ping(_)
+            | Command::Publish(_)
+            | Command::PUnsubscribe(_)
+            | Command::Save(_)
+            | Command::Unsubscribe(_)
+            | Command::Unwatch(_) => vec![],
+            Command::Discard(_)
+            | Command::Exec(_)
+            | Command::Multi(_)
+            | Command::PSubscribe(_)
+            | Command::Subscribe(_)
+            | Command::Unknown(_)
+            | Command::Watch(_) => return None,
+        };
+        Some(keys)
+    }
+
+    /// Execute a command queued in a transaction, the shards of its `keys()`
+    /// are already locked by `guard`. Blocking commands do not block here,
+    /// keyless commands are handed over to `session`.
+    ///
+    /// `SAVE` needs all the shards, `Transaction::exec` runs it itself.
+    /// Commands whose `keys()` is `None` reply with an error.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>, session: &mut dyn Session) -> Frame {
+        match self {
+            Command::Append(cmd) => cmd.execute(guard),
+            Command::BLMove(cmd) => cmd.execute(guard),
+            Command::BLPop(cmd) => cmd.execute(guard),
+            Command::BRPop(cmd) => cmd.execute(guard),
+            Command::Decr(cmd) => cmd.execute(guard),
+            Command::DecrBy(cmd) => cmd.execute(guard),
+            Command::Del(cmd) => cmd.execute(guard),
+            Command::Exists(cmd) => cmd.execute(guard),
+            Command::Expire(cmd) => cmd.execute(guard),
+            Command::Get(cmd) => cmd.execute(guard),
+            Command::GetSet(cmd) => cmd.execute(guard),
+            Command::HDel(cmd) => cmd.execute(guard),
+            Command::HGet(cmd) => cmd.execute(guard),
+            Command::HGetAll(cmd) => cmd.execute(guard),
+            Command::HIncrBy(cmd) => cmd.execute(guard),
+            Command::HSet(cmd) => cmd.execute(guard),
+            Command::Incr(cmd) => cmd.execute(guard),
+            Command::IncrBy(cmd) => cmd.execute(guard),
+            Command::LLen(cmd) => cmd.execute(guard),
+            Command::LMove(cmd) => cmd.execute(guard),
+            Command::LPop(cmd) => cmd.execute(guard),
+            Command::LPush(cmd) => cmd.execute(guard),
+            Command::LRange(cmd) => cmd.execute(guard),
+            Command::MGet(cmd) => cmd.execute(guard),
+            Command::MSet(cmd) => cmd.execute(guard),
+            Command::Persist(cmd) => cmd.execute(guard),
+            Command::PExpire(cmd) => cmd.execute(guard),
+            Command::PExpireAt(cmd) => cmd.execute(guard),
+            Command::PTtl(cmd) => cmd.execute(guard),
+            Command::RPop(cmd) => cmd.execute(guard),
+            Command::RPush(cmd) => cmd.execute(guard),
+            Command::SAdd(cmd) => cmd.execute(guard),
+            Command::Set(cmd) => cmd.execute(guard),
+            Command::SetNx(cmd) => cmd.execute(guard),
+            Command::SInter(cmd) => cmd.execute(guard),
+            Command::SMembers(cmd) => cmd.execute(guard),
+            Command::SRem(cmd) => cmd.execute(guard),
+            Command::Strlen(cmd) => cmd.execute(guard),
+            Command::SUnion(cmd) => cmd.execute(guard),
+            Command::Ttl(cmd) => cmd.execute(guard),
+            Command::ZAdd(cmd) => cmd.execute(guard),
+            Command::ZIncrBy(cmd) => cmd.execute(guard),
+            Command::ZRange(cmd) => cmd.execute(guard),
+            Command::ZRangeByScore(cmd) => cmd.execute(guard),
+            Command::ZRank(cmd) => cmd.execute(guard),
+            Command::Ping(cmd) => cmd.response(),
+            // EXEC之后本来就会取消所有的WATCH
+            Command::Unwatch(_) => Frame::Simple("OK".to_string()),
+            cmd @ (Command::BgSave(_)
+            | Command::Client(_)
+            | Command::Hello(_)
+            | Command::Info(_)
+            | Command::Publish(_)
+            | Command::PUnsubscribe(_)
+            | Command::Unsubscribe(_)) => session.apply(cmd),
+            cmd => Frame::Error(format!("ERR '{}' can not be executed in a transaction", cmd.get_name())),
+        }
+    }
+}
+
+/// Error returned by `Command::from_frame`.
+///
+/// Each kind has a Redis error code, `Display` gives the whole error reply,
+/// code included, so the server sends it as is.
+#[derive(Debug)]
+pub enum CommandError {
+    /// The frame is not a command, either not an array or empty.
+    Protocol(String),
+    /// Missing or extra arguments, holds the command name.
+    WrongArity(String),
+    /// An argument has the wrong format or value, e.g. `syntax error`.
+    InvalidArgument(String),
+}
+
+impl CommandError {
+    /// The error code the reply starts with.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CommandError::Protocol(_) | CommandError::WrongArity(_) | CommandError::InvalidArgument(_) => "ERR",
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Protocol(msg) | CommandError::InvalidArgument(msg) => write!(f, "{} {}", self.code(), msg),
+            CommandError::WrongArity(name) => {
+                write!(f, "{} wrong number of arguments for '{}' command", self.code(), name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use super::{
+        Append, BLMove, BLPop, BRPop, BgSave, Client, End, Command, Decr, DecrBy, Del, Discard, Exec, Exists, Expire, Get, GetSet, HDel, HGet, HGetAll, HIncrBy, HSet,
+        Hello, Incr, IncrBy, Info, LLen, LMove, LPop, LPush, LRange, MGet, MSet, Multi, RPop, RPush, SAdd, SInter, SMembers, SRem, SUnion,
+        PExpire, PExpireAt, PSubscribe, PTtl, PUnsubscribe, Persist, Ping, Publish, Save, Set, SetCondition, SetNx, Strlen, Subscribe, Ttl, Unsubscribe,
+        Unwatch, Watch, ZAdd, ZIncrBy, ZRange, ZRangeByScore, ZRank,
+    };
+    use std::ops::Bound;
+    use std::time::Duration;
+    use crate::minis_redis::aof::{self, FsyncPolicy};
+    use crate::minis_redis::db::Db;
+    use crate::minis_redis::frame::Frame;
+    use crate::minis_redis::test_util::{command, run, sorted, TempAof};
+
+    #[test]
+    fn round_trip() {
+        let channels = vec!["a".to_string(), "b".to_string()];
+        let frames = vec![
+            Get::new("k").into_frame(),
+            Set::new("k", Bytes::from("v")).into_frame(),
+            Set::new("k", Bytes::from("v"))
+                .with_condition(SetCondition::NotExists)
+                .with_get()
+                .into_frame(),
+            Set::new("k", Bytes::from("v")).with_condition(SetCondition::Exists).into_frame(),
+            SetNx::new("k", Bytes::from("v")).into_frame(),
+            GetSet::new("k", Bytes::from("v")).into_frame(),
+            Append::new("k", Bytes::from("v")).into_frame(),
+            Strlen::new("k").into_frame(),
+            Del::new(&channels).into_frame(),
+            Exists::new(&channels).into_frame(),
+            MGet::new(&channels).into_frame(),
+            MSet::new(&[("a".to_string(), Bytes::from("1")), ("b".to_string(), Bytes::from("2"))]).into_frame(),
+            Incr::new("n").into_frame(),
+            Decr::new("n").into_frame(),
+            IncrBy::new("n", -5).into_frame(),
+            DecrBy::new("n", 5).into_frame(),
+            Set::new("k", Bytes::from("v")).with_expire(Duration::from_millis(1500)).into_frame(),
+            Set::new("k", Bytes::from("v")).with_keep_ttl().into_frame(),
+            Expire::new("k", 10).into_frame(),
+            PExpire::new("k", -1).into_frame(),
+            PExpireAt::new("k", 1_700_000_000_000).into_frame(),
+            Ttl::new("k").into_frame(),
+            PTtl::new("k").into_frame(),
+            Persist::new("k").into_frame(),
+            LPush::new("l", &[Bytes::from("a"), Bytes::from("b")]).into_frame(),
+            RPush::new("l", &[Bytes::from("c")]).into_frame(),
+            LPop::new("l", None).into_frame(),
+            RPop::new("l", Some(2)).into_frame(),
+            LRange::new("l", 0, -1).into_frame(),
+            LLen::new("l").into_frame(),
+            LMove::new("l", "m", End::Left, End::Right).into_frame(),
+            BLMove::new("l", "m", End::Right, End::Left, Some(Duration::from_millis(1500))).into_frame(),
+            BLPop::new(&channels, None).into_frame(),
+            BRPop::new(&channels, Some(Duration::from_secs(2))).into_frame(),
+            HSet::new("h", &[(Bytes::from("f"), Bytes::from("1")), (Bytes::from("g"), Bytes::from("2"))]).into_frame(),
+            HGet::new("h", Bytes::from("f")).into_frame(),
+            HGetAll::new("h").into_frame(),
+            HDel::new("h", &[Bytes::from("f")]).into_frame(),
+            HIncrBy::new("h", Bytes::from("f"), -3).into_frame(),
+            SAdd::new("s", &[Bytes::from("a"), Bytes::from("b")]).into_frame(),
+            SRem::new("s", &[Bytes::from("a")]).into_frame(),
+            SMembers::new("s").into_frame(),
+            SInter::new(&channels).into_frame(),
+            SUnion::new(&channels).into_frame(),
+            ZAdd::new("z", &[(1.5, Bytes::from("a")), (f64::NEG_INFINITY, Bytes::from("b"))]).into_frame(),
+            ZRange::new("z", 0, -1, false).into_frame(),
+            ZRange::new("z", 1, 2, true).into_frame(),
+            ZRangeByScore::new("z", Bound::Excluded(1.0), Bound::Included(f64::INFINITY), true).into_frame(),
+            ZRank::new("z", Bytes::from("a")).into_frame(),
+            ZIncrBy::new("z", 2.5, Bytes::from("a")).into_frame(),
+            Multi::new().into_frame(),
+            Exec::new().into_frame(),
+            Discard::new().into_frame(),
+            Watch::new(&channels).into_frame(),
+            Unwatch::new().into_frame(),
+            Save::new().into_frame(),
+            BgSave::new().into_frame(),
+            Hello::new(Some(3)).into_frame(),
+            Hello::new(None).into_frame(),
+            Client::List.into_frame(),
+            Client::Id.into_frame(),
+            Info::new(Some("stats")).into_frame(),
+            Info::new(None).into_frame(),
+            Ping::new(None).into_frame(),
+            Ping::new(Some(Bytes::from("hi"))).into_frame(),
+            Publish::new("chan", Bytes::from("msg")).into_frame(),
+            Subscribe::new(&channels).into_frame(),
+            Unsubscribe::new(&[]).into_frame(),
+            PSubscribe::new(&channels).into_frame(),
+            PUnsubscribe::new(&channels).into_frame(),
+            PUnsubscribe::new(&[]).into_frame(),
+        ];
+
+        for frame in frames {
+            let cmd = Command::from_frame(frame.clone()).unwrap();
+            assert_eq!(cmd.into_frame(), frame);
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        // 命令名大小写不敏感, 多余的参数和缺少的参数都是错误
+        assert_eq!(Command::from_frame(command(&["GeT", "k"])).unwrap().get_name(), "get");
+        let err = Command::from_frame(command(&["get", "k", "extra"])).unwrap_err();
+        assert_eq!(err.to_string(), "ERR wrong number of arguments for 'get' command");
+        let err = Command::from_frame(command(&["SET", "k"])).unwrap_err();
+        assert_eq!(err.to_string(), "ERR wrong number of arguments for 'set' command");
+        assert!(Command::from_frame(command(&["set", "k"])).is_err());
+        assert!(Command::from_frame(command(&["subscribe"])).is_err());
+        assert!(Command::from_frame(command(&["psubscribe"])).is_err());
+        assert!(Command::from_frame(command(&["del"])).is_err());
+        assert!(Command::from_frame(command(&["mset", "a", "1", "b"])).is_err());
+        let err = Command::from_frame(command(&["incrby", "n", "one"])).unwrap_err();
+        assert_eq!(err.to_string(), "ERR value is not an integer or out of range");
+        let err = Command::from_frame(Frame::Array(vec![])).unwrap_err();
+        assert_eq!(err.to_string(), "ERR empty command");
+        assert!(Command::from_frame(command(&["set", "k", "v", "nx", "xx"])).is_err());
+        assert!(Command::from_frame(command(&["set", "k", "v", "px"])).is_err());
+        assert!(Command::from_frame(command(&["set", "k", "v", "ex", "0"])).is_err());
+        assert!(Command::from_frame(command(&["set", "k", "v", "ex", "1", "keepttl"])).is_err());
+        assert!(Command::from_frame(command(&["expire", "k"])).is_err());
+        assert_eq!(Command::from_frame(command(&["set", "k", "v", "xx", "get"])).unwrap().get_name(), "set");
+        assert!(Command::from_frame(command(&["hello", "three"])).is_err());
+        let err = Command::from_frame(command(&["blpop", "k"])).unwrap_err();
+        assert_eq!(err.to_string(), "ERR wrong number of arguments for 'blpop' command");
+        let err = Command::from_frame(command(&["brpop", "k", "-1"])).unwrap_err();
+        assert_eq!(err.to_string(), "ERR timeout is negative");
+        assert!(Command::from_frame(command(&["blmove", "a", "b", "left", "up", "0"])).is_err());
+        assert!(Command::from_frame(command(&["client", "kill"])).is_err());
+        assert!(Command::from_frame(command(&["watch"])).is_err());
+        assert!(Command::from_frame(command(&["exec", "now"])).is_err());
+        assert!(Command::from_frame(Frame::Simple("get".to_string())).is_err());
+
+        match Command::from_frame(command(&["flushall"])).unwrap() {
+            Command::Unknown(cmd) => assert_eq!(cmd.get_name(), "flushall"),
+            cmd => panic!("unexpected command {:?}", cmd),
+        }
+    }
+
+    #[test]
+    fn mutations_are_logged() {
+        let temp = TempAof::new("logged");
+        let db = Db::new(4).with_aof(temp.open(FsyncPolicy::Always).unwrap());
+
+        run(&db, &["set", "k", "1", "ex", "100"]);
+        run(&db, &["incrby", "k", "41"]);
+        run(&db, &["append", "k", "!"]);
+        run(&db, &["mset", "x", "1", "y", "2"]);
+        run(&db, &["del", "x", "missing"]);
+        run(&db, &["set", "y", "3", "nx"]);
+        run(&db, &["setnx", "z", "1"]);
+        run(&db, &["getset", "z", "2"]);
+        run(&db, &["persist", "k"]);
+        run(&db, &["expire", "z", "100"]);
+        run(&db, &["pexpire", "y", "-1"]);
+        run(&db, &["rpush", "l", "a", "b", "c"]);
+        run(&db, &["lpush", "l", "0"]);
+        run(&db, &["rpop", "l", "2"]);
+        run(&db, &["lpop", "l"]);
+        run(&db, &["lmove", "l", "l2", "left", "left"]);
+        run(&db, &["hset", "h", "f", "1", "g", "2"]);
+        run(&db, &["hincrby", "h", "f", "10"]);
+        run(&db, &["hdel", "h", "g", "missing"]);
+        run(&db, &["sadd", "s", "a", "b", "c"]);
+        run(&db, &["srem", "s", "b"]);
+        run(&db, &["zadd", "zs", "1", "a", "2", "b"]);
+        run(&db, &["zincrby", "zs", "0.5", "b"]);
+        run(&db, &["zincrby", "zs", "-inf", "a"]);
+
+        // 重新执行AOF得到同样的数据
+        let restored = Db::new(4);
+        aof::replay(temp.path(), &restored).unwrap();
+        for key in ["k", "x", "y", "z"] {
+            assert_eq!(run(&restored, &["get", key]), run(&db, &["get", key]));
+            assert_eq!(run(&restored, &["ttl", key]), run(&db, &["ttl", key]));
+        }
+        for key in ["l", "l2"] {
+            assert_eq!(run(&restored, &["lrange", key, "0", "-1"]), run(&db, &["lrange", key, "0", "-1"]));
+        }
+        assert_eq!(sorted(run(&restored, &["hgetall", "h"])), sorted(run(&db, &["hgetall", "h"])));
+        assert_eq!(sorted(run(&restored, &["smembers", "s"])), sorted(run(&db, &["smembers", "s"])));
+        assert_eq!(
+            run(&restored, &["zrange", "zs", "0", "-1", "withscores"]),
+            run(&db, &["zrange", "zs", "0", "-1", "withscores"])
+        );
+    }
+}
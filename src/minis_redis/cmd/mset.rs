@@ -0,0 +1,102 @@
+use crate::minis_redis::db::{Db, Guard};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::{Parse, ParseError};
+
+use bytes::Bytes;
+
+/// Sets the given keys to their respective values.
+///
+/// All the keys are set at once: no client sees some of the keys updated and
+/// others not, even when the keys live on different shards.
+#[derive(Debug)]
+pub struct MSet {
+    pairs: Vec<(String, Bytes)>,
+}
+
+impl MSet {
+    /// Create a new `MSet` command which sets every key to its value.
+    pub fn new(pairs: &[(String, Bytes)]) -> MSet {
+        MSet { pairs: pairs.to_vec() }
+    }
+
+    pub fn pairs(&self) -> &[(String, Bytes)] {
+        &self.pairs
+    }
+
+    /// Parse an `MSet` instance from a received frame.
+    ///
+    /// The `MSET` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// MSET key value [key value ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<MSet> {
+        let mut pairs = vec![(parse.next_string()?, parse.next_bytes()?)];
+
+        loop {
+            match parse.next_string() {
+                Ok(key) => pairs.push((key, parse.next_bytes()?)),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(MSet { pairs })
+    }
+
+    /// Apply the `MSet` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock(self.pairs.iter().map(|(key, _)| key));
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `MSet` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        guard.log(|| MSet::new(&self.pairs).into_frame());
+        for (key, value) in self.pairs {
+            guard.insert(key, value);
+        }
+        Frame::Simple("OK".to_string())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("mset".as_bytes()));
+        for (key, value) in self.pairs {
+            frame.push_bulk(Bytes::from(key.into_bytes()));
+            frame.push_bulk(value);
+        }
+        frame
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::minis_redis::db::Db;
+    use crate::minis_redis::frame::Frame;
+    use crate::minis_redis::test_util::run;
+
+    #[test]
+    fn keys_across_shards() {
+        let db = Db::new(4);
+        let bulk = |s: &'static str| Frame::Bulk(Bytes::from(s));
+
+        // 同一个key出现多次时后面的值生效
+        assert_eq!(
+            run(&db, &["mset", "a", "1", "b", "2", "c", "3", "a", "4"]),
+            Frame::Simple("OK".to_string())
+        );
+        assert_eq!(
+            run(&db, &["mget", "a", "b", "c", "d"]),
+            Frame::Array(vec![bulk("4"), bulk("2"), bulk("3"), Frame::Null])
+        );
+        assert_eq!(run(&db, &["exists", "a", "a", "d"]), Frame::Integer(2));
+        assert_eq!(run(&db, &["del", "a", "b", "c", "d", "e", "f"]), Frame::Integer(3));
+        assert_eq!(run(&db, &["exists", "a", "b", "c"]), Frame::Integer(0));
+    }
+}
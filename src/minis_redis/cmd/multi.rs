@@ -0,0 +1,118 @@
+use crate::minis_redis::cmd::Session;
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+use crate::minis_redis::transaction::Transaction;
+
+use bytes::Bytes;
+
+/// Marks the start of a transaction.
+///
+/// The following commands of the connection are queued instead of being
+/// executed, until `EXEC` runs them all at once or `DISCARD` drops them.
+#[derive(Debug, Default)]
+pub struct Multi;
+
+/// Executes all the commands queued since `MULTI`.
+///
+/// The reply is an array with the reply of each command, or nil if one of
+/// the watched keys was modified and the transaction was aborted.
+#[derive(Debug, Default)]
+pub struct Exec;
+
+/// Drops all the commands queued since `MULTI` and unwatches all the keys.
+#[derive(Debug, Default)]
+pub struct Discard;
+
+impl Multi {
+    pub fn new() -> Multi {
+        Multi
+    }
+
+    /// Parse a `Multi` instance from a received frame.
+    ///
+    /// The `MULTI` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// MULTI
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::minis_redis::Result<Multi> {
+        Ok(Multi)
+    }
+
+    /// Apply the `Multi` command to the transaction of the connection.
+    pub fn apply(self, transaction: &mut Transaction) -> Frame {
+        transaction.multi()
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("multi".as_bytes()));
+        frame
+    }
+}
+
+impl Exec {
+    pub fn new() -> Exec {
+        Exec
+    }
+
+    /// Parse an `Exec` instance from a received frame.
+    ///
+    /// The `EXEC` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// EXEC
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::minis_redis::Result<Exec> {
+        Ok(Exec)
+    }
+
+    /// Apply the `Exec` command to the transaction of the connection, the
+    /// queued keyless commands run on `session`.
+    pub fn apply(self, transaction: &mut Transaction, session: &mut dyn Session) -> Frame {
+        transaction.exec(session)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("exec".as_bytes()));
+        frame
+    }
+}
+
+impl Discard {
+    pub fn new() -> Discard {
+        Discard
+    }
+
+    /// Parse a `Discard` instance from a received frame.
+    ///
+    /// The `DISCARD` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// DISCARD
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::minis_redis::Result<Discard> {
+        Ok(Discard)
+    }
+
+    /// Apply the `Discard` command to the transaction of the connection.
+    pub fn apply(self, transaction: &mut Transaction) -> Frame {
+        transaction.discard()
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("discard".as_bytes()));
+        frame
+    }
+}
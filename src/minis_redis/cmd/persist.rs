@@ -0,0 +1,66 @@
+use crate::minis_redis::db::{Db, Guard};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+
+use bytes::Bytes;
+
+/// Remove the existing timeout on `key`.
+///
+/// The reply is 1 if the timeout was removed, 0 if `key` does not exist or
+/// does not have an associated timeout.
+#[derive(Debug)]
+pub struct Persist {
+    key: String,
+}
+
+impl Persist {
+    /// Create a new `Persist` command for `key`.
+    pub fn new(key: impl ToString) -> Persist {
+        Persist { key: key.to_string() }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `Persist` instance from a received frame.
+    ///
+    /// The `PERSIST` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// PERSIST key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<Persist> {
+        Ok(Persist {
+            key: parse.next_string()?,
+        })
+    }
+
+    /// Apply the `Persist` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `Persist` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        match guard.expires_at(&self.key) {
+            Some(Some(_)) => {
+                guard.set_expiry(&self.key, None);
+                guard.log(|| Persist::new(&self.key).into_frame());
+                Frame::Integer(1)
+            }
+            _ => Frame::Integer(0),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("persist".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
@@ -0,0 +1,220 @@
+use crate::minis_redis::db::{Db, Guard, WrongType};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::{Parse, ParseError};
+
+use bytes::Bytes;
+use std::convert::TryFrom;
+
+/// Remove and return the first element of the list stored at `key`.
+///
+/// With a `count`, up to `count` elements are removed and returned as an
+/// array. The reply is nil if `key` does not exist. The key is deleted once
+/// its list becomes empty.
+#[derive(Debug)]
+pub struct LPop {
+    key: String,
+    count: Option<u64>,
+}
+
+/// Like `LPop`, but the elements are removed from the tail of the list.
+#[derive(Debug)]
+pub struct RPop {
+    key: String,
+    count: Option<u64>,
+}
+
+impl LPop {
+    /// Create a new `LPop` command which pops one element, or `count`
+    /// elements as an array, from `key`.
+    pub fn new(key: impl ToString, count: Option<u64>) -> LPop {
+        LPop {
+            key: key.to_string(),
+            count,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn count(&self) -> Option<u64> {
+        self.count
+    }
+
+    /// Parse an `LPop` instance from a received frame.
+    ///
+    /// The `LPOP` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// LPOP key [count]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<LPop> {
+        let key = parse.next_string()?;
+        let count = parse_count(parse)?;
+
+        Ok(LPop { key, count })
+    }
+
+    /// Apply the `LPop` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `LPop` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        pop(guard, "lpop", self.key, self.count, true)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        into_frame("lpop", self.key, self.count)
+    }
+}
+
+impl RPop {
+    /// Create a new `RPop` command which pops one element, or `count`
+    /// elements as an array, from `key`.
+    pub fn new(key: impl ToString, count: Option<u64>) -> RPop {
+        RPop {
+            key: key.to_string(),
+            count,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn count(&self) -> Option<u64> {
+        self.count
+    }
+
+    /// Parse an `RPop` instance from a received frame.
+    ///
+    /// The `RPOP` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// RPOP key [count]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<RPop> {
+        let key = parse.next_string()?;
+        let count = parse_count(parse)?;
+
+        Ok(RPop { key, count })
+    }
+
+    /// Apply the `RPop` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `RPop` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        pop(guard, "rpop", self.key, self.count, false)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        into_frame("rpop", self.key, self.count)
+    }
+}
+
+/// 可选的count参数, 不能是负数
+fn parse_count(parse: &mut Parse) -> crate::minis_redis::Result<Option<u64>> {
+    match parse.next_int() {
+        Ok(count) => match u64::try_from(count) {
+            Ok(count) => Ok(Some(count)),
+            Err(_) => Err("value is out of range, must be positive".into()),
+        },
+        Err(ParseError::EndOfStream) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// LPOP 和 RPOP 共用, `front` 为true时从头部弹出
+fn pop(guard: &mut Guard<'_>, name: &'static str, key: String, count: Option<u64>, front: bool) -> Frame {
+    let list = match guard.list_mut(&key) {
+        Ok(Some(list)) => list,
+        Ok(None) => return Frame::Null,
+        Err(err) => return err.into(),
+    };
+
+    let n = count.map_or(1, |count| count.min(list.len() as u64) as usize);
+    let popped: Vec<Bytes> = (0..n)
+        .map(|_| if front { list.pop_front() } else { list.pop_back() }.unwrap())
+        .collect();
+    if list.is_empty() {
+        guard.remove(&key);
+    }
+    if n > 0 {
+        // 回放时list的内容相同, 弹出的也是同样的元素
+        guard.log(|| into_frame(name, key, count.map(|_| n as u64)));
+    }
+
+    match count {
+        // 存在的list至少有一个元素
+        None => Frame::Bulk(popped.into_iter().next().unwrap()),
+        Some(_) => Frame::Array(popped.into_iter().map(Frame::Bulk).collect()),
+    }
+}
+
+/// 在已经持有的锁下从 `key` 弹出一个元素, 记为LPOP或者RPOP, BLPOP和BRPOP用
+pub(crate) fn pop_one(guard: &mut Guard<'_>, key: &str, front: bool) -> Result<Option<Bytes>, WrongType> {
+    let list = match guard.list_mut(key)? {
+        Some(list) => list,
+        None => return Ok(None),
+    };
+    let value = if front { list.pop_front() } else { list.pop_back() };
+    if list.is_empty() {
+        guard.remove(key);
+    }
+
+    let name = if front { "lpop" } else { "rpop" };
+    guard.log(|| into_frame(name, key.to_string(), None));
+    Ok(value)
+}
+
+fn into_frame(name: &'static str, key: String, count: Option<u64>) -> Frame {
+    let mut frame = Frame::array();
+    frame.push_bulk(Bytes::from(name.as_bytes()));
+    frame.push_bulk(Bytes::from(key.into_bytes()));
+    if let Some(count) = count {
+        frame.push_bulk(Bytes::from(count.to_string()));
+    }
+    frame
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::minis_redis::cmd::Command;
+    use crate::minis_redis::db::Db;
+    use crate::minis_redis::frame::Frame;
+    use crate::minis_redis::test_util::{command, run};
+
+    #[test]
+    fn pop_with_count() {
+        let db = Db::new(4);
+        let bulk = |s: &'static str| Frame::Bulk(Bytes::from(s));
+        let array = |items: &[&'static str]| Frame::Array(items.iter().map(|s| bulk(s)).collect());
+
+        run(&db, &["rpush", "l", "0", "a", "b", "c"]);
+        assert_eq!(run(&db, &["lpop", "l"]), bulk("0"));
+        assert_eq!(run(&db, &["rpop", "l", "2"]), array(&["c", "b"]));
+        assert_eq!(run(&db, &["rpop", "missing"]), Frame::Null);
+        // 弹空之后key被删除
+        assert_eq!(run(&db, &["lpop", "l", "10"]), array(&["a"]));
+        assert_eq!(run(&db, &["exists", "l"]), Frame::Integer(0));
+        assert_eq!(
+            Command::from_frame(command(&["lpop", "l", "-1"])).unwrap_err().to_string(),
+            "ERR value is out of range, must be positive"
+        );
+    }
+}
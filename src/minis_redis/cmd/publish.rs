@@ -0,0 +1,67 @@
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+use crate::minis_redis::pubsub::PubSub;
+
+use bytes::Bytes;
+
+/// Posts a message to the given channel.
+///
+/// The reply is the number of clients that received the message.
+#[derive(Debug)]
+pub struct Publish {
+    /// Name of the channel on which the message should be published.
+    channel: String,
+
+    /// The message to publish.
+    message: Bytes,
+}
+
+impl Publish {
+    /// Create a new `Publish` command which sends `message` on `channel`.
+    pub fn new(channel: impl ToString, message: Bytes) -> Publish {
+        Publish {
+            channel: channel.to_string(),
+            message,
+        }
+    }
+
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    pub fn message(&self) -> &Bytes {
+        &self.message
+    }
+
+    /// Parse a `Publish` instance from a received frame.
+    ///
+    /// The `PUBLISH` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// PUBLISH channel message
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<Publish> {
+        let channel = parse.next_string()?;
+        let message = parse.next_bytes()?;
+
+        Ok(Publish { channel, message })
+    }
+
+    /// Apply the `Publish` command, replying with the number of subscriptions
+    /// that received the message.
+    pub fn apply(self, pubsub: &PubSub) -> Frame {
+        Frame::Integer(pubsub.publish(&self.channel, self.message) as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("publish".as_bytes()));
+        frame.push_bulk(Bytes::from(self.channel.into_bytes()));
+        frame.push_bulk(self.message);
+
+        frame
+    }
+}
@@ -0,0 +1,185 @@
+use crate::minis_redis::db::{Db, Guard};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+
+use bytes::Bytes;
+
+/// Insert all the values at the head of the list stored at `key`, creating
+/// the list if `key` does not exist.
+///
+/// The values are inserted one after the other, so `LPUSH k a b c` leaves
+/// `c` at the head. The reply is the length of the list after the push.
+#[derive(Debug)]
+pub struct LPush {
+    key: String,
+    values: Vec<Bytes>,
+}
+
+/// Like `LPush`, but the values are appended at the tail of the list.
+#[derive(Debug)]
+pub struct RPush {
+    key: String,
+    values: Vec<Bytes>,
+}
+
+impl LPush {
+    /// Create a new `LPush` command which pushes `values` onto `key`.
+    pub fn new(key: impl ToString, values: &[Bytes]) -> LPush {
+        LPush {
+            key: key.to_string(),
+            values: values.to_vec(),
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn values(&self) -> &[Bytes] {
+        &self.values
+    }
+
+    /// Parse an `LPush` instance from a received frame.
+    ///
+    /// The `LPUSH` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// LPUSH key element [element ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<LPush> {
+        let key = parse.next_string()?;
+        let values = parse.next_bytes_list()?;
+
+        Ok(LPush { key, values })
+    }
+
+    /// Apply the `LPush` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `LPush` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        push(guard, "lpush", self.key, self.values, true)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        into_frame("lpush", self.key, self.values)
+    }
+}
+
+impl RPush {
+    /// Create a new `RPush` command which appends `values` to `key`.
+    pub fn new(key: impl ToString, values: &[Bytes]) -> RPush {
+        RPush {
+            key: key.to_string(),
+            values: values.to_vec(),
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn values(&self) -> &[Bytes] {
+        &self.values
+    }
+
+    /// Parse an `RPush` instance from a received frame.
+    ///
+    /// The `RPUSH` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// RPUSH key element [element ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<RPush> {
+        let key = parse.next_string()?;
+        let values = parse.next_bytes_list()?;
+
+        Ok(RPush { key, values })
+    }
+
+    /// Apply the `RPush` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `RPush` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        push(guard, "rpush", self.key, self.values, false)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        into_frame("rpush", self.key, self.values)
+    }
+}
+
+/// LPUSH 和 RPUSH 共用, `front` 为true时从头部插入
+fn push(guard: &mut Guard<'_>, name: &'static str, key: String, values: Vec<Bytes>, front: bool) -> Frame {
+    let list = match guard.list_or_default(&key) {
+        Ok(list) => list,
+        Err(err) => return err.into(),
+    };
+    for value in &values {
+        if front {
+            list.push_front(value.clone());
+        } else {
+            list.push_back(value.clone());
+        }
+    }
+    let len = list.len();
+    guard.signal_ready(&key);
+
+    guard.log(|| into_frame(name, key, values));
+    Frame::Integer(len as i64)
+}
+
+fn into_frame(name: &'static str, key: String, values: Vec<Bytes>) -> Frame {
+    let mut frame = Frame::array();
+    frame.push_bulk(Bytes::from(name.as_bytes()));
+    frame.push_bulk(Bytes::from(key.into_bytes()));
+    for value in values {
+        frame.push_bulk(value);
+    }
+    frame
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::minis_redis::db::Db;
+    use crate::minis_redis::frame::Frame;
+    use crate::minis_redis::test_util::run;
+
+    #[test]
+    fn push_and_wrong_type() {
+        let db = Db::new(4);
+        let bulk = |s: &'static str| Frame::Bulk(Bytes::from(s));
+        let wrong_type = Frame::Error("WRONGTYPE Operation against a key holding the wrong kind of value".to_string());
+
+        assert_eq!(run(&db, &["rpush", "l", "b", "c"]), Frame::Integer(2));
+        assert_eq!(run(&db, &["lpush", "l", "a", "0"]), Frame::Integer(4));
+        assert_eq!(run(&db, &["llen", "l"]), Frame::Integer(4));
+        assert_eq!(run(&db, &["llen", "missing"]), Frame::Integer(0));
+
+        // 类型不对
+        run(&db, &["set", "s", "v"]);
+        assert_eq!(run(&db, &["lpush", "s", "v"]), wrong_type);
+        assert_eq!(run(&db, &["get", "l"]), wrong_type);
+        assert_eq!(run(&db, &["incr", "l"]), wrong_type);
+        assert_eq!(run(&db, &["set", "l", "v", "get"]), wrong_type);
+        assert_eq!(run(&db, &["mget", "s", "l"]), Frame::Array(vec![bulk("v"), Frame::Null]));
+        // SET 可以覆盖其他类型
+        assert_eq!(run(&db, &["set", "l", "v"]), Frame::Simple("OK".to_string()));
+        assert_eq!(run(&db, &["get", "l"]), bulk("v"));
+    }
+}
@@ -0,0 +1,86 @@
+use crate::minis_redis::db::Db;
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+use crate::minis_redis::snapshot::Snapshots;
+
+use bytes::Bytes;
+
+/// Write a snapshot of the whole dataset, replying once it is on disk.
+///
+/// All the shards are locked only while the data is copied, other
+/// connections keep reading and writing while the file is written.
+#[derive(Debug, Default)]
+pub struct Save;
+
+/// Like `Save`, but the snapshot is written by a background thread and the
+/// reply is sent right away.
+#[derive(Debug, Default)]
+pub struct BgSave;
+
+impl Save {
+    pub fn new() -> Save {
+        Save
+    }
+
+    /// Parse a `Save` instance from a received frame.
+    ///
+    /// The `SAVE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// SAVE
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::minis_redis::Result<Save> {
+        Ok(Save)
+    }
+
+    /// Apply the `Save` command, the snapshot goes to `snapshots`.
+    pub fn apply(self, db: &Db, snapshots: &Snapshots) -> Frame {
+        match snapshots.save(db) {
+            Ok(_) => Frame::Simple("OK".to_string()),
+            Err(err) => Frame::Error(format!("ERR {}", err)),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("save".as_bytes()));
+        frame
+    }
+}
+
+impl BgSave {
+    pub fn new() -> BgSave {
+        BgSave
+    }
+
+    /// Parse a `BgSave` instance from a received frame.
+    ///
+    /// The `BGSAVE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// BGSAVE
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::minis_redis::Result<BgSave> {
+        Ok(BgSave)
+    }
+
+    /// Apply the `BgSave` command, must be called from within a tokio runtime.
+    pub fn apply(self, db: &Db, snapshots: &Snapshots) -> Frame {
+        match snapshots.spawn_save(db.clone()) {
+            Ok(()) => Frame::Simple("Background saving started".to_string()),
+            Err(err) => Frame::Error(format!("ERR {}", err)),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("bgsave".as_bytes()));
+        frame
+    }
+}
@@ -0,0 +1,290 @@
+use crate::minis_redis::cmd::PExpireAt;
+use crate::minis_redis::db::{Db, Guard, self};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::{Parse, ParseError};
+
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Set `key` to hold the string `value`.
+///
+/// If `key` already holds a value, it is overwritten.
+///
+/// # Options
+///
+/// * NX -- Only set the key if it does not already exist.
+/// * XX -- Only set the key if it already exists.
+/// * GET -- Return the old string stored at key, or nil if key did not exist.
+/// * EX `seconds` -- Set the specified expire time, in seconds.
+/// * PX `milliseconds` -- Set the specified expire time, in milliseconds.
+/// * KEEPTTL -- Retain the time to live associated with the key.
+#[derive(Debug)]
+pub struct Set {
+    /// the lookup key
+    key: String,
+
+    /// the value to be stored
+    value: Bytes,
+
+    /// only set the key under this condition
+    condition: Option<SetCondition>,
+
+    /// reply with the old value instead of OK
+    get: bool,
+
+    /// When to expire the key
+    expire: Option<Duration>,
+
+    /// keep the old expiration instead of clearing it
+    keep_ttl: bool,
+}
+
+/// When `SET` is allowed to write the key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SetCondition {
+    /// NX: only if the key does not exist
+    NotExists,
+    /// XX: only if the key already exists
+    Exists,
+}
+
+impl Set {
+    /// Create a new `Set` command which sets `key` to `value`.
+    pub fn new(key: impl ToString, value: Bytes) -> Set {
+        Set {
+            key: key.to_string(),
+            value,
+            condition: None,
+            get: false,
+            expire: None,
+            keep_ttl: false,
+        }
+    }
+
+    /// Only set the key under `condition`
+    pub fn with_condition(mut self, condition: SetCondition) -> Set {
+        self.condition = Some(condition);
+        self
+    }
+
+    /// Reply with the old value of the key
+    pub fn with_get(mut self) -> Set {
+        self.get = true;
+        self
+    }
+
+    /// Expire the key after `expire`
+    pub fn with_expire(mut self, expire: Duration) -> Set {
+        self.expire = Some(expire);
+        self.keep_ttl = false;
+        self
+    }
+
+    /// Keep the expiration the key already has
+    pub fn with_keep_ttl(mut self) -> Set {
+        self.keep_ttl = true;
+        self.expire = None;
+        self
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Get the value
+    pub fn value(&self) -> &Bytes {
+        &self.value
+    }
+
+    pub fn condition(&self) -> Option<SetCondition> {
+        self.condition
+    }
+
+    pub fn get(&self) -> bool {
+        self.get
+    }
+
+    /// Get the expire
+    pub fn expire(&self) -> Option<Duration> {
+        self.expire
+    }
+
+    pub fn keep_ttl(&self) -> bool {
+        self.keep_ttl
+    }
+
+    /// Parse a `Set` instance from a received frame.
+    ///
+    /// The `SET` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// SET key value [NX | XX] [GET] [EX seconds | PX milliseconds | KEEPTTL]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<Set> {
+        let key = parse.next_string()?;
+        let value = parse.next_bytes()?;
+        let mut set = Set::new(key, value);
+
+        loop {
+            let option = match parse.next_string() {
+                Ok(option) => option.to_uppercase(),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            };
+            let no_ttl_option = set.expire.is_none() && !set.keep_ttl;
+            match &option[..] {
+                "NX" if set.condition.is_none() => set.condition = Some(SetCondition::NotExists),
+                "XX" if set.condition.is_none() => set.condition = Some(SetCondition::Exists),
+                "GET" if !set.get => set.get = true,
+                "EX" if no_ttl_option => set.expire = Some(Duration::from_secs(parse_expire(parse)?)),
+                "PX" if no_ttl_option => set.expire = Some(Duration::from_millis(parse_expire(parse)?)),
+                "KEEPTTL" if no_ttl_option => set.keep_ttl = true,
+                // NX 和 XX 同时出现、选项重复或者不认识的选项
+                _ => return Err("syntax error".into()),
+            }
+        }
+
+        Ok(set)
+    }
+
+    /// Apply the `Set` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `Set` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        // SET可以覆盖任何类型的key, 只有GET要求原来的值是string
+        let exists = guard.contains_key(&self.key);
+        let old = match guard.get(&self.key) {
+            Ok(old) => old.cloned(),
+            Err(err) if self.get => return err.into(),
+            Err(_) => None,
+        };
+
+        let write = match self.condition {
+            None => true,
+            Some(SetCondition::NotExists) => !exists,
+            Some(SetCondition::Exists) => exists,
+        };
+        if write {
+            // AOF里只记写入的结果: 没有NX/XX、GET, 相对的过期时间换成PEXPIREAT
+            let when = self.expire.map(|expire| Instant::now() + expire);
+            guard.log(|| {
+                let set = Set::new(&self.key, self.value.clone());
+                if self.keep_ttl { set.with_keep_ttl() } else { set }.into_frame()
+            });
+            if let Some(when) = when {
+                guard.log(|| PExpireAt::new(&self.key, db::unix_millis(when)).into_frame());
+            }
+
+            if self.keep_ttl {
+                guard.update(self.key, self.value);
+            } else if let Some(when) = when {
+                guard.insert(self.key.clone(), self.value);
+                guard.set_expiry(&self.key, Some(when));
+            } else {
+                guard.insert(self.key, self.value);
+            }
+        }
+
+        match (self.get, write) {
+            (true, _) => old.map_or(Frame::Null, Frame::Bulk),
+            (false, true) => Frame::Simple("OK".to_string()),
+            // 条件不满足, 没有写入
+            (false, false) => Frame::Null,
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Set` command to send to
+    /// the server.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("set".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(self.value);
+        match self.condition {
+            Some(SetCondition::NotExists) => frame.push_bulk(Bytes::from("NX".as_bytes())),
+            Some(SetCondition::Exists) => frame.push_bulk(Bytes::from("XX".as_bytes())),
+            None => {}
+        }
+        if self.get {
+            frame.push_bulk(Bytes::from("GET".as_bytes()));
+        }
+        if let Some(expire) = self.expire {
+            // 用PX而不是EX, 毫秒的精度更高
+            frame.push_bulk(Bytes::from("PX".as_bytes()));
+            frame.push_bulk(Bytes::from(expire.as_millis().to_string()));
+        }
+        if self.keep_ttl {
+            frame.push_bulk(Bytes::from("KEEPTTL".as_bytes()));
+        }
+        frame
+    }
+}
+
+/// EX/PX 后面的过期时间, 必须是正数
+fn parse_expire(parse: &mut Parse) -> crate::minis_redis::Result<u64> {
+    match parse.next_int()? {
+        n if n > 0 => Ok(n as u64),
+        _ => Err("invalid expire time in 'set' command".into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use crate::minis_redis::db::Db;
+    use crate::minis_redis::frame::Frame;
+    use crate::minis_redis::test_util::run;
+
+    #[test]
+    fn conditions_and_get() {
+        let db = Db::new(4);
+        let ok = Frame::Simple("OK".to_string());
+        let bulk = |s: &'static str| Frame::Bulk(Bytes::from(s));
+
+        assert_eq!(run(&db, &["set", "k", "1", "xx"]), Frame::Null);
+        assert_eq!(run(&db, &["set", "k", "1", "nx"]), ok);
+        assert_eq!(run(&db, &["set", "k", "2", "nx", "get"]), bulk("1"));
+        assert_eq!(run(&db, &["set", "k", "2", "xx", "get"]), bulk("1"));
+        assert_eq!(run(&db, &["getset", "k", "3"]), bulk("2"));
+        assert_eq!(run(&db, &["setnx", "k", "4"]), Frame::Integer(0));
+        assert_eq!(run(&db, &["setnx", "n", "4"]), Frame::Integer(1));
+        assert_eq!(run(&db, &["get", "k"]), bulk("3"));
+        assert_eq!(run(&db, &["get", "missing"]), Frame::Null);
+    }
+
+    #[test]
+    fn expire_options() {
+        let db = Db::new(4);
+        assert_eq!(run(&db, &["set", "k", "v", "ex", "10"]), Frame::Simple("OK".to_string()));
+        assert_eq!(run(&db, &["ttl", "k"]), Frame::Integer(10));
+
+        // INCR/APPEND 和 KEEPTTL 保留过期时间, 普通的 SET 清除过期时间
+        run(&db, &["append", "k", "v"]);
+        assert_eq!(run(&db, &["ttl", "k"]), Frame::Integer(10));
+        run(&db, &["set", "k", "v", "keepttl"]);
+        assert_eq!(run(&db, &["ttl", "k"]), Frame::Integer(10));
+        run(&db, &["set", "k", "v"]);
+        assert_eq!(run(&db, &["ttl", "k"]), Frame::Integer(-1));
+
+        // 过期之后访问时就当作不存在
+        run(&db, &["set", "k", "v", "px", "20"]);
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(run(&db, &["get", "k"]), Frame::Null);
+        assert_eq!(run(&db, &["exists", "k"]), Frame::Integer(0));
+        assert_eq!(run(&db, &["incr", "k"]), Frame::Integer(1));
+        assert_eq!(run(&db, &["ttl", "k"]), Frame::Integer(-1));
+    }
+}
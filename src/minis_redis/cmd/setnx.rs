@@ -0,0 +1,74 @@
+use crate::minis_redis::cmd::Set;
+use crate::minis_redis::db::{Db, Guard};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+
+use bytes::Bytes;
+
+/// Set `key` to hold `value` only if `key` does not exist.
+///
+/// The reply is 1 if the key was set, 0 otherwise.
+#[derive(Debug)]
+pub struct SetNx {
+    key: String,
+    value: Bytes,
+}
+
+impl SetNx {
+    /// Create a new `SetNx` command which sets `key` to `value`.
+    pub fn new(key: impl ToString, value: Bytes) -> SetNx {
+        SetNx {
+            key: key.to_string(),
+            value,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn value(&self) -> &Bytes {
+        &self.value
+    }
+
+    /// Parse a `SetNx` instance from a received frame.
+    ///
+    /// The `SETNX` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// SETNX key value
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<SetNx> {
+        let key = parse.next_string()?;
+        let value = parse.next_bytes()?;
+
+        Ok(SetNx { key, value })
+    }
+
+    /// Apply the `SetNx` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `SetNx` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        if guard.contains_key(&self.key) {
+            return Frame::Integer(0);
+        }
+        guard.log(|| Set::new(&self.key, self.value.clone()).into_frame());
+        guard.insert(self.key, self.value);
+        Frame::Integer(1)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("setnx".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(self.value);
+        frame
+    }
+}
@@ -0,0 +1,374 @@
+use crate::minis_redis::db::{Db, Guard, WrongType};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+
+use bytes::Bytes;
+use std::collections::HashSet;
+
+/// Adds the given members to the set stored at `key`, creating the set if
+/// `key` does not exist.
+///
+/// The reply is the number of members that were added, members already in
+/// the set are not counted.
+#[derive(Debug)]
+pub struct SAdd {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+/// Removes the given members from the set stored at `key`.
+///
+/// The reply is the number of members that were removed. The key is deleted
+/// once its set becomes empty.
+#[derive(Debug)]
+pub struct SRem {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+/// Returns all the members of the set stored at `key`.
+#[derive(Debug)]
+pub struct SMembers {
+    key: String,
+}
+
+/// Returns the members present in every one of the given sets. A key that
+/// does not exist is an empty set.
+#[derive(Debug)]
+pub struct SInter {
+    keys: Vec<String>,
+}
+
+/// Returns the members present in any of the given sets.
+#[derive(Debug)]
+pub struct SUnion {
+    keys: Vec<String>,
+}
+
+impl SAdd {
+    /// Create a new `SAdd` command which adds `members` to `key`.
+    pub fn new(key: impl ToString, members: &[Bytes]) -> SAdd {
+        SAdd {
+            key: key.to_string(),
+            members: members.to_vec(),
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `SAdd` instance from a received frame.
+    ///
+    /// The `SADD` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// SADD key member [member ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<SAdd> {
+        let key = parse.next_string()?;
+        let members = parse.next_bytes_list()?;
+
+        Ok(SAdd { key, members })
+    }
+
+    /// Apply the `SAdd` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `SAdd` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        let set = match guard.set_or_default(&self.key) {
+            Ok(set) => set,
+            Err(err) => return err.into(),
+        };
+        let added = self
+            .members
+            .iter()
+            .filter(|member| set.insert((*member).clone()))
+            .count();
+
+        if added > 0 {
+            guard.log(|| self.into_frame());
+        }
+        Frame::Integer(added as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        into_frame("sadd", &[self.key], self.members)
+    }
+}
+
+impl SRem {
+    /// Create a new `SRem` command which removes `members` from `key`.
+    pub fn new(key: impl ToString, members: &[Bytes]) -> SRem {
+        SRem {
+            key: key.to_string(),
+            members: members.to_vec(),
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `SRem` instance from a received frame.
+    ///
+    /// The `SREM` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// SREM key member [member ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<SRem> {
+        let key = parse.next_string()?;
+        let members = parse.next_bytes_list()?;
+
+        Ok(SRem { key, members })
+    }
+
+    /// Apply the `SRem` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `SRem` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        let set = match guard.set_mut(&self.key) {
+            Ok(Some(set)) => set,
+            Ok(None) => return Frame::Integer(0),
+            Err(err) => return err.into(),
+        };
+        let removed = self.members.iter().filter(|member| set.remove(*member)).count();
+        if set.is_empty() {
+            guard.remove(&self.key);
+        }
+
+        if removed > 0 {
+            guard.log(|| self.into_frame());
+        }
+        Frame::Integer(removed as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        into_frame("srem", &[self.key], self.members)
+    }
+}
+
+impl SMembers {
+    /// Create a new `SMembers` command for `key`.
+    pub fn new(key: impl ToString) -> SMembers {
+        SMembers { key: key.to_string() }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `SMembers` instance from a received frame.
+    ///
+    /// The `SMEMBERS` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// SMEMBERS key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<SMembers> {
+        let key = parse.next_string()?;
+
+        Ok(SMembers { key })
+    }
+
+    /// Apply the `SMembers` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `SMembers` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        match guard.set(&self.key) {
+            Ok(set) => Frame::Set(
+                set.into_iter()
+                    .flatten()
+                    .map(|member| Frame::Bulk(member.clone()))
+                    .collect(),
+            ),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        into_frame("smembers", &[self.key], vec![])
+    }
+}
+
+impl SInter {
+    /// Create a new `SInter` command which intersects the sets at `keys`.
+    pub fn new(keys: &[String]) -> SInter {
+        SInter { keys: keys.to_vec() }
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse an `SInter` instance from a received frame.
+    ///
+    /// The `SINTER` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// SINTER key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<SInter> {
+        Ok(SInter {
+            keys: parse.next_strings()?,
+        })
+    }
+
+    /// Apply the `SInter` command to the specified `Db` instance.
+    ///
+    /// All the shards involved are locked together, so the sets are read at
+    /// the same point in time.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock(&self.keys);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `SInter` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        let sets = match sets(guard, &self.keys) {
+            Ok(sets) => sets,
+            Err(err) => return err.into(),
+        };
+
+        // 从最小的集合开始, 检查每个成员是否在其他所有集合中
+        let mut sets: Vec<&HashSet<Bytes>> = match sets.into_iter().collect::<Option<_>>() {
+            Some(sets) => sets,
+            // 有一个key不存在, 交集就是空的
+            None => return Frame::Set(vec![]),
+        };
+        sets.sort_by_key(|set| set.len());
+        let (smallest, others) = sets.split_first().unwrap();
+        let members = smallest
+            .iter()
+            .filter(|member| others.iter().all(|set| set.contains(*member)))
+            .map(|member| Frame::Bulk(member.clone()))
+            .collect();
+        Frame::Set(members)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        into_frame("sinter", &self.keys, vec![])
+    }
+}
+
+impl SUnion {
+    /// Create a new `SUnion` command which merges the sets at `keys`.
+    pub fn new(keys: &[String]) -> SUnion {
+        SUnion { keys: keys.to_vec() }
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse an `SUnion` instance from a received frame.
+    ///
+    /// The `SUNION` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// SUNION key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<SUnion> {
+        Ok(SUnion {
+            keys: parse.next_strings()?,
+        })
+    }
+
+    /// Apply the `SUnion` command to the specified `Db` instance.
+    ///
+    /// All the shards involved are locked together, so the sets are read at
+    /// the same point in time.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock(&self.keys);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `SUnion` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        let sets = match sets(guard, &self.keys) {
+            Ok(sets) => sets,
+            Err(err) => return err.into(),
+        };
+
+        let union: HashSet<&Bytes> = sets.into_iter().flatten().flatten().collect();
+        Frame::Set(union.into_iter().map(|member| Frame::Bulk(member.clone())).collect())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        into_frame("sunion", &self.keys, vec![])
+    }
+}
+
+/// SINTER 和 SUNION 共用: 每个key的集合, 不存在的key是None, 有一个key不是set就报错
+fn sets<'a>(guard: &'a Guard<'_>, keys: &[String]) -> Result<Vec<Option<&'a HashSet<Bytes>>>, WrongType> {
+    keys.iter().map(|key| guard.set(key)).collect()
+}
+
+fn into_frame(name: &'static str, keys: &[String], members: Vec<Bytes>) -> Frame {
+    let mut frame = Frame::array();
+    frame.push_bulk(Bytes::from(name.as_bytes()));
+    for key in keys {
+        frame.push_bulk(Bytes::from(key.clone().into_bytes()));
+    }
+    for member in members {
+        frame.push_bulk(member);
+    }
+    frame
+}
+
+#[cfg(test)]
+mod test {
+    use crate::minis_redis::db::Db;
+    use crate::minis_redis::frame::Frame;
+    use crate::minis_redis::test_util::{run, sorted};
+
+    #[test]
+    fn set_commands() {
+        let db = Db::new(4);
+        let wrong_type = Frame::Error("WRONGTYPE Operation against a key holding the wrong kind of value".to_string());
+
+        // key分布在不同的分片上
+        assert_eq!(run(&db, &["sadd", "s1", "a", "b", "c", "a"]), Frame::Integer(3));
+        assert_eq!(run(&db, &["sadd", "s2", "b", "c", "d"]), Frame::Integer(3));
+        assert_eq!(run(&db, &["sadd", "s3", "c", "d"]), Frame::Integer(2));
+        assert_eq!(sorted(run(&db, &["sinter", "s1", "s2", "s3"])), ["c"]);
+        assert_eq!(sorted(run(&db, &["sinter", "s1", "missing"])), Vec::<String>::new());
+        assert_eq!(sorted(run(&db, &["sunion", "s1", "s3", "missing"])), ["a", "b", "c", "d"]);
+        assert_eq!(run(&db, &["srem", "s3", "c", "x"]), Frame::Integer(1));
+        assert_eq!(sorted(run(&db, &["smembers", "s3"])), ["d"]);
+        run(&db, &["srem", "s3", "d"]);
+        assert_eq!(run(&db, &["exists", "s3"]), Frame::Integer(0));
+
+        run(&db, &["set", "str", "v"]);
+        assert_eq!(run(&db, &["sadd", "str", "a"]), wrong_type);
+        assert_eq!(run(&db, &["sunion", "s1", "str"]), wrong_type);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::minis_redis::db::{Db, Guard};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+
+use bytes::Bytes;
+
+/// Returns the length of the string stored at `key`, 0 if it does not exist.
+#[derive(Debug)]
+pub struct Strlen {
+    key: String,
+}
+
+impl Strlen {
+    /// Create a new `Strlen` command for `key`.
+    pub fn new(key: impl ToString) -> Strlen {
+        Strlen { key: key.to_string() }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `Strlen` instance from a received frame.
+    ///
+    /// The `STRLEN` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// STRLEN key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<Strlen> {
+        let key = parse.next_string()?;
+
+        Ok(Strlen { key })
+    }
+
+    /// Apply the `Strlen` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `Strlen` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        match guard.get(&self.key) {
+            Ok(value) => Frame::Integer(value.map_or(0, |value| value.len()) as i64),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("strlen".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
@@ -0,0 +1,240 @@
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::{Parse, ParseError};
+use crate::minis_redis::pubsub::Subscriptions;
+
+use bytes::Bytes;
+
+/// Subscribes the client to one or more channels.
+///
+/// Once the client enters the subscribed state, it is not supposed to issue any
+/// other commands, except for additional SUBSCRIBE, UNSUBSCRIBE and PING.
+#[derive(Debug)]
+pub struct Subscribe {
+    channels: Vec<String>,
+}
+
+/// Unsubscribes the client from one or more channels.
+///
+/// When no channels are specified, the client is unsubscribed from all the
+/// previously subscribed channels.
+#[derive(Clone, Debug)]
+pub struct Unsubscribe {
+    channels: Vec<String>,
+}
+
+/// Subscribes the client to the given glob-style patterns.
+///
+/// Every message published on a channel whose name matches one of the
+/// patterns is delivered as a `pmessage`.
+#[derive(Debug)]
+pub struct PSubscribe {
+    patterns: Vec<String>,
+}
+
+/// Unsubscribes the client from the given patterns, or from all of them if
+/// none is given.
+#[derive(Clone, Debug)]
+pub struct PUnsubscribe {
+    patterns: Vec<String>,
+}
+
+impl Subscribe {
+    /// Creates a new `Subscribe` command to listen on the specified channels.
+    pub fn new(channels: &[String]) -> Subscribe {
+        Subscribe {
+            channels: channels.to_vec(),
+        }
+    }
+
+    pub fn channels(&self) -> &[String] {
+        &self.channels
+    }
+
+    /// Parse a `Subscribe` instance from a received frame.
+    ///
+    /// The `SUBSCRIBE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// SUBSCRIBE channel [channel ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<Subscribe> {
+        // At least one channel is required
+        let mut channels = vec![parse.next_string()?];
+
+        loop {
+            match parse.next_string() {
+                Ok(s) => channels.push(s),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(Subscribe { channels })
+    }
+
+    /// Apply the `Subscribe` command to the connection's subscriptions,
+    /// returning one confirmation per channel.
+    pub fn apply(self, subscriptions: &mut Subscriptions) -> Vec<Frame> {
+        subscriptions.subscribe(&self.channels)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("subscribe".as_bytes()));
+        for channel in self.channels {
+            frame.push_bulk(Bytes::from(channel.into_bytes()));
+        }
+        frame
+    }
+}
+
+impl Unsubscribe {
+    /// Create a new `Unsubscribe` command with the given `channels`.
+    pub fn new(channels: &[String]) -> Unsubscribe {
+        Unsubscribe {
+            channels: channels.to_vec(),
+        }
+    }
+
+    pub fn channels(&self) -> &[String] {
+        &self.channels
+    }
+
+    /// Parse an `Unsubscribe` instance from a received frame.
+    ///
+    /// The `UNSUBSCRIBE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// UNSUBSCRIBE [channel [channel ...]]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<Unsubscribe> {
+        let mut channels = vec![];
+
+        loop {
+            match parse.next_string() {
+                Ok(s) => channels.push(s),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(Unsubscribe { channels })
+    }
+
+    /// Apply the `Unsubscribe` command to the connection's subscriptions,
+    /// returning one confirmation per channel.
+    pub fn apply(self, subscriptions: &mut Subscriptions) -> Vec<Frame> {
+        subscriptions.unsubscribe(&self.channels)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("unsubscribe".as_bytes()));
+        for channel in self.channels {
+            frame.push_bulk(Bytes::from(channel.into_bytes()));
+        }
+        frame
+    }
+}
+
+impl PSubscribe {
+    /// Creates a new `PSubscribe` command to listen on the specified patterns.
+    pub fn new(patterns: &[String]) -> PSubscribe {
+        PSubscribe {
+            patterns: patterns.to_vec(),
+        }
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// Parse a `PSubscribe` instance from a received frame.
+    ///
+    /// The `PSUBSCRIBE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// PSUBSCRIBE pattern [pattern ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<PSubscribe> {
+        Ok(PSubscribe {
+            patterns: parse.next_strings()?,
+        })
+    }
+
+    /// Apply the `PSubscribe` command to the connection's subscriptions,
+    /// returning one confirmation per pattern.
+    pub fn apply(self, subscriptions: &mut Subscriptions) -> Vec<Frame> {
+        subscriptions.psubscribe(&self.patterns)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("psubscribe".as_bytes()));
+        for pattern in self.patterns {
+            frame.push_bulk(Bytes::from(pattern.into_bytes()));
+        }
+        frame
+    }
+}
+
+impl PUnsubscribe {
+    /// Create a new `PUnsubscribe` command with the given `patterns`.
+    pub fn new(patterns: &[String]) -> PUnsubscribe {
+        PUnsubscribe {
+            patterns: patterns.to_vec(),
+        }
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// Parse a `PUnsubscribe` instance from a received frame.
+    ///
+    /// The `PUNSUBSCRIBE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// PUNSUBSCRIBE [pattern [pattern ...]]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<PUnsubscribe> {
+        let mut patterns = vec![];
+
+        loop {
+            match parse.next_string() {
+                Ok(s) => patterns.push(s),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(PUnsubscribe { patterns })
+    }
+
+    /// Apply the `PUnsubscribe` command to the connection's subscriptions,
+    /// returning one confirmation per pattern.
+    pub fn apply(self, subscriptions: &mut Subscriptions) -> Vec<Frame> {
+        subscriptions.punsubscribe(&self.patterns)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("punsubscribe".as_bytes()));
+        for pattern in self.patterns {
+            frame.push_bulk(Bytes::from(pattern.into_bytes()));
+        }
+        frame
+    }
+}
@@ -0,0 +1,124 @@
+use crate::minis_redis::db::{Db, Guard};
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+
+use bytes::Bytes;
+use tokio::time::Instant;
+
+/// Returns the remaining time to live of `key`, in seconds.
+///
+/// The reply is -2 if `key` does not exist and -1 if it exists but has no
+/// associated expire.
+#[derive(Debug)]
+pub struct Ttl {
+    key: String,
+}
+
+/// Like `Ttl`, but the time to live is in milliseconds.
+#[derive(Debug)]
+pub struct PTtl {
+    key: String,
+}
+
+impl Ttl {
+    /// Create a new `Ttl` command for `key`.
+    pub fn new(key: impl ToString) -> Ttl {
+        Ttl { key: key.to_string() }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `Ttl` instance from a received frame.
+    ///
+    /// The `TTL` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// TTL key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<Ttl> {
+        Ok(Ttl {
+            key: parse.next_string()?,
+        })
+    }
+
+    /// Apply the `Ttl` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `Ttl` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        // 和redis一样四舍五入到秒
+        Frame::Integer(match ttl_millis(guard, &self.key) {
+            ms if ms >= 0 => (ms + 500) / 1000,
+            ms => ms,
+        })
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("ttl".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl PTtl {
+    /// Create a new `PTtl` command for `key`.
+    pub fn new(key: impl ToString) -> PTtl {
+        PTtl { key: key.to_string() }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `PTtl` instance from a received frame.
+    ///
+    /// The `PTTL` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// PTTL key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<PTtl> {
+        Ok(PTtl {
+            key: parse.next_string()?,
+        })
+    }
+
+    /// Apply the `PTtl` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `PTtl` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        Frame::Integer(ttl_millis(guard, &self.key))
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("pttl".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+/// 剩余的毫秒数, key不存在时是-2, 没有过期时间时是-1
+fn ttl_millis(guard: &Guard<'_>, key: &str) -> i64 {
+    match guard.expires_at(key) {
+        None => -2,
+        Some(None) => -1,
+        Some(Some(when)) => when.saturating_duration_since(Instant::now()).as_millis() as i64,
+    }
+}
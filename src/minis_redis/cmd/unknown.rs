@@ -0,0 +1,27 @@
+use crate::minis_redis::frame::Frame;
+
+/// Represents an "unknown" command. This is not a real `Redis` command.
+#[derive(Debug)]
+pub struct Unknown {
+    command_name: String,
+}
+
+impl Unknown {
+    /// Create a new `Unknown` command which responds to unknown commands
+    /// issued by clients
+    pub(crate) fn new(key: impl ToString) -> Unknown {
+        Unknown {
+            command_name: key.to_string(),
+        }
+    }
+
+    /// Returns the command name
+    pub fn get_name(&self) -> &str {
+        &self.command_name
+    }
+
+    /// The reply to this command.
+    pub fn response(self) -> Frame {
+        Frame::Error(format!("ERR unknown command '{}'", self.command_name))
+    }
+}
@@ -0,0 +1,92 @@
+use crate::minis_redis::frame::Frame;
+use crate::minis_redis::parse::Parse;
+use crate::minis_redis::transaction::Transaction;
+
+use bytes::Bytes;
+
+/// Marks the given keys to be watched for the next transaction.
+///
+/// If any of them is modified, deleted or expires before `EXEC`, the
+/// transaction is aborted. Watching is optimistic: the keys are not locked
+/// until `EXEC`.
+#[derive(Debug)]
+pub struct Watch {
+    keys: Vec<String>,
+}
+
+/// Forgets all the keys watched by the connection.
+#[derive(Debug, Default)]
+pub struct Unwatch;
+
+impl Watch {
+    /// Create a new `Watch` command which watches `keys`.
+    pub fn new(keys: &[String]) -> Watch {
+        Watch { keys: keys.to_vec() }
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse a `Watch` instance from a received frame.
+    ///
+    /// The `WATCH` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// WATCH key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<Watch> {
+        Ok(Watch {
+            keys: parse.next_strings()?,
+        })
+    }
+
+    /// Apply the `Watch` command to the transaction of the connection.
+    pub fn apply(self, transaction: &mut Transaction) -> Frame {
+        transaction.watch(&self.keys)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("watch".as_bytes()));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key.into_bytes()));
+        }
+        frame
+    }
+}
+
+impl Unwatch {
+    pub fn new() -> Unwatch {
+        Unwatch
+    }
+
+    /// Parse an `Unwatch` instance from a received frame.
+    ///
+    /// The `UNWATCH` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// UNWATCH
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::minis_redis::Result<Unwatch> {
+        Ok(Unwatch)
+    }
+
+    /// Apply the `Unwatch` command to the transaction of the connection.
+    pub fn apply(self, transaction: &mut Transaction) -> Frame {
+        transaction.unwatch();
+        Frame::Simple("OK".to_string())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("unwatch".as_bytes()));
+        frame
+    }
+}
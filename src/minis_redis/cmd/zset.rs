@@ -0,0 +1,495 @@
+use crate::minis_redis::cmd::lrange::index_range;
+use crate::minis_redis::db::{Db, Guard};
+use crate::minis_redis::frame::{format_double, Frame};
+use crate::minis_redis::parse::{Parse, ParseError};
+
+use bytes::Bytes;
+use std::ops::Bound;
+
+/// Adds the given members with their scores to the sorted set stored at
+/// `key`, creating the sorted set if `key` does not exist.
+///
+/// The reply is the number of members that were added, members whose score
+/// was updated are not counted.
+#[derive(Debug)]
+pub struct ZAdd {
+    key: String,
+    members: Vec<(f64, Bytes)>,
+}
+
+/// Returns the members of the sorted set stored at `key` whose rank is
+/// between `start` and `stop`, both inclusive. Negative indexes count from
+/// the highest score.
+#[derive(Debug)]
+pub struct ZRange {
+    key: String,
+    start: i64,
+    stop: i64,
+    with_scores: bool,
+}
+
+/// Returns the members of the sorted set stored at `key` whose score is
+/// between `min` and `max`, ordered from the lowest score.
+#[derive(Debug)]
+pub struct ZRangeByScore {
+    key: String,
+    min: Bound<f64>,
+    max: Bound<f64>,
+    with_scores: bool,
+}
+
+/// Returns the rank of `member` in the sorted set stored at `key`, the
+/// member with the lowest score having rank 0.
+#[derive(Debug)]
+pub struct ZRank {
+    key: String,
+    member: Bytes,
+}
+
+/// Adds `increment` to the score of `member` in the sorted set stored at
+/// `key`. A missing member is added with `increment` as its score.
+#[derive(Debug)]
+pub struct ZIncrBy {
+    key: String,
+    increment: f64,
+    member: Bytes,
+}
+
+impl ZAdd {
+    /// Create a new `ZAdd` command which adds every `(score, member)` pair.
+    pub fn new(key: impl ToString, members: &[(f64, Bytes)]) -> ZAdd {
+        ZAdd {
+            key: key.to_string(),
+            members: members.to_vec(),
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZAdd` instance from a received frame.
+    ///
+    /// The `ZADD` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// ZADD key score member [score member ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<ZAdd> {
+        let key = parse.next_string()?;
+        let mut members = vec![(parse_score(&parse.next_string()?)?, parse.next_bytes()?)];
+
+        loop {
+            match parse.next_string() {
+                Ok(score) => members.push((parse_score(&score)?, parse.next_bytes()?)),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(ZAdd { key, members })
+    }
+
+    /// Apply the `ZAdd` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `ZAdd` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        let zset = match guard.zset_or_default(&self.key) {
+            Ok(zset) => zset,
+            Err(err) => return err.into(),
+        };
+        let added = self
+            .members
+            .iter()
+            .filter(|(score, member)| zset.insert(member.clone(), *score))
+            .count();
+
+        guard.log(|| self.into_frame());
+        Frame::Integer(added as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zadd".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        for (score, member) in self.members {
+            frame.push_bulk(Bytes::from(format_double(score)));
+            frame.push_bulk(member);
+        }
+        frame
+    }
+}
+
+impl ZRange {
+    /// Create a new `ZRange` command for the ranks `start..=stop` of `key`.
+    pub fn new(key: impl ToString, start: i64, stop: i64, with_scores: bool) -> ZRange {
+        ZRange {
+            key: key.to_string(),
+            start,
+            stop,
+            with_scores,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZRange` instance from a received frame.
+    ///
+    /// The `ZRANGE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// ZRANGE key start stop [WITHSCORES]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<ZRange> {
+        let key = parse.next_string()?;
+        let start = parse.next_int()?;
+        let stop = parse.next_int()?;
+        let with_scores = parse_with_scores(parse)?;
+
+        Ok(ZRange {
+            key,
+            start,
+            stop,
+            with_scores,
+        })
+    }
+
+    /// Apply the `ZRange` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `ZRange` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        let zset = match guard.zset(&self.key) {
+            Ok(Some(zset)) => zset,
+            Ok(None) => return Frame::Array(vec![]),
+            Err(err) => return err.into(),
+        };
+
+        match index_range(self.start, self.stop, zset.len()) {
+            Some((start, stop)) => members_frame(zset.range_by_rank(start, stop), self.with_scores),
+            None => Frame::Array(vec![]),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zrange".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.start.to_string()));
+        frame.push_bulk(Bytes::from(self.stop.to_string()));
+        if self.with_scores {
+            frame.push_bulk(Bytes::from("withscores".as_bytes()));
+        }
+        frame
+    }
+}
+
+impl ZRangeByScore {
+    /// Create a new `ZRangeByScore` command for the scores between `min` and
+    /// `max` of `key`.
+    pub fn new(key: impl ToString, min: Bound<f64>, max: Bound<f64>, with_scores: bool) -> ZRangeByScore {
+        ZRangeByScore {
+            key: key.to_string(),
+            min,
+            max,
+            with_scores,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZRangeByScore` instance from a received frame.
+    ///
+    /// The `ZRANGEBYSCORE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// ZRANGEBYSCORE key min max [WITHSCORES]
+    /// ```
+    ///
+    /// A bound prefixed with `(` is exclusive, `-inf` and `+inf` are
+    /// accepted.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<ZRangeByScore> {
+        let key = parse.next_string()?;
+        let min = parse_bound(&parse.next_string()?)?;
+        let max = parse_bound(&parse.next_string()?)?;
+        let with_scores = parse_with_scores(parse)?;
+
+        Ok(ZRangeByScore {
+            key,
+            min,
+            max,
+            with_scores,
+        })
+    }
+
+    /// Apply the `ZRangeByScore` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `ZRangeByScore` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        match guard.zset(&self.key) {
+            Ok(Some(zset)) => members_frame(zset.range_by_score(self.min, self.max), self.with_scores),
+            Ok(None) => Frame::Array(vec![]),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zrangebyscore".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(format_bound(self.min, "-inf")));
+        frame.push_bulk(Bytes::from(format_bound(self.max, "+inf")));
+        if self.with_scores {
+            frame.push_bulk(Bytes::from("withscores".as_bytes()));
+        }
+        frame
+    }
+}
+
+impl ZRank {
+    /// Create a new `ZRank` command which looks up `member` in `key`.
+    pub fn new(key: impl ToString, member: Bytes) -> ZRank {
+        ZRank {
+            key: key.to_string(),
+            member,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZRank` instance from a received frame.
+    ///
+    /// The `ZRANK` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// ZRANK key member
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<ZRank> {
+        let key = parse.next_string()?;
+        let member = parse.next_bytes()?;
+
+        Ok(ZRank { key, member })
+    }
+
+    /// Apply the `ZRank` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `ZRank` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        match guard.zset(&self.key) {
+            Ok(zset) => match zset.and_then(|zset| zset.rank(&self.member)) {
+                Some(rank) => Frame::Integer(rank as i64),
+                None => Frame::Null,
+            },
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zrank".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(self.member);
+        frame
+    }
+}
+
+impl ZIncrBy {
+    /// Create a new `ZIncrBy` command which adds `increment` to the score of
+    /// `member` in `key`.
+    pub fn new(key: impl ToString, increment: f64, member: Bytes) -> ZIncrBy {
+        ZIncrBy {
+            key: key.to_string(),
+            increment,
+            member,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZIncrBy` instance from a received frame.
+    ///
+    /// The `ZINCRBY` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// ZINCRBY key increment member
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::minis_redis::Result<ZIncrBy> {
+        let key = parse.next_string()?;
+        let increment = parse_score(&parse.next_string()?)?;
+        let member = parse.next_bytes()?;
+
+        Ok(ZIncrBy { key, increment, member })
+    }
+
+    /// Apply the `ZIncrBy` command to the specified `Db` instance.
+    pub fn apply(self, db: &Db) -> Frame {
+        let mut guard = db.lock([&self.key]);
+        self.execute(&mut guard)
+    }
+
+    /// Apply the `ZIncrBy` command while the shards of its keys are already locked.
+    pub(crate) fn execute(self, guard: &mut Guard<'_>) -> Frame {
+        let zset = match guard.zset_or_default(&self.key) {
+            Ok(zset) => zset,
+            Err(err) => return err.into(),
+        };
+        // inf 加 -inf 得到NaN, 这时成员一定已经存在, 不会留下空的集合
+        let score = zset.score(&self.member).unwrap_or(0.0) + self.increment;
+        if score.is_nan() {
+            return Frame::Error("ERR resulting score is not a number (NaN)".to_string());
+        }
+        zset.insert(self.member.clone(), score);
+
+        // 和HINCRBY一样, 记成执行之后的结果
+        guard.log(|| ZAdd::new(&self.key, &[(score, self.member)]).into_frame());
+        Frame::Double(score)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zincrby".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(format_double(self.increment)));
+        frame.push_bulk(self.member);
+        frame
+    }
+}
+
+/// 解析分数, 接受 `inf`、`-inf` 这样的写法, 但不能是NaN
+fn parse_score(src: &str) -> crate::minis_redis::Result<f64> {
+    match src.parse::<f64>() {
+        Ok(score) if !score.is_nan() => Ok(score),
+        _ => Err("value is not a valid float".into()),
+    }
+}
+
+/// ZRANGEBYSCORE 的范围, `(` 开头的是开区间
+fn parse_bound(src: &str) -> crate::minis_redis::Result<Bound<f64>> {
+    let (exclusive, score) = match src.strip_prefix('(') {
+        Some(score) => (true, score),
+        None => (false, src),
+    };
+    match score.parse::<f64>() {
+        Ok(score) if score.is_nan() => Err("min or max is not a float".into()),
+        Ok(score) if exclusive => Ok(Bound::Excluded(score)),
+        Ok(score) => Ok(Bound::Included(score)),
+        Err(_) => Err("min or max is not a float".into()),
+    }
+}
+
+fn format_bound(bound: Bound<f64>, unbounded: &str) -> String {
+    match bound {
+        Bound::Included(score) => format_double(score),
+        Bound::Excluded(score) => format!("({}", format_double(score)),
+        Bound::Unbounded => unbounded.to_string(),
+    }
+}
+
+/// 可选的WITHSCORES参数
+fn parse_with_scores(parse: &mut Parse) -> crate::minis_redis::Result<bool> {
+    match parse.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("withscores") => Ok(true),
+        Ok(_) => Err("syntax error".into()),
+        Err(ParseError::EndOfStream) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// 成员列表, 带WITHSCORES时每个成员后面跟着它的分数
+fn members_frame<'a>(members: impl Iterator<Item = (&'a Bytes, f64)>, with_scores: bool) -> Frame {
+    let mut frames = vec![];
+    for (member, score) in members {
+        frames.push(Frame::Bulk(member.clone()));
+        if with_scores {
+            frames.push(Frame::Double(score));
+        }
+    }
+    Frame::Array(frames)
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::minis_redis::cmd::Command;
+    use crate::minis_redis::db::Db;
+    use crate::minis_redis::frame::Frame;
+    use crate::minis_redis::test_util::{command, run};
+
+    #[test]
+    fn sorted_set_commands() {
+        let db = Db::new(4);
+        let bulk = |s: &'static str| Frame::Bulk(Bytes::from(s));
+        let wrong_type = Frame::Error("WRONGTYPE Operation against a key holding the wrong kind of value".to_string());
+
+        assert_eq!(run(&db, &["zadd", "z", "2", "b", "1", "a", "3", "c"]), Frame::Integer(3));
+        assert_eq!(run(&db, &["zadd", "z", "0", "c", "4", "d"]), Frame::Integer(1));
+        let zrange = run(&db, &["zrange", "z", "0", "-1"]);
+        assert_eq!(zrange, Frame::Array(vec![bulk("c"), bulk("a"), bulk("b"), bulk("d")]));
+        assert_eq!(
+            run(&db, &["zrange", "z", "-2", "-1", "withscores"]),
+            Frame::Array(vec![bulk("b"), Frame::Double(2.0), bulk("d"), Frame::Double(4.0)])
+        );
+        assert_eq!(
+            run(&db, &["zrangebyscore", "z", "(1", "+inf"]),
+            Frame::Array(vec![bulk("b"), bulk("d")])
+        );
+        assert_eq!(run(&db, &["zrangebyscore", "z", "-inf", "(0"]), Frame::Array(vec![]));
+        assert_eq!(run(&db, &["zrank", "z", "b"]), Frame::Integer(2));
+        assert_eq!(run(&db, &["zrank", "z", "x"]), Frame::Null);
+        assert_eq!(run(&db, &["zincrby", "z", "2.5", "a"]), Frame::Double(3.5));
+        assert_eq!(run(&db, &["zrank", "z", "a"]), Frame::Integer(2));
+        run(&db, &["zincrby", "z", "inf", "a"]);
+        assert_eq!(
+            run(&db, &["zincrby", "z", "-inf", "a"]),
+            Frame::Error("ERR resulting score is not a number (NaN)".to_string())
+        );
+        let err = Command::from_frame(command(&["zadd", "z", "nan", "a"])).unwrap_err();
+        assert_eq!(err.to_string(), "ERR value is not a valid float");
+        let err = Command::from_frame(command(&["zrangebyscore", "z", "x", "1"])).unwrap_err();
+        assert_eq!(err.to_string(), "ERR min or max is not a float");
+
+        run(&db, &["set", "str", "v"]);
+        run(&db, &["sadd", "s", "a"]);
+        assert_eq!(run(&db, &["zrange", "s", "0", "-1"]), wrong_type);
+        assert_eq!(run(&db, &["get", "z"]), wrong_type);
+    }
+}
@@ -1,65 +1,424 @@
-use std::{error::Error, io::Cursor, ops::Deref};
+use std::{future::Future, io::Cursor, pin::Pin, slice, sync::Arc, task::{Context, Poll}, time::Duration};
 
+use tokio::{net::{ TcpStream, ToSocketAddrs }, io::{self, AsyncRead, AsyncReadExt, AsyncWrite, BufWriter, AsyncWriteExt, ReadHalf, WriteHalf}};
+use tokio::time::{self, Instant};
+use bytes::{Buf, BytesMut};
 
-use mini_redis::{ Result, frame::{ self, Frame } };
-use tokio::{net::{ TcpStream }, io::{self, AsyncReadExt, BufWriter, AsyncWriteExt}};
-use bytes::{self, BytesMut, BufMut, Buf};
+use super::pool::{BufferPool, PooledBuffer};
+use super::stats::Stats;
+use super::frame::{blank_inline_line, format_double, get_bulk_header, Error, Frame, Limits, Protocol, TimeoutKind};
 
-pub struct Connection {
-    // stream: TcpStream,
+/// 流式读写bulk string时每次搬运的数据块大小
+const STREAM_CHUNK: usize = 64 * 1024;
+
+/// 在任意双向字节流上收发frame, 例如 `TcpStream`、`UnixStream`、
+/// `tokio::io::duplex` 或者包装过的加密流。默认是 `TcpStream`
+pub struct Connection<S = TcpStream> {
     /// 缓冲写: 为减少syscall, 会把数据写入内部缓冲；
     /// 但是有些情况会绕过缓冲直接写入socket,例如数据量较大的情况,因为复制数据到缓冲耗费性能
-    stream: BufWriter<TcpStream>,
-    buffer: BytesMut,
+    stream: BufWriter<S>,
+    /// 读缓冲, 从 `BufferPool` 中借来
+    buffer: PooledBuffer,
+    /// 通过 `HELLO` 协商的协议版本, 默认RESP2
+    protocol: Protocol,
+    /// 读写frame时的各项上限
+    limits: Limits,
+    timeouts: Timeouts,
+    stats: Arc<Stats>,
+}
+
+/// 连接的超时设置, 默认都是None, 即一直等待。
+///
+/// 超时之后读返回 `Error::Timeout`, 写返回 `io::ErrorKind::TimedOut`。
+/// 此时可能已经读了或者写了半个frame, 连接不能再继续使用, 应当关闭
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timeouts {
+    /// 空闲超时: 等待下一个frame的第一个字节最多等多久
+    pub idle: Option<Duration>,
+    /// 读超时: 收到一个frame的第一个字节之后, 整个frame要在这个时间内到齐
+    pub read: Option<Duration>,
+    /// 写超时: 每次写操作(`write_frame`、`write_frames`、`feed_frame`、`flush`)
+    /// 最多花多长时间, 防止对端一直不读导致写阻塞
+    pub write: Option<Duration>,
+}
+
+/// `Connection` 的读半边, 由 `Connection::into_split` 得到。
+///
+/// 和写半边可以放在不同的task里, 例如pub/sub中一个task读命令, 另一个task推送消息
+pub struct FrameReader<R> {
+    stream: R,
+    buffer: PooledBuffer,
+    limits: Limits,
+    timeouts: Timeouts,
+    stats: Arc<Stats>,
+}
+
+/// `Connection` 的写半边, 由 `Connection::into_split` 得到
+pub struct FrameWriter<W> {
+    stream: BufWriter<W>,
+    protocol: Protocol,
+    limits: Limits,
+    timeouts: Timeouts,
+    stats: Arc<Stats>,
+}
+
+/// 从字节流中读取并解析frame, 由 `Connection` 和 `FrameReader` 共用
+struct Decoder<'a, R> {
+    stream: &'a mut R,
+    buffer: &'a mut PooledBuffer,
+    limits: &'a Limits,
+    timeouts: &'a Timeouts,
+    stats: &'a Stats,
 }
 
-impl Connection {
-    pub fn new(stream: TcpStream) -> Connection {
+/// 把frame编码写入字节流, 由 `Connection` 和 `FrameWriter` 共用
+struct Encoder<'a, W> {
+    stream: Counted<'a, W>,
+    protocol: Protocol,
+    limits: &'a Limits,
+    timeout: Option<Duration>,
+}
+
+impl Connection<TcpStream> {
+    /// 连接到 `addr` 并在这个tcp连接上创建Connection
+    pub async fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Connection> {
+        let stream = TcpStream::connect(addr).await?;
+        Ok(Connection::new(stream))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    pub fn new(stream: S) -> Connection<S> {
+        Connection::with_limits(stream, Limits::default())
+    }
+
+    /// 使用自定义的上限创建Connection
+    pub fn with_limits(stream: S, limits: Limits) -> Connection<S> {
+        Connection::with_pool(stream, limits, BufferPool::default())
+    }
+
+    /// 读缓冲从 `pool` 中借, 连接关闭时还回去。
+    ///
+    /// 服务端让所有连接共用一个pool, 就可以复用缓冲并统计全部连接的缓冲大小
+    pub fn with_pool(stream: S, limits: Limits, pool: BufferPool) -> Connection<S> {
         Connection {
             stream: BufWriter::new(stream),
-            buffer: BytesMut::with_capacity(1024),
+            buffer: pool.get(),
+            protocol: Protocol::default(),
+            limits,
+            timeouts: Timeouts::default(),
+            stats: Arc::new(Stats::new()),
         }
     }
 
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn timeouts(&self) -> &Timeouts {
+        &self.timeouts
+    }
+
+    /// 连接的流量统计。可以clone一份 `Arc` 交给别的task, 随时调用 `snapshot` 查看;
+    /// `into_split` 之后读写两个半边仍然共用这一份
+    pub fn stats(&self) -> &Arc<Stats> {
+        &self.stats
+    }
+
+    /// 修改超时设置, 对之后开始的读写生效
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
+
+    /// 当前连接使用的协议版本
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// 切换协议版本, 之后写出的frame都按新协议编码
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+
+    /// 拆分成可以分别放在不同task中的读半边和写半边。
+    ///
+    /// 写缓冲中还没发出去的数据会先flush, 读缓冲中已经收到还没解析的数据
+    /// 会交给读半边, 所以拆分前后收发的字节流是连续的
+    pub async fn into_split(mut self) -> io::Result<(FrameReader<ReadHalf<S>>, FrameWriter<WriteHalf<S>>)> {
+        self.stream.flush().await?;
+        let (read, write) = io::split(self.stream.into_inner());
+
+        let reader = FrameReader {
+            stream: read,
+            buffer: self.buffer,
+            limits: self.limits,
+            timeouts: self.timeouts,
+            stats: self.stats.clone(),
+        };
+        let writer = FrameWriter {
+            stream: BufWriter::new(write),
+            protocol: self.protocol,
+            limits: self.limits,
+            timeouts: self.timeouts,
+            stats: self.stats,
+        };
+        Ok((reader, writer))
+    }
+
+    /// 写入一个frame并flush到socket
     pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
-        // Arrays are encoded by encoding each entry. All other frame types are
-        // considered literals. For now, mini-redis is not able to encode
-        // recursive frame structures. See below for more details.
-        match frame {
-            Frame::Array(val) => {
-                // Encode the frame type prefix. For an array, it is `*`.
-                self.stream.write_u8(b'*').await?;
+        self.encoder().write_frame(frame).await
+    }
 
-                // Encode the length of the array.
-                self.write_decimal(val.len() as u64).await?;
+    /// 把多个frame编码进写缓冲, 最后只flush一次。
+    ///
+    /// 用于pipeline: 一批请求的回复合并成尽量少的syscall写出。
+    /// 所有frame都先检查一遍, 有不合法的就一个都不写
+    pub async fn write_frames(&mut self, frames: &[Frame]) -> io::Result<()> {
+        self.encoder().write_frames(frames).await
+    }
 
-                // Iterate and encode each entry in the array.
-                for entry in &**val {
-                    self.write_value(entry).await?;
-                }
-            }
-            // The frame type is a literal. Encode the value directly.
-            _ => self.write_value(frame).await?,
+    /// 只把frame编码进写缓冲, 不flush; 之后需要调用 `flush`。
+    ///
+    /// 缓冲满了的时候数据仍然会被写到socket
+    pub async fn feed_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.encoder().feed_frame(frame).await
+    }
+
+    /// 把写缓冲中的数据全部写到socket
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.encoder().flush().await
+    }
+
+    /// 读取一个完整的frame, 对端正常关闭连接时返回 `None`
+    ///
+    /// 数据不合法返回 `ProtocolViolation`, 超过 `Limits` 返回 `FrameTooLarge`,
+    /// frame读到一半连接断开返回 `ConnectionReset`。出错后连接中剩余的数据
+    /// 已经无法可靠地分帧, 调用方应当关闭连接
+    pub async fn read_frame(&mut self) -> Result<Option<Frame>, Error> {
+        self.decoder().read_frame().await
+    }
+
+    /// 只从已经读到缓冲中的数据里解析frame, 不会读socket。
+    ///
+    /// pipeline的客户端一次会发来多个请求, 服务端可以用它把已经到达的请求
+    /// 都处理完, 再用 `write_frames` 一次性写回所有回复
+    pub fn try_read_frame(&mut self) -> Result<Option<Frame>, Error> {
+        self.decoder().parse_frame()
+    }
+
+    /// 从socket再读一些数据到读缓冲, 不解析也不受空闲超时的限制, 返回读到的字节数,
+    /// 0表示对端关闭了连接。
+    ///
+    /// 连接阻塞在BLPOP之类的命令上时用它发现客户端断开, 期间发来的请求留在缓冲里,
+    /// 之后还能用 `read_frame` 和 `try_read_frame` 读出来
+    pub async fn fill_buffer(&mut self) -> Result<usize, Error> {
+        self.decoder().fill(None, TimeoutKind::Idle).await
+    }
+
+    /// 流式读取一个bulk string的第一步: 只读取头部 `$<len>\r\n`, 返回内容的长度,
+    /// null bulk string返回None。
+    ///
+    /// 之后必须用 `copy_bulk_to` 把内容读走, 才能继续读下一个frame。
+    /// 内容不会进入内存, 所以不受 `max_bulk_len` 的限制。
+    /// 下一个frame不是bulk string时返回 `ProtocolViolation`
+    pub async fn read_bulk_header(&mut self) -> Result<Option<u64>, Error> {
+        self.decoder().read_bulk_header().await
+    }
+
+    /// 把 `read_bulk_header` 读到的bulk string的 `len` 字节内容分块复制到 `dst`,
+    /// 例如一个文件。同时会读掉结尾的 `\r\n`。
+    ///
+    /// 读超时对每一块数据分别生效
+    pub async fn copy_bulk_to<W>(&mut self, len: u64, dst: &mut W) -> Result<(), Error>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        self.decoder().copy_bulk_to(len, dst).await
+    }
+
+    /// 把 `src` 中的 `len` 字节作为一个bulk string分块写出去并flush,
+    /// 内容不需要先全部读进内存。
+    ///
+    /// `src` 的数据不足 `len` 字节时返回 `UnexpectedEof`, 这时已经写出了半个frame,
+    /// 连接需要关闭。写超时对每一块数据分别生效
+    pub async fn write_bulk_from<R>(&mut self, len: u64, src: &mut R) -> io::Result<()>
+    where
+        R: AsyncRead + Unpin,
+    {
+        self.encoder().write_bulk_from(len, src).await
+    }
+
+    fn decoder(&mut self) -> Decoder<'_, BufWriter<S>> {
+        Decoder {
+            stream: &mut self.stream,
+            buffer: &mut self.buffer,
+            limits: &self.limits,
+            timeouts: &self.timeouts,
+            stats: &self.stats,
+        }
+    }
+
+    fn encoder(&mut self) -> Encoder<'_, BufWriter<S>> {
+        Encoder {
+            stream: Counted {
+                inner: &mut self.stream,
+                stats: &self.stats,
+            },
+            protocol: self.protocol,
+            limits: &self.limits,
+            timeout: self.timeouts.write,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> FrameReader<R> {
+    /// 在单独的读取流上创建, 例如 `TcpStream::into_split` 得到的 `OwnedReadHalf`
+    pub fn new(stream: R, limits: Limits) -> FrameReader<R> {
+        FrameReader {
+            stream,
+            buffer: BufferPool::default().get(),
+            limits,
+            timeouts: Timeouts::default(),
+            stats: Arc::new(Stats::new()),
+        }
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// 同 `Connection::stats`
+    pub fn stats(&self) -> &Arc<Stats> {
+        &self.stats
+    }
+
+    /// 只有 `idle` 和 `read` 对读半边有效
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
+
+    /// 同 `Connection::read_frame`
+    pub async fn read_frame(&mut self) -> Result<Option<Frame>, Error> {
+        self.decoder().read_frame().await
+    }
+
+    /// 同 `Connection::try_read_frame`
+    pub fn try_read_frame(&mut self) -> Result<Option<Frame>, Error> {
+        self.decoder().parse_frame()
+    }
+
+    /// 同 `Connection::read_bulk_header`
+    pub async fn read_bulk_header(&mut self) -> Result<Option<u64>, Error> {
+        self.decoder().read_bulk_header().await
+    }
+
+    /// 同 `Connection::copy_bulk_to`
+    pub async fn copy_bulk_to<W>(&mut self, len: u64, dst: &mut W) -> Result<(), Error>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        self.decoder().copy_bulk_to(len, dst).await
+    }
+
+    fn decoder(&mut self) -> Decoder<'_, R> {
+        Decoder {
+            stream: &mut self.stream,
+            buffer: &mut self.buffer,
+            limits: &self.limits,
+            timeouts: &self.timeouts,
+            stats: &self.stats,
         }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> FrameWriter<W> {
+    /// 在单独的写入流上创建, 例如 `TcpStream::into_split` 得到的 `OwnedWriteHalf`
+    pub fn new(stream: W, limits: Limits) -> FrameWriter<W> {
+        FrameWriter {
+            stream: BufWriter::new(stream),
+            protocol: Protocol::default(),
+            limits,
+            timeouts: Timeouts::default(),
+            stats: Arc::new(Stats::new()),
+        }
+    }
 
-        // Ensure the encoded frame is written to the socket. The calls above
-        // are to the buffered stream and writes. Calling `flush` writes the
-        // remaining contents of the buffer to the socket.
-        self.stream.flush().await
+    /// 同 `Connection::stats`
+    pub fn stats(&self) -> &Arc<Stats> {
+        &self.stats
     }
 
-    pub async fn read_frame(&mut self) -> Result<Option<Frame>> {
+    /// 只有 `write` 对写半边有效
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
+
+    /// 当前使用的协议版本
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// 切换协议版本, 之后写出的frame都按新协议编码
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+
+    /// 同 `Connection::write_frame`
+    pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.encoder().write_frame(frame).await
+    }
+
+    /// 同 `Connection::write_frames`
+    pub async fn write_frames(&mut self, frames: &[Frame]) -> io::Result<()> {
+        self.encoder().write_frames(frames).await
+    }
+
+    /// 同 `Connection::feed_frame`
+    pub async fn feed_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.encoder().feed_frame(frame).await
+    }
+
+    /// 把写缓冲中的数据全部写到socket
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.encoder().flush().await
+    }
+
+    /// 同 `Connection::write_bulk_from`
+    pub async fn write_bulk_from<R>(&mut self, len: u64, src: &mut R) -> io::Result<()>
+    where
+        R: AsyncRead + Unpin,
+    {
+        self.encoder().write_bulk_from(len, src).await
+    }
+
+    fn encoder(&mut self) -> Encoder<'_, BufWriter<W>> {
+        Encoder {
+            stream: Counted {
+                inner: &mut self.stream,
+                stats: &self.stats,
+            },
+            protocol: self.protocol,
+            limits: &self.limits,
+            timeout: self.timeouts.write,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> Decoder<'_, R> {
+    async fn read_frame(&mut self) -> Result<Option<Frame>, Error> {
+        // 当前frame必须在这个时间之前读完, 收到它的第一个字节时才开始计时
+        let mut deadline = None;
+
         loop {
             if let Some(frame) = self.parse_frame()? {
                 return Ok(Some(frame))
             }
 
-            // 不停的从socket中读取数据，直到能返回一个成功解析的frame 或者 连接断开
-            // note:用read_buf不用read, 因为read_buf方法内部会advancing the buffer's internal cursor
-            let n = self.stream.read_buf(&mut self.buffer).await?;
-            if n == 0 { // end of file: tcp连接断开
-                if self.buffer.len() > 0 {
-                    return Err("Conn Reset By Peer".into())
+            let (until, kind) = self.next_deadline(&mut deadline);
+            if self.fill(until, kind).await? == 0 { // end of file: tcp连接断开
+                if !self.buffer.is_empty() {
+                    return Err(Error::ConnectionReset)
                 } else {
                     // peer closed normally
                     return Ok(None)
@@ -68,22 +427,307 @@ impl Connection {
         }
     }
 
-    fn parse_frame(&mut self) -> Result<Option<Frame>> {
-        let mut buf = Cursor::new(&self.buffer[..]);
-        match Frame::check(&mut buf) {
-            Err(frame::Error::Incomplete) => Ok(None),
+    async fn read_bulk_header(&mut self) -> Result<Option<u64>, Error> {
+        let mut deadline = None;
+
+        loop {
+            let mut buf = Cursor::new(&self.buffer[..]);
+            match get_bulk_header(&mut buf) {
+                Ok(len) => {
+                    let n = buf.position() as usize;
+                    self.buffer.advance(n);
+                    self.stats.frame_read();
+                    return Ok(len);
+                }
+                Err(Error::Incomplete) => {}
+                Err(e) => {
+                    self.stats.parse_error();
+                    return Err(e);
+                }
+            }
+
+            let (until, kind) = self.next_deadline(&mut deadline);
+            // 调用方在等一个bulk string, 连接断开都算异常
+            if self.fill(until, kind).await? == 0 {
+                return Err(Error::ConnectionReset);
+            }
+        }
+    }
+
+    async fn copy_bulk_to<W: AsyncWrite + Unpin>(&mut self, len: u64, dst: &mut W) -> Result<(), Error> {
+        let mut remaining = len;
+        while remaining > 0 {
+            if self.buffer.is_empty() {
+                // 读缓冲在上一块写出去之后已经空了, 预留一整块, 避免每次只读很少的数据
+                self.buffer.reserve(STREAM_CHUNK);
+                self.fill_payload().await?;
+            }
+            let n = remaining.min(self.buffer.len() as u64) as usize;
+            dst.write_all(&self.buffer[..n]).await?;
+            self.buffer.advance(n);
+            remaining -= n as u64;
+        }
+
+        while self.buffer.len() < 2 {
+            self.fill_payload().await?;
+        }
+        if &self.buffer[..2] != b"\r\n" {
+            self.stats.parse_error();
+            return Err("protocol error; invalid frame format".into());
+        }
+        self.buffer.advance(2);
+        self.buffer.sync();
+        Ok(())
+    }
+
+    /// 缓冲为空说明还在等下一个frame, 适用空闲超时; 否则适用读超时。
+    /// `deadline` 是当前frame的截止时间, 第一次需要时才计算
+    fn next_deadline(&self, deadline: &mut Option<Option<Instant>>) -> (Option<Instant>, TimeoutKind) {
+        if self.buffer.is_empty() {
+            (self.timeouts.idle.map(|idle| Instant::now() + idle), TimeoutKind::Idle)
+        } else {
+            let read = self.timeouts.read;
+            (*deadline.get_or_insert_with(|| read.map(|read| Instant::now() + read)), TimeoutKind::Read)
+        }
+    }
+
+    /// 读取bulk string内容的下一块, 连接断开说明这个frame不完整
+    async fn fill_payload(&mut self) -> Result<(), Error> {
+        let until = self.timeouts.read.map(|read| Instant::now() + read);
+        match self.fill(until, TimeoutKind::Read).await? {
+            0 => Err(Error::ConnectionReset),
+            _ => Ok(()),
+        }
+    }
+
+    /// 从socket再读一些数据到缓冲, 返回读到的字节数, 0表示对端关闭了连接
+    async fn fill(&mut self, until: Option<Instant>, kind: TimeoutKind) -> Result<usize, Error> {
+        // 还差数据才能组成完整的frame, 但缓冲已经到了上限:
+        // 对端在发送超大的数据或者一直发送没有换行的垃圾数据
+        if self.buffer.len() >= self.limits.max_buffer {
+            self.stats.parse_error();
+            return Err(Error::FrameTooLarge(format!(
+                "buffered {} bytes exceeds limit {}",
+                self.buffer.len(),
+                self.limits.max_buffer
+            )));
+        }
+
+        // 空闲等待下一个frame时, 如果缓冲被之前的大value撑大了, 先只等 `shrink_after`,
+        // 这段时间里一直没有数据就把它换回标准大小的缓冲, 然后再接着等
+        if let Some(shrink_after) = self.buffer.shrink_after() {
+            let shrink_at = Instant::now() + shrink_after;
+            if kind == TimeoutKind::Idle && until.is_none_or(|until| shrink_at < until) {
+                if let Some(res) = within(Some(shrink_at), self.stream.read_buf(&mut **self.buffer)).await {
+                    return self.filled(res);
+                }
+                self.buffer.shrink();
+            }
+        }
+
+        // 不停的从socket中读取数据，直到能返回一个成功解析的frame 或者 连接断开
+        // note:用read_buf不用read, 因为read_buf方法内部会advancing the buffer's internal cursor
+        let res = within(until, self.stream.read_buf(&mut **self.buffer)).await;
+        self.filled(res.ok_or(Error::Timeout(kind))?)
+    }
+
+    fn filled(&mut self, res: io::Result<usize>) -> Result<usize, Error> {
+        // 读的时候缓冲可能扩容了
+        self.buffer.sync();
+        match res {
+            Ok(n) => {
+                self.stats.add_read(n);
+                Ok(n)
+            }
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => Err(Error::ConnectionReset),
             Err(e) => Err(e.into()),
-            Ok(()) => {
-                let len = buf.position(); // 一个完整frame的长度
-                buf.set_position(0); // reset the position to 0, 因为check操作内部会advance cursor
-                let frame = Frame::parse(&mut buf)?;
-                self.buffer.advance(len as usize); // 丢弃掉buffer中解析过的字节
-                Ok(Some(frame))
+        }
+    }
+
+    fn parse_frame(&mut self) -> Result<Option<Frame>, Error> {
+        let res = parse_buffered(self.buffer, self.limits);
+        self.buffer.sync();
+        match &res {
+            Ok(Some(_)) => self.stats.frame_read(),
+            Ok(None) => {}
+            Err(_) => self.stats.parse_error(),
+        }
+        res
+    }
+}
+
+/// 从读缓冲中解析出一个完整的frame, 数据还不够时返回None。
+///
+/// 异步和阻塞的Connection共用这一段, 保证两边的分帧行为完全一致
+pub(crate) fn parse_buffered(buffer: &mut BytesMut, limits: &Limits) -> Result<Option<Frame>, Error> {
+    while let Some(n) = blank_inline_line(buffer) {
+        buffer.advance(n);
+    }
+
+    let mut buf = Cursor::new(&buffer[..]);
+    match Frame::check(&mut buf, limits) {
+        Err(Error::Incomplete) => Ok(None),
+        Err(e) => Err(e),
+        Ok(()) => {
+            let len = buf.position() as usize; // 一个完整frame的长度
+            // 把这个frame的字节从buffer中切下来(丢弃掉buffer中解析过的字节),
+            // 解析出的bulk string直接引用这块内存, 不再复制
+            let data = buffer.split_to(len).freeze();
+            let frame = Frame::parse_shared(&data)?;
+            Ok(Some(frame))
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> Encoder<'_, W> {
+    async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        let until = self.deadline();
+        let res = within(until, async {
+            self.encode(frame).await?;
+
+            // Ensure the encoded frame is written to the socket. The calls above
+            // are to the buffered stream and writes. Calling `flush` writes the
+            // remaining contents of the buffer to the socket.
+            self.stream.flush().await
+        });
+        res.await.unwrap_or_else(write_timeout)
+    }
+
+    async fn write_frames(&mut self, frames: &[Frame]) -> io::Result<()> {
+        for frame in frames {
+            self.check_depth(frame)?;
+        }
+        let until = self.deadline();
+        let res = within(until, async {
+            for frame in frames {
+                self.encode(frame).await?;
+            }
+            self.stream.flush().await
+        });
+        res.await.unwrap_or_else(write_timeout)
+    }
+
+    async fn feed_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        let until = self.deadline();
+        within(until, self.encode(frame)).await.unwrap_or_else(write_timeout)
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        let until = self.deadline();
+        within(until, self.stream.flush()).await.unwrap_or_else(write_timeout)
+    }
+
+    async fn write_bulk_from<R: AsyncRead + Unpin>(&mut self, len: u64, src: &mut R) -> io::Result<()> {
+        let until = self.deadline();
+        let res = within(until, async {
+            self.stream.write_u8(b'$').await?;
+            self.write_decimal(len as i64).await
+        });
+        res.await.unwrap_or_else(write_timeout)?;
+
+        let mut chunk = vec![0; STREAM_CHUNK.min(len as usize)];
+        let mut remaining = len;
+        while remaining > 0 {
+            let n = remaining.min(chunk.len() as u64) as usize;
+            let n = src.read(&mut chunk[..n]).await?;
+            if n == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("bulk source ended {} bytes early", remaining),
+                ));
+            }
+
+            let until = self.deadline();
+            within(until, self.stream.write_all(&chunk[..n])).await.unwrap_or_else(write_timeout)?;
+            remaining -= n as u64;
+        }
+
+        let until = self.deadline();
+        let res = within(until, async {
+            self.stream.write_all(b"\r\n").await?;
+            self.stream.flush().await
+        });
+        res.await.unwrap_or_else(write_timeout)?;
+        self.stream.stats.frame_written();
+        Ok(())
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+
+    async fn encode(&mut self, frame: &Frame) -> io::Result<()> {
+        // async fn 不能直接递归, 所以嵌套的聚合类型用一个显式的栈来编码:
+        // 栈里保存每一层还没写完的子frame, 遇到聚合类型就写入头部并压栈,
+        // 当前层写完就出栈, 回到上一层继续。
+        // 深度在写之前先检查, 避免写了一半才发现超限, 导致对端收到残缺的frame
+        self.check_depth(frame)?;
+
+        let resp3 = self.protocol == Protocol::Resp3;
+        let mut stack = vec![Children::One(Some(frame))];
+
+        while let Some(children) = stack.last_mut() {
+            let frame = match children.next() {
+                Some(frame) => frame,
+                None => {
+                    stack.pop();
+                    continue;
+                }
+            };
+
+            match frame {
+                Frame::Array(val) => {
+                    self.write_header(b'*', val.len()).await?;
+                    stack.push(Children::List(val.iter()));
+                }
+                // RESP2 没有set和push类型, 降级为数组
+                Frame::Set(val) => {
+                    self.write_header(if resp3 { b'~' } else { b'*' }, val.len()).await?;
+                    stack.push(Children::List(val.iter()));
+                }
+                Frame::Push(val) => {
+                    self.write_header(if resp3 { b'>' } else { b'*' }, val.len()).await?;
+                    stack.push(Children::List(val.iter()));
+                }
+                // RESP2 没有map类型, 降级为 key value 交替出现的数组
+                Frame::Map(val) => {
+                    if resp3 {
+                        self.write_header(b'%', val.len()).await?;
+                    } else {
+                        self.write_header(b'*', val.len() * 2).await?;
+                    }
+                    stack.push(Children::Pairs(val.iter(), None));
+                }
+                // 属性写在被修饰的frame之前; RESP2 客户端不认识属性, 直接丢弃
+                Frame::Attribute { attributes, data } => {
+                    stack.push(Children::One(Some(data)));
+                    if resp3 {
+                        self.write_header(b'|', attributes.len()).await?;
+                        stack.push(Children::Pairs(attributes.iter(), None));
+                    }
+                }
+                // The frame type is a literal. Encode the value directly.
+                _ => self.write_value(frame).await?,
             }
         }
+
+        self.stream.stats.frame_written();
+        Ok(())
+    }
+
+    fn check_depth(&self, frame: &Frame) -> io::Result<()> {
+        if depth(frame) > self.limits.max_depth {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("frame nesting exceeds max depth {}", self.limits.max_depth),
+            ));
+        }
+        Ok(())
     }
+
     /// Write a frame literal to the stream
     async fn write_value(&mut self, frame: &Frame) -> io::Result<()> {
+        let resp3 = self.protocol == Protocol::Resp3;
         match frame {
             Frame::Simple(val) => {
                 self.stream.write_u8(b'+').await?;
@@ -99,33 +743,80 @@ impl Connection {
                 self.stream.write_u8(b':').await?;
                 self.write_decimal(*val).await?;
             }
+            Frame::Null if resp3 => {
+                self.stream.write_all(b"_\r\n").await?;
+            }
             Frame::Null => {
                 self.stream.write_all(b"$-1\r\n").await?;
             }
             Frame::Bulk(val) => {
-                let len = val.len();
-
-                self.stream.write_u8(b'$').await?;
-                self.write_decimal(len as u64).await?;
-                self.stream.write_all(val).await?;
+                self.write_bulk(val).await?;
+            }
+            Frame::Double(val) if resp3 => {
+                self.stream.write_u8(b',').await?;
+                self.stream.write_all(format_double(*val).as_bytes()).await?;
                 self.stream.write_all(b"\r\n").await?;
             }
-            // Encoding an `Array` from within a value cannot be done using a
-            // recursive strategy. In general, async fns do not support
-            // recursion. Mini-redis has not needed to encode nested arrays yet,
-            // so for now it is skipped.
-            Frame::Array(_val) => unreachable!(),
+            Frame::Double(val) => {
+                self.write_bulk(format_double(*val).as_bytes()).await?;
+            }
+            Frame::Boolean(val) if resp3 => {
+                self.stream.write_all(if *val { b"#t\r\n" } else { b"#f\r\n" }).await?;
+            }
+            Frame::Boolean(val) => {
+                self.stream.write_u8(b':').await?;
+                self.write_decimal(*val as i64).await?;
+            }
+            Frame::BigNumber(val) if resp3 => {
+                self.stream.write_u8(b'(').await?;
+                self.stream.write_all(val.as_bytes()).await?;
+                self.stream.write_all(b"\r\n").await?;
+            }
+            Frame::BigNumber(val) => {
+                self.write_bulk(val.as_bytes()).await?;
+            }
+            Frame::Verbatim { format, data } if resp3 => {
+                self.stream.write_u8(b'=').await?;
+                self.write_decimal(data.len() as i64 + 4).await?;
+                self.stream.write_all(format).await?;
+                self.stream.write_u8(b':').await?;
+                self.stream.write_all(data).await?;
+                self.stream.write_all(b"\r\n").await?;
+            }
+            Frame::Verbatim { data, .. } => {
+                self.write_bulk(data).await?;
+            }
+            // 聚合类型由 `write_frame` 用显式栈展开, 不会走到这里
+            Frame::Array(_)
+            | Frame::Map(_)
+            | Frame::Set(_)
+            | Frame::Push(_)
+            | Frame::Attribute { .. } => unreachable!(),
         }
 
         Ok(())
     }
 
+    /// Write a `$<len>\r\n<data>\r\n` bulk string
+    async fn write_bulk(&mut self, val: &[u8]) -> io::Result<()> {
+        self.stream.write_u8(b'$').await?;
+        self.write_decimal(val.len() as i64).await?;
+        self.stream.write_all(val).await?;
+        self.stream.write_all(b"\r\n").await
+    }
+
+    /// Write the `<prefix><len>\r\n` header of an aggregate frame
+    async fn write_header(&mut self, prefix: u8, len: usize) -> io::Result<()> {
+        self.stream.write_u8(prefix).await?;
+        self.write_decimal(len as i64).await
+    }
+
     /// Write a decimal frame to the stream
-    async fn write_decimal(&mut self, val: u64) -> io::Result<()> {
+    async fn write_decimal(&mut self, val: i64) -> io::Result<()> {
         use std::io::Write;
 
         // Convert the value to a string
-        let mut buf = [0u8; 12];
+        let mut buf = [0u8; 20];
         let mut buf = Cursor::new(&mut buf[..]);
         write!(&mut buf, "{}", val)?;
 
@@ -135,4 +826,595 @@ impl Connection {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// 统计写出字节数的包装, `Encoder` 的所有写操作都经过它
+struct Counted<'a, W> {
+    inner: &'a mut W,
+    stats: &'a Stats,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for Counted<'_, W> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let res = Pin::new(&mut *self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = res {
+            self.stats.add_written(n);
+        }
+        res
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.inner).poll_shutdown(cx)
+    }
+}
+
+/// 在 `deadline` 之前完成 `f`, 超时返回None; 没有截止时间就一直等
+async fn within<F: Future>(deadline: Option<Instant>, f: F) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => time::timeout_at(deadline, f).await.ok(),
+        None => Some(f.await),
+    }
+}
+
+fn write_timeout() -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::TimedOut, Error::Timeout(TimeoutKind::Write)))
+}
+
+/// 编码栈中的一层: 某个聚合frame还没写完的子frame
+enum Children<'a> {
+    List(slice::Iter<'a, Frame>),
+    /// map/attribute 的键值对, 第二个字段是下一个要写的value
+    Pairs(slice::Iter<'a, (Frame, Frame)>, Option<&'a Frame>),
+    One(Option<&'a Frame>),
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = &'a Frame;
+
+    fn next(&mut self) -> Option<&'a Frame> {
+        match self {
+            Children::List(iter) => iter.next(),
+            Children::Pairs(iter, value) => value.take().or_else(|| {
+                let (k, v) = iter.next()?;
+                *value = Some(v);
+                Some(k)
+            }),
+            Children::One(frame) => frame.take(),
+        }
+    }
+}
+
+/// 计算frame的嵌套深度, 非聚合类型为0
+pub(crate) fn depth(frame: &Frame) -> usize {
+    let mut max = 0;
+    let mut stack = vec![(frame, 0)];
+    while let Some((frame, d)) = stack.pop() {
+        match frame {
+            Frame::Array(val) | Frame::Set(val) | Frame::Push(val) => {
+                max = max.max(d + 1);
+                stack.extend(val.iter().map(|entry| (entry, d + 1)));
+            }
+            Frame::Map(val) => {
+                max = max.max(d + 1);
+                stack.extend(val.iter().flat_map(|(k, v)| [(k, d + 1), (v, d + 1)]));
+            }
+            Frame::Attribute { attributes, data } => {
+                max = max.max(d + 1);
+                stack.extend(attributes.iter().flat_map(|(k, v)| [(k, d + 1), (v, d + 1)]));
+                stack.push((data, d));
+            }
+            _ => {}
+        }
+    }
+    max
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use bytes::Bytes;
+    use tokio::io::{self, DuplexStream};
+    use tokio::net::TcpListener;
+
+    use tokio::io::AsyncWriteExt;
+
+    use super::{Connection, Timeouts};
+    use crate::minis_redis::pool::{BufferPool, PoolConfig};
+    use crate::minis_redis::cmd::{Command, Hello};
+    use crate::minis_redis::frame::{Error, Frame, Limits, Protocol, TimeoutKind};
+
+    /// 默认允许的最大嵌套层数
+    fn max_depth() -> usize {
+        Limits::default().max_depth
+    }
+
+    /// 建立一对通过内存管道相连的Connection
+    fn pair() -> (Connection<DuplexStream>, Connection<DuplexStream>) {
+        let (client, server) = io::duplex(64 * 1024);
+        (Connection::new(client), Connection::new(server))
+    }
+
+    fn bulk(s: &'static str) -> Frame {
+        Frame::Bulk(Bytes::from(s))
+    }
+
+    fn hello(frame: Frame) -> Hello {
+        match Command::from_frame(frame).unwrap() {
+            Command::Hello(cmd) => cmd,
+            cmd => panic!("unexpected command {:?}", cmd),
+        }
+    }
+
+    fn nested(depth: usize) -> Frame {
+        let mut frame = bulk("leaf");
+        for _ in 0..depth {
+            frame = Frame::Array(vec![frame, Frame::Integer(1)]);
+        }
+        frame
+    }
+
+    fn resp3_frames() -> Vec<Frame> {
+        vec![
+            Frame::Null,
+            Frame::Double(1.5),
+            Frame::Double(f64::NEG_INFINITY),
+            Frame::Boolean(true),
+            Frame::BigNumber("-3492890328409238509324850943850943825024385".to_string()),
+            Frame::Verbatim {
+                format: *b"txt",
+                data: Bytes::from("Some string"),
+            },
+            Frame::Map(vec![
+                (bulk("a"), Frame::Integer(1)),
+                (bulk("b"), Frame::Set(vec![bulk("x"), bulk("y")])),
+            ]),
+            Frame::Push(vec![bulk("message"), bulk("chan"), bulk("hi")]),
+            Frame::Array(vec![
+                Frame::Attribute {
+                    attributes: vec![(bulk("ttl"), Frame::Integer(3600))],
+                    data: Box::new(Frame::Integer(2039123)),
+                },
+                Frame::Integer(9),
+            ]),
+        ]
+    }
+
+    #[tokio::test]
+    async fn round_trip_nested_arrays() {
+        let (mut client, mut server) = pair();
+        let frames = vec![
+            Frame::Simple("OK".to_string()),
+            Frame::Integer(-42),
+            Frame::Array(vec![]),
+            // EXEC 的回复: 每条命令的结果组成的数组
+            Frame::Array(vec![
+                Frame::Simple("OK".to_string()),
+                Frame::Array(vec![bulk("a"), Frame::Null]),
+                Frame::Error("ERR boom".to_string()),
+            ]),
+            // SCAN 的回复: [cursor, [keys...]]
+            Frame::Array(vec![
+                bulk("0"),
+                Frame::Array(vec![
+                    bulk("k1"),
+                    Frame::Array(vec![Frame::Array(vec![]), Frame::Integer(42)]),
+                ]),
+            ]),
+            nested(max_depth()),
+        ];
+
+        for frame in &frames {
+            client.write_frame(frame).await.unwrap();
+        }
+        for frame in &frames {
+            let got = server.read_frame().await.unwrap().unwrap();
+            assert_eq!(&got, frame);
+        }
+    }
+
+    #[tokio::test]
+    async fn tcp_connect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (client, server) = tokio::join!(Connection::connect(addr), listener.accept());
+        let (mut client, mut server) = (client.unwrap(), Connection::new(server.unwrap().0));
+
+        client.write_frame(&bulk("ping")).await.unwrap();
+        assert_eq!(server.read_frame().await.unwrap().unwrap(), bulk("ping"));
+    }
+
+    #[tokio::test]
+    async fn reject_too_deep() {
+        let (mut client, mut server) = pair();
+        let err = client.write_frame(&nested(max_depth() + 1)).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+        // 超限的frame一个字节都不应该写出去, 连接仍然可用
+        client.write_frame(&Frame::Integer(7)).await.unwrap();
+        let got = server.read_frame().await.unwrap().unwrap();
+        assert_eq!(got, Frame::Integer(7));
+    }
+
+    #[tokio::test]
+    async fn round_trip_resp3() {
+        let (mut client, mut server) = pair();
+        server.set_protocol(Protocol::Resp3);
+
+        for frame in resp3_frames() {
+            server.write_frame(&frame).await.unwrap();
+            let got = client.read_frame().await.unwrap().unwrap();
+            assert_eq!(got, frame);
+        }
+
+        server.write_frame(&Frame::Double(f64::NAN)).await.unwrap();
+        match client.read_frame().await.unwrap().unwrap() {
+            Frame::Double(val) => assert!(val.is_nan()),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+
+    #[tokio::test]
+    async fn downgrade_resp3_for_resp2_clients() {
+        let (mut client, mut server) = pair();
+        let expected = vec![
+            Frame::Null,
+            bulk("1.5"),
+            bulk("-inf"),
+            Frame::Integer(1),
+            bulk("-3492890328409238509324850943850943825024385"),
+            bulk("Some string"),
+            Frame::Array(vec![
+                bulk("a"),
+                Frame::Integer(1),
+                bulk("b"),
+                Frame::Array(vec![bulk("x"), bulk("y")]),
+            ]),
+            Frame::Array(vec![bulk("message"), bulk("chan"), bulk("hi")]),
+            Frame::Array(vec![Frame::Integer(2039123), Frame::Integer(9)]),
+        ];
+
+        for (frame, expected) in resp3_frames().iter().zip(expected) {
+            server.write_frame(frame).await.unwrap();
+            let got = client.read_frame().await.unwrap().unwrap();
+            assert_eq!(got, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn hello_switches_protocol() {
+        let (mut client, mut server) = pair();
+
+        client.write_frame(&Hello::new(Some(3)).into_frame()).await.unwrap();
+        let frame = server.read_frame().await.unwrap().unwrap();
+        let reply = hello(frame).apply(&mut server);
+        server.write_frame(&reply).await.unwrap();
+        assert_eq!(server.protocol(), Protocol::Resp3);
+        match client.read_frame().await.unwrap().unwrap() {
+            Frame::Map(fields) => assert!(fields.contains(&(bulk("proto"), Frame::Integer(3)))),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+
+        client.write_frame(&Hello::new(Some(4)).into_frame()).await.unwrap();
+        let frame = server.read_frame().await.unwrap().unwrap();
+        let reply = hello(frame).apply(&mut server);
+        server.write_frame(&reply).await.unwrap();
+        assert_eq!(server.protocol(), Protocol::Resp3);
+        let reply = client.read_frame().await.unwrap().unwrap();
+        assert_eq!(reply, Frame::Error("NOPROTO unsupported protocol version".to_string()));
+
+        client.write_frame(&Hello::new(Some(2)).into_frame()).await.unwrap();
+        let frame = server.read_frame().await.unwrap().unwrap();
+        let reply = hello(frame).apply(&mut server);
+        server.write_frame(&reply).await.unwrap();
+        assert_eq!(server.protocol(), Protocol::Resp2);
+        match client.read_frame().await.unwrap().unwrap() {
+            Frame::Array(fields) => assert!(fields.contains(&Frame::Integer(2))),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+
+    fn small_limits() -> Limits {
+        Limits {
+            max_bulk_len: 16,
+            max_array_len: 4,
+            max_buffer: 64,
+            max_depth: 2,
+            max_inline_len: 32,
+        }
+    }
+
+    /// 把原始字节发给一个使用 `small_limits` 的Connection, 返回read_frame的结果
+    async fn read_raw(raw: &[u8]) -> Result<Option<Frame>, Error> {
+        let (mut client, server) = io::duplex(64 * 1024);
+        let mut server = Connection::with_limits(server, small_limits());
+        client.write_all(raw).await.unwrap();
+        drop(client);
+        server.read_frame().await
+    }
+
+    #[tokio::test]
+    async fn within_limits() {
+        let frame = read_raw(b"*2\r\n*1\r\n$16\r\n0123456789abcdef\r\n:1\r\n").await;
+        assert_eq!(
+            frame.unwrap().unwrap(),
+            Frame::Array(vec![
+                Frame::Array(vec![bulk("0123456789abcdef")]),
+                Frame::Integer(1)
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn reject_oversized_frames() {
+        // 头部声明的长度超限, 不需要等数据到达
+        for raw in [
+            &b"$4294967296\r\n"[..],
+            b"*5\r\n",
+            b"%5\r\n",
+            b"*1\r\n*1\r\n*1\r\n:1\r\n",
+            &[b'+'; 100][..],
+        ] {
+            match read_raw(raw).await {
+                Err(Error::FrameTooLarge(_)) => {}
+                other => panic!("{:?} => {:?}", raw, other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn typed_errors() {
+        match read_raw(b"*1\r\n?garbage\r\n").await {
+            Err(Error::ProtocolViolation(_)) => {}
+            other => panic!("{:?}", other),
+        }
+        match read_raw(b"GET \"garbage\r\n").await {
+            Err(Error::ProtocolViolation(_)) => {}
+            other => panic!("{:?}", other),
+        }
+        match read_raw(b"$-5\r\n").await {
+            Err(Error::ProtocolViolation(_)) => {}
+            other => panic!("{:?}", other),
+        }
+        match read_raw(b"$10\r\nabc").await {
+            Err(Error::ConnectionReset) => {}
+            other => panic!("{:?}", other),
+        }
+        assert!(read_raw(b"").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn pipelined_batch() {
+        let (mut client, mut server) = pair();
+        let requests: Vec<Frame> = (0..100).map(Frame::Integer).collect();
+
+        // 客户端一次性发出所有请求
+        client.write_frames(&requests).await.unwrap();
+
+        // 第一个请求需要等待读socket, 之后的都已经在缓冲里了
+        let mut received = vec![server.read_frame().await.unwrap().unwrap()];
+        while let Some(frame) = server.try_read_frame().unwrap() {
+            received.push(frame);
+        }
+        assert_eq!(received, requests);
+
+        server.write_frames(&received).await.unwrap();
+        for frame in &requests {
+            assert_eq!(&client.read_frame().await.unwrap().unwrap(), frame);
+        }
+
+        // 有一个frame不合法就整批都不写
+        let batch = vec![Frame::Integer(1), nested(max_depth() + 1)];
+        assert!(server.write_frames(&batch).await.is_err());
+        server.write_frame(&Frame::Integer(2)).await.unwrap();
+        assert_eq!(client.read_frame().await.unwrap().unwrap(), Frame::Integer(2));
+    }
+
+    #[tokio::test]
+    async fn split_halves_in_separate_tasks() {
+        let (mut client, mut server) = pair();
+        server.set_protocol(Protocol::Resp3);
+
+        // 拆分前已经到达但还没解析的数据要留给读半边
+        client.write_frames(&[Frame::Integer(0), Frame::Integer(1)]).await.unwrap();
+        assert_eq!(server.read_frame().await.unwrap().unwrap(), Frame::Integer(0));
+
+        let (mut reader, mut writer) = server.into_split().await.unwrap();
+        assert_eq!(writer.protocol(), Protocol::Resp3);
+
+        // 读半边收到什么就通过channel交给写半边原样发回去
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let read_task = tokio::spawn(async move {
+            while let Some(frame) = reader.read_frame().await.unwrap() {
+                tx.send(frame).await.unwrap();
+            }
+        });
+        let write_task = tokio::spawn(async move {
+            while let Some(frame) = rx.recv().await {
+                writer.write_frame(&frame).await.unwrap();
+            }
+        });
+
+        for i in 2..10 {
+            client.write_frame(&Frame::Integer(i)).await.unwrap();
+        }
+        for i in 1..10 {
+            assert_eq!(client.read_frame().await.unwrap().unwrap(), Frame::Integer(i));
+        }
+
+        drop(client);
+        read_task.await.unwrap();
+        write_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn inline_commands() {
+        let (mut client, server) = io::duplex(64 * 1024);
+        let mut server = Connection::new(server);
+
+        // 和RESP请求混在一起, 中间的空行被丢弃
+        client.write_all(b"PING\r\n\r\n  \nSET a 'b c'\n*1\r\n$4\r\nPING\r\n").await.unwrap();
+        drop(client);
+
+        let ping = Frame::Array(vec![bulk("PING")]);
+        assert_eq!(server.read_frame().await.unwrap().unwrap(), ping);
+        assert_eq!(
+            server.read_frame().await.unwrap().unwrap(),
+            Frame::Array(vec![bulk("SET"), bulk("a"), bulk("b c")])
+        );
+        assert_eq!(server.read_frame().await.unwrap().unwrap(), ping);
+        assert!(server.read_frame().await.unwrap().is_none());
+
+        // 一直没有换行的输入受 max_inline_len 限制
+        match read_raw(&[b'a'; 100]).await {
+            Err(Error::FrameTooLarge(_)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn timeouts() {
+        let timeouts = Timeouts {
+            idle: Some(Duration::from_millis(200)),
+            read: Some(Duration::from_millis(50)),
+            write: Some(Duration::from_millis(50)),
+        };
+        let (mut client, mut server) = pair();
+        server.set_timeouts(timeouts);
+
+        // 什么都不发
+        match server.read_frame().await {
+            Err(Error::Timeout(TimeoutKind::Idle)) => {}
+            other => panic!("{:?}", other),
+        }
+
+        // 发了半个frame之后就不发了, 不用等到空闲超时
+        let start = Instant::now();
+        client.stream.write_all(b"*2\r\n$3\r\nGET\r\n").await.unwrap();
+        client.flush().await.unwrap();
+        match server.read_frame().await {
+            Err(Error::Timeout(TimeoutKind::Read)) => {}
+            other => panic!("{:?}", other),
+        }
+        assert!(start.elapsed() < Duration::from_millis(200));
+
+        // 对端一直不读, 写满管道之后超时
+        let (_client, server) = io::duplex(64);
+        let mut server = Connection::new(server);
+        server.set_timeouts(timeouts);
+        let err = server.write_frame(&Frame::Bulk(Bytes::from(vec![0; 64 * 1024]))).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(err.to_string(), "write timeout");
+    }
+
+    #[tokio::test]
+    async fn stream_large_bulk() {
+        let (mut client, mut server) = pair();
+        let value: Vec<u8> = (0..4 * 1024 * 1024).map(|i| i as u8).collect();
+
+        // 写端从任意AsyncRead读取内容, 读端写到任意AsyncWrite, 两边都不需要完整的Frame
+        let expected = value.clone();
+        let writer = tokio::spawn(async move {
+            server.write_bulk_from(value.len() as u64, &mut &value[..]).await.unwrap();
+            server.write_frame(&Frame::Null).await.unwrap();
+            server.write_frame(&Frame::Integer(1)).await.unwrap();
+            server
+        });
+
+        let len = client.read_bulk_header().await.unwrap().unwrap();
+        assert_eq!(len, expected.len() as u64);
+        let mut received = Vec::new();
+        client.copy_bulk_to(len, &mut received).await.unwrap();
+        assert!(received == expected);
+
+        // 之后的frame照常读取
+        assert_eq!(client.read_bulk_header().await.unwrap(), None);
+        assert_eq!(client.read_frame().await.unwrap().unwrap(), Frame::Integer(1));
+
+        // 内容不足声明的长度
+        let mut server = writer.await.unwrap();
+        let err = server.write_bulk_from(10, &mut &b"short"[..]).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // 不是bulk string
+        let (mut client, mut server) = pair();
+        server.write_frame(&Frame::Integer(2)).await.unwrap();
+        match client.read_bulk_header().await {
+            Err(Error::ProtocolViolation(_)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn traffic_stats() {
+        let (mut client, mut server) = pair();
+        let stats = server.stats().clone();
+
+        client.write_frames(&[Frame::Integer(1), Frame::Integer(22)]).await.unwrap();
+        assert_eq!(server.read_frame().await.unwrap().unwrap(), Frame::Integer(1));
+        assert_eq!(server.try_read_frame().unwrap().unwrap(), Frame::Integer(22));
+        server.write_frame(&bulk("hello")).await.unwrap();
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.bytes_read, 9);
+        assert_eq!(snapshot.frames_read, 2);
+        assert_eq!(snapshot.bytes_written, 11);
+        assert_eq!(snapshot.frames_written, 1);
+        assert_eq!(snapshot.parse_errors, 0);
+        assert_eq!(client.stats().snapshot().bytes_written, 9);
+
+        // 拆分之后两个半边继续累加到同一份统计上
+        let (mut reader, mut writer) = server.into_split().await.unwrap();
+        client.write_frame(&Frame::Null).await.unwrap();
+        reader.read_frame().await.unwrap();
+        writer.write_frame(&Frame::Null).await.unwrap();
+        client.stream.write_all(b"*1\r\n?\r\n").await.unwrap();
+        client.flush().await.unwrap();
+        assert!(reader.read_frame().await.is_err());
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.frames_read, 3);
+        assert_eq!(snapshot.frames_written, 2);
+        assert_eq!(snapshot.parse_errors, 1);
+        assert!(snapshot.idle <= snapshot.age);
+    }
+
+    #[tokio::test]
+    async fn shrink_idle_buffer() {
+        let pool = BufferPool::new(PoolConfig {
+            buffer_size: 1024,
+            max_pooled: 4,
+            shrink_after: Some(Duration::from_millis(100)),
+        });
+        let (client, server) = io::duplex(1024 * 1024);
+        let mut client = Connection::new(client);
+        let mut server = Connection::with_pool(server, Limits::default(), pool.clone());
+        assert_eq!(pool.buffered_bytes(), 1024);
+
+        // 一个大的value把读缓冲撑大
+        let big = Frame::Bulk(Bytes::from(vec![b'x'; 512 * 1024]));
+        client.write_frame(&big).await.unwrap();
+        assert_eq!(server.read_frame().await.unwrap().unwrap(), big);
+        assert!(pool.buffered_bytes() > 256 * 1024);
+
+        // 空闲超过 shrink_after 之后换回标准大小, 连接照常使用
+        let reader = tokio::spawn(async move {
+            let frame = server.read_frame().await.unwrap().unwrap();
+            (server, frame)
+        });
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(pool.buffered_bytes(), 1024);
+        client.write_frame(&Frame::Integer(1)).await.unwrap();
+        let (server, frame) = reader.await.unwrap();
+        assert_eq!(frame, Frame::Integer(1));
+
+        // 连接关闭后缓冲还回池里
+        drop(server);
+        assert_eq!(pool.buffered_bytes(), 0);
+        assert_eq!(pool.pooled(), 1);
+    }
+}
@@ -0,0 +1,847 @@
+//! 分片的键值存储。
+//!
+//! key按hash分到多个分片, 每个分片一把std的 `Mutex`: 锁竞争被分散到各个分片,
+//! 而且命令执行期间不会跨await持有锁, 所以用同步mutex就够了。
+//!
+//! 涉及多个key的命令(MGET、MSET、DEL ...)通过 `Db::lock` 一次锁住所有相关的分片,
+//! 命令在这些锁下执行, 对其他连接来说是原子的。
+//!
+//! key可以设置过期时间。过期的key在访问时就当作不存在(惰性过期), 同时每个分片有一个
+//! 后台task按过期时间的先后把它们真正删掉, 没人再访问的key也不会一直占着内存。
+//!
+//! 开启了AOF时, 修改数据的命令在还持有分片锁的时候通过 `Guard::log` 写日志,
+//! 同一个key上的修改在日志中的顺序和实际执行的顺序一致。
+//!
+//! 快照通过 `Db::lock_all` 按顺序锁住所有的分片, 用 `Guard::copy` 复制出同一时刻的数据
+//! 就放开锁, 写文件时不持有任何锁。
+//!
+//! value有多种类型(`Value`), 命令用错了类型时回复 `WrongType` 错误, 和redis一样。
+//!
+//! BLPOP这类阻塞命令在list所在的分片里排队等待(见 `blocked`)。往list里放入了数据的命令
+//! 调用 `Guard::signal_ready`, `Guard` 释放锁之后按先来后到把数据交给等待的客户端。
+//!
+//! 被WATCH的key在分片里记一个版本号, 每次修改、删除以及被清理task删掉都加一, 事务在EXEC
+//! 时比较版本号来发现冲突(见 `transaction`)。没有人WATCH的key不记版本号, 不占内存。
+//!
+//! 惰性过期不会改版本号: 读到过期的key时只是当作不存在, 没有修改分片。已经过期、还没被
+//! 清理掉的key靠EXEC时再比较一次key是否存在来发现, 只比较版本号是不够的。
+
+use std::collections::hash_map::{DefaultHasher, HashMap};
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use tokio::sync::Notify;
+use tokio::time::{self, Instant};
+
+use super::aof::Aof;
+use super::blocked::{self, Waiter};
+use super::frame::Frame;
+use super::sorted_set::SortedSet;
+
+/// 分片的键值存储, clone之后共享同一份数据
+#[derive(Clone, Debug)]
+pub struct Db {
+    shared: Arc<Shared>,
+    /// 修改写到这个AOF里, 没开启AOF(以及回放AOF)时是None
+    aof: Option<Aof>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    shards: Vec<Mutex<Shard>>,
+    /// 每个分片一个, 唤醒该分片的清理task
+    purge: Vec<Arc<Notify>>,
+}
+
+/// 一个分片中的数据
+#[derive(Debug, Default)]
+struct Shard {
+    entries: HashMap<String, Entry>,
+    /// 设置了过期时间的key, 按过期时间排序, 清理task从前往后删
+    expirations: BTreeSet<(Instant, String)>,
+    /// 阻塞在每个key上的客户端, 先来的在前面
+    blocked: HashMap<String, VecDeque<Arc<Waiter>>>,
+    /// 被WATCH的key的版本号
+    watched: HashMap<String, Watched>,
+}
+
+/// 一个被WATCH的key
+#[derive(Debug)]
+struct Watched {
+    /// 开始WATCH之后被修改的次数
+    version: u64,
+    /// 有几个连接在WATCH它, 减到0就不再记录
+    watchers: usize,
+}
+
+#[derive(Debug)]
+struct Entry {
+    data: Value,
+    expires_at: Option<Instant>,
+}
+
+/// 一个key的值
+///
+/// 集合类型放在 `Arc` 里, clone只增加引用计数。修改时通过 `Arc::make_mut` 拿到可变引用,
+/// 只有还被快照的副本引用着时才真的复制一份
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    String(Bytes),
+    List(Arc<VecDeque<Bytes>>),
+    Hash(Arc<HashMap<Bytes, Bytes>>),
+    Set(Arc<HashSet<Bytes>>),
+    ZSet(Arc<SortedSet>),
+}
+
+/// `Value` 中除了string以外的各个类型, `Guard` 按类型取值时用
+trait Typed: Default {
+    fn from_ref(value: &Value) -> Option<&Self>;
+    fn from_mut(value: &mut Value) -> Option<&mut Self>;
+    fn into_value(self) -> Value;
+}
+
+/// 对key执行了和它的类型不符的操作, 比如对list执行GET
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WrongType;
+
+/// `Db::lock` 锁住的一组分片, drop时释放所有的锁。
+///
+/// 只能访问加锁时传入的key, 访问其他key会panic
+pub struct Guard<'a> {
+    db: &'a Db,
+    /// 按分片下标从小到大排列
+    shards: Vec<(usize, MutexGuard<'a, Shard>)>,
+    /// 放入了数据、而且有客户端在等的key, 释放锁之后交给这些客户端
+    ready: Vec<String>,
+    /// EXEC执行期间的AOF记录, 事务结束时一起写进AOF
+    batch: Option<Vec<Frame>>,
+}
+
+impl Db {
+    pub fn new(num_shards: usize) -> Db {
+        assert!(num_shards > 0, "a db needs at least one shard");
+        Db {
+            shared: Arc::new(Shared {
+                shards: (0..num_shards).map(|_| Mutex::default()).collect(),
+                purge: (0..num_shards).map(|_| Arc::new(Notify::new())).collect(),
+            }),
+            aof: None,
+        }
+    }
+
+    /// 之后的修改都写进 `aof`, 之前clone出去的 `Db` 不受影响
+    pub fn with_aof(mut self, aof: Aof) -> Db {
+        self.aof = Some(aof);
+        self
+    }
+
+    pub fn aof(&self) -> Option<&Aof> {
+        self.aof.as_ref()
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.shared.shards.len()
+    }
+
+    /// key所在的分片下标
+    pub fn shard_index(&self, key: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish() as usize % self.shared.shards.len()
+    }
+
+    /// 每个分片启动一个后台task清理过期的key, 必须在tokio runtime中调用。
+    ///
+    /// task只持有 `Db` 的弱引用, 所有的 `Db` 都drop之后自动退出
+    pub fn spawn_purge_tasks(&self) {
+        for index in 0..self.num_shards() {
+            let shared = Arc::downgrade(&self.shared);
+            let notify = self.shared.purge[index].clone();
+            tokio::spawn(purge_expired_keys(shared, notify, index));
+        }
+    }
+
+    /// 锁住 `keys` 所在的全部分片。
+    ///
+    /// 分片下标去重之后按从小到大的顺序加锁, 所有命令都遵守同样的顺序,
+    /// 两个多key命令即使涉及的分片有交叉也不会互相死锁
+    pub fn lock<I>(&self, keys: I) -> Guard<'_>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let mut indexes: Vec<usize> = keys.into_iter().map(|key| self.shard_index(key.as_ref())).collect();
+        indexes.sort_unstable();
+        indexes.dedup();
+
+        let shards = indexes
+            .into_iter()
+            .map(|index| (index, self.shared.shards[index].lock().unwrap()))
+            .collect();
+        Guard {
+            db: self,
+            shards,
+            ready: Vec::new(),
+            batch: None,
+        }
+    }
+
+    /// 按分片下标从小到大锁住所有的分片, 和 `lock` 的顺序一样, 不会和多key命令死锁
+    pub fn lock_all(&self) -> Guard<'_> {
+        let shards = self
+            .shared
+            .shards
+            .iter()
+            .enumerate()
+            .map(|(index, shard)| (index, shard.lock().unwrap()))
+            .collect();
+        Guard {
+            db: self,
+            shards,
+            ready: Vec::new(),
+            batch: None,
+        }
+    }
+}
+
+impl Drop for Shared {
+    /// 唤醒所有的清理task, 让它们发现 `Db` 已经没有了
+    fn drop(&mut self) {
+        for notify in &self.purge {
+            notify.notify_one();
+        }
+    }
+}
+
+impl Guard<'_> {
+    /// 加锁的 `Db`
+    pub fn db(&self) -> &Db {
+        self.db
+    }
+
+    /// 复制锁住的分片中没有过期的key, 锁住了所有分片时就是整个 `Db` 在这一时刻的数据。
+    ///
+    /// 每个key复制一次key字符串, 值只增加引用计数(见 `Value`), 所以时间和key的个数成正比,
+    /// 和集合里有多少元素无关
+    pub fn copy(&self) -> Vec<(String, Value, Option<Instant>)> {
+        let now = Instant::now();
+        self.shards
+            .iter()
+            .flat_map(|(_, shard)| shard.entries.iter())
+            .filter(|(_, entry)| !entry.expired(now))
+            .map(|(key, entry)| (key.clone(), entry.data.clone(), entry.expires_at))
+            .collect()
+    }
+
+    /// `key` 的值, 不管是什么类型
+    pub fn value(&self, key: &str) -> Option<&Value> {
+        self.shard(key).live(key).map(|entry| &entry.data)
+    }
+
+    /// 可以修改的 `key` 的值, 返回 `Some` 就当作修改了它
+    pub fn value_mut(&mut self, key: &str) -> Option<&mut Value> {
+        let shard = self.shard_mut(key);
+        if shard.live(key).is_some() {
+            shard.touch(key);
+        }
+        shard.live_mut(key).map(|entry| &mut entry.data)
+    }
+
+    /// string类型的 `key`
+    pub fn get(&self, key: &str) -> Result<Option<&Bytes>, WrongType> {
+        self.value(key).map(Value::as_string).transpose()
+    }
+
+    /// list类型的 `key`
+    pub fn list(&self, key: &str) -> Result<Option<&VecDeque<Bytes>>, WrongType> {
+        self.typed(key)
+    }
+
+    pub fn list_mut(&mut self, key: &str) -> Result<Option<&mut VecDeque<Bytes>>, WrongType> {
+        self.typed_mut(key)
+    }
+
+    /// list类型的 `key`, 不存在时创建一个空的list。
+    ///
+    /// 空的list不能留在db里, 调用者要保证之后至少放进一个元素; 其他的 `*_or_default` 也一样
+    pub fn list_or_default(&mut self, key: &str) -> Result<&mut VecDeque<Bytes>, WrongType> {
+        self.typed_or_default(key)
+    }
+
+    /// hash类型的 `key`
+    pub fn hash(&self, key: &str) -> Result<Option<&HashMap<Bytes, Bytes>>, WrongType> {
+        self.typed(key)
+    }
+
+    pub fn hash_mut(&mut self, key: &str) -> Result<Option<&mut HashMap<Bytes, Bytes>>, WrongType> {
+        self.typed_mut(key)
+    }
+
+    pub fn hash_or_default(&mut self, key: &str) -> Result<&mut HashMap<Bytes, Bytes>, WrongType> {
+        self.typed_or_default(key)
+    }
+
+    /// set类型的 `key`
+    pub fn set(&self, key: &str) -> Result<Option<&HashSet<Bytes>>, WrongType> {
+        self.typed(key)
+    }
+
+    pub fn set_mut(&mut self, key: &str) -> Result<Option<&mut HashSet<Bytes>>, WrongType> {
+        self.typed_mut(key)
+    }
+
+    pub fn set_or_default(&mut self, key: &str) -> Result<&mut HashSet<Bytes>, WrongType> {
+        self.typed_or_default(key)
+    }
+
+    /// zset类型的 `key`
+    pub fn zset(&self, key: &str) -> Result<Option<&SortedSet>, WrongType> {
+        self.typed(key)
+    }
+
+    pub fn zset_mut(&mut self, key: &str) -> Result<Option<&mut SortedSet>, WrongType> {
+        self.typed_mut(key)
+    }
+
+    pub fn zset_or_default(&mut self, key: &str) -> Result<&mut SortedSet, WrongType> {
+        self.typed_or_default(key)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.shard(key).live(key).is_some()
+    }
+
+    /// 写入 `key` 并清除它的过期时间, 返回原来的值
+    pub fn insert(&mut self, key: String, value: impl Into<Value>) -> Option<Value> {
+        let shard = self.shard_mut(&key);
+        let old = shard.remove(&key);
+        shard.touch(&key);
+        shard.entries.insert(
+            key,
+            Entry {
+                data: value.into(),
+                expires_at: None,
+            },
+        );
+        old
+    }
+
+    /// 修改 `key` 的值但保留它的过期时间(INCR、APPEND), 返回原来的值
+    pub fn update(&mut self, key: String, value: impl Into<Value>) -> Option<Value> {
+        let expires_at = self.expires_at(&key).flatten();
+        let old = self.insert(key.clone(), value);
+        if let Some(when) = expires_at {
+            self.set_expiry(&key, Some(when));
+        }
+        old
+    }
+
+    /// 删除 `key`, 返回原来的值
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.shard_mut(key).remove(key)
+    }
+
+    /// 开启了AOF时, 把 `record()` 生成的命令写进AOF, 否则什么都不做。
+    ///
+    /// 在修改完数据、释放锁之前调用。EXEC执行期间先攒起来, 由 `commit_batch` 一起写
+    pub fn log(&mut self, record: impl FnOnce() -> Frame) {
+        let Some(aof) = &self.db.aof else {
+            return;
+        };
+        match &mut self.batch {
+            Some(batch) => batch.push(record()),
+            None => aof.append(&record()),
+        }
+    }
+
+    /// 之后的AOF记录先攒起来, 直到 `commit_batch`
+    pub(crate) fn begin_batch(&mut self) {
+        self.batch = Some(Vec::new());
+    }
+
+    /// 把 `begin_batch` 之后的AOF记录作为一个事务写进AOF
+    pub(crate) fn commit_batch(&mut self) {
+        let batch = self.batch.take().unwrap_or_default();
+        if let Some(aof) = &self.db.aof {
+            aof.append_transaction(&batch);
+        }
+    }
+
+    /// `key` 的过期时间: key不存在时返回 `None`, 没有过期时间时返回 `Some(None)`
+    pub fn expires_at(&self, key: &str) -> Option<Option<Instant>> {
+        self.shard(key).live(key).map(|entry| entry.expires_at)
+    }
+
+    /// 设置(`None` 表示清除) `key` 的过期时间, key不存在时返回false。
+    ///
+    /// 过期时间已经过去的话直接删除key
+    pub fn set_expiry(&mut self, key: &str, when: Option<Instant>) -> bool {
+        let pos = self.position(key);
+        let (index, shard) = &mut self.shards[pos];
+        if shard.live(key).is_none() {
+            return false;
+        }
+        if when.is_some_and(|when| when <= Instant::now()) {
+            shard.remove(key);
+            return true;
+        }
+
+        shard.touch(key);
+        let entry = shard.entries.get_mut(key).unwrap();
+        let old = std::mem::replace(&mut entry.expires_at, when);
+        if let Some(old) = old {
+            shard.expirations.remove(&(old, key.to_string()));
+        }
+        if let Some(when) = when {
+            // 新的过期时间比清理task正在等的更早, 叫醒它重新计算
+            let earliest = shard.expirations.first().is_none_or(|(next, _)| when < *next);
+            shard.expirations.insert((when, key.to_string()));
+            if earliest {
+                self.db.shared.purge[*index].notify_one();
+            }
+        }
+        true
+    }
+
+    /// 往 `key` 的list里放入了数据。有客户端阻塞在这个key上的话, 释放锁之后把数据交给它们
+    pub fn signal_ready(&mut self, key: &str) {
+        if self.shard(key).blocked.contains_key(key) && !self.ready.iter().any(|ready| ready == key) {
+            self.ready.push(key.to_string());
+        }
+    }
+
+    /// 把 `waiter` 排到 `key` 的等待队列最后
+    pub(crate) fn block(&mut self, key: &str, waiter: Arc<Waiter>) {
+        self.shard_mut(key).blocked.entry(key.to_string()).or_default().push_back(waiter);
+    }
+
+    /// 把 `waiter` 从 `key` 的等待队列中去掉
+    pub(crate) fn unblock(&mut self, key: &str, waiter: &Arc<Waiter>) {
+        let blocked = &mut self.shard_mut(key).blocked;
+        if let Some(queue) = blocked.get_mut(key) {
+            queue.retain(|other| !Arc::ptr_eq(other, waiter));
+            if queue.is_empty() {
+                blocked.remove(key);
+            }
+        }
+    }
+
+    /// `key` 的等待队列中排在最前面的客户端
+    pub(crate) fn first_waiter(&self, key: &str) -> Option<Arc<Waiter>> {
+        self.shard(key).blocked.get(key).and_then(|queue| queue.front().cloned())
+    }
+
+    /// 开始WATCH `key`, 返回它现在的版本号
+    pub(crate) fn watch(&mut self, key: &str) -> u64 {
+        let watched = self.shard_mut(key).watched.entry(key.to_string()).or_insert(Watched {
+            version: 0,
+            watchers: 0,
+        });
+        watched.watchers += 1;
+        watched.version
+    }
+
+    /// 取消一次 `watch`
+    pub(crate) fn unwatch(&mut self, key: &str) {
+        let watched = &mut self.shard_mut(key).watched;
+        if let Some(entry) = watched.get_mut(key) {
+            entry.watchers -= 1;
+            if entry.watchers == 0 {
+                watched.remove(key);
+            }
+        }
+    }
+
+    /// 被WATCH的 `key` 现在的版本号
+    pub(crate) fn version(&self, key: &str) -> Option<u64> {
+        self.shard(key).watched.get(key).map(|watched| watched.version)
+    }
+
+    fn typed<T: Typed>(&self, key: &str) -> Result<Option<&T>, WrongType> {
+        self.value(key).map(|value| T::from_ref(value).ok_or(WrongType)).transpose()
+    }
+
+    fn typed_mut<T: Typed>(&mut self, key: &str) -> Result<Option<&mut T>, WrongType> {
+        // 先检查类型, 类型不对的命令没有修改key
+        self.typed::<T>(key)?;
+        Ok(self.value_mut(key).map(|value| T::from_mut(value).unwrap()))
+    }
+
+    fn typed_or_default<T: Typed>(&mut self, key: &str) -> Result<&mut T, WrongType> {
+        if !self.contains_key(key) {
+            self.insert(key.to_string(), T::default().into_value());
+        }
+        self.typed_mut(key).map(Option::unwrap)
+    }
+
+    fn position(&self, key: &str) -> usize {
+        let index = self.db.shard_index(key);
+        match self.shards.binary_search_by_key(&index, |(i, _)| *i) {
+            Ok(pos) => pos,
+            Err(_) => panic!("shard of key {:?} is not locked", key),
+        }
+    }
+
+    fn shard(&self, key: &str) -> &Shard {
+        &self.shards[self.position(key)].1
+    }
+
+    fn shard_mut(&mut self, key: &str) -> &mut Shard {
+        let pos = self.position(key);
+        &mut self.shards[pos].1
+    }
+}
+
+impl Drop for Guard<'_> {
+    /// 先释放所有的锁, 再服务阻塞在ready的key上的客户端, 服务时会重新加锁
+    fn drop(&mut self) {
+        self.shards.clear();
+        for key in std::mem::take(&mut self.ready) {
+            blocked::serve(self.db, &key);
+        }
+    }
+}
+
+/// 过期时间对应的unix时间戳(毫秒), 写AOF用: 重启之后 `Instant` 就没有意义了
+pub fn unix_millis(when: Instant) -> i64 {
+    let now = Instant::now();
+    let unix_now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let unix = if when >= now {
+        unix_now + (when - now)
+    } else {
+        unix_now.saturating_sub(now - when)
+    };
+    unix.as_millis() as i64
+}
+
+/// unix时间戳(毫秒)对应的 `Instant`, 已经过去的时间返回当前时间, 太远表示不了时返回None
+pub fn instant_at(unix_millis: i64) -> Option<Instant> {
+    let unix_now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let at = Duration::from_millis(unix_millis.max(0) as u64);
+    let now = Instant::now();
+    match at.checked_sub(unix_now) {
+        Some(after) => now.checked_add(after),
+        None => Some(now),
+    }
+}
+
+impl Value {
+    /// TYPE命令回复的类型名
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::ZSet(_) => "zset",
+        }
+    }
+
+    pub fn as_string(&self) -> Result<&Bytes, WrongType> {
+        match self {
+            Value::String(value) => Ok(value),
+            _ => Err(WrongType),
+        }
+    }
+}
+
+impl From<Bytes> for Value {
+    fn from(value: Bytes) -> Value {
+        Value::String(value)
+    }
+}
+
+impl Typed for VecDeque<Bytes> {
+    fn from_ref(value: &Value) -> Option<&Self> {
+        match value {
+            Value::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    fn from_mut(value: &mut Value) -> Option<&mut Self> {
+        match value {
+            Value::List(list) => Some(Arc::make_mut(list)),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::List(Arc::new(self))
+    }
+}
+
+impl Typed for HashMap<Bytes, Bytes> {
+    fn from_ref(value: &Value) -> Option<&Self> {
+        match value {
+            Value::Hash(hash) => Some(hash),
+            _ => None,
+        }
+    }
+
+    fn from_mut(value: &mut Value) -> Option<&mut Self> {
+        match value {
+            Value::Hash(hash) => Some(Arc::make_mut(hash)),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Hash(Arc::new(self))
+    }
+}
+
+impl Typed for HashSet<Bytes> {
+    fn from_ref(value: &Value) -> Option<&Self> {
+        match value {
+            Value::Set(set) => Some(set),
+            _ => None,
+        }
+    }
+
+    fn from_mut(value: &mut Value) -> Option<&mut Self> {
+        match value {
+            Value::Set(set) => Some(Arc::make_mut(set)),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Set(Arc::new(self))
+    }
+}
+
+impl Typed for SortedSet {
+    fn from_ref(value: &Value) -> Option<&Self> {
+        match value {
+            Value::ZSet(zset) => Some(zset),
+            _ => None,
+        }
+    }
+
+    fn from_mut(value: &mut Value) -> Option<&mut Self> {
+        match value {
+            Value::ZSet(zset) => Some(Arc::make_mut(zset)),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::ZSet(Arc::new(self))
+    }
+}
+
+impl fmt::Display for WrongType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "WRONGTYPE Operation against a key holding the wrong kind of value".fmt(f)
+    }
+}
+
+impl std::error::Error for WrongType {}
+
+impl From<WrongType> for Frame {
+    fn from(err: WrongType) -> Frame {
+        Frame::Error(err.to_string())
+    }
+}
+
+impl Entry {
+    fn expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|when| when <= now)
+    }
+}
+
+impl Shard {
+    /// 没有过期的entry。过期的entry还留在分片里, 版本号也没变, 见模块文档
+    fn live(&self, key: &str) -> Option<&Entry> {
+        self.entries.get(key).filter(|entry| !entry.expired(Instant::now()))
+    }
+
+    fn live_mut(&mut self, key: &str) -> Option<&mut Entry> {
+        self.entries.get_mut(key).filter(|entry| !entry.expired(Instant::now()))
+    }
+
+    /// 删除 `key`, 返回没有过期的值
+    fn remove(&mut self, key: &str) -> Option<Value> {
+        let entry = self.entries.remove(key)?;
+        self.touch(key);
+        if let Some(when) = entry.expires_at {
+            self.expirations.remove(&(when, key.to_string()));
+        }
+        (!entry.expired(Instant::now())).then_some(entry.data)
+    }
+
+    /// 删掉所有已经过期的key, 返回下一个key过期的时间
+    fn purge_expired(&mut self) -> Option<Instant> {
+        let now = Instant::now();
+        while let Some((when, key)) = self.expirations.pop_first() {
+            if when > now {
+                self.expirations.insert((when, key));
+                return Some(when);
+            }
+            self.entries.remove(&key);
+            self.touch(&key);
+        }
+        None
+    }
+
+    /// 修改了 `key`, 有人WATCH它的话版本号加一
+    fn touch(&mut self, key: &str) {
+        if let Some(watched) = self.watched.get_mut(key) {
+            watched.version += 1;
+        }
+    }
+}
+
+/// 一个分片的清理task: 删掉过期的key, 然后睡到下一个key过期, 或者被更早的过期时间叫醒
+async fn purge_expired_keys(shared: Weak<Shared>, notify: Arc<Notify>, index: usize) {
+    loop {
+        let next = match shared.upgrade() {
+            Some(shared) => shared.shards[index].lock().unwrap().purge_expired(),
+            None => return,
+        };
+
+        match next {
+            Some(when) => {
+                tokio::select! {
+                    _ = time::sleep_until(when) => {}
+                    _ = notify.notified() => {}
+                }
+            }
+            None => notify.notified().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::{Db, Value, WrongType};
+    use tokio::time::Instant;
+
+    /// 找到分别落在两个不同分片上的key
+    fn keys_on_two_shards(db: &Db) -> (String, String) {
+        let a = "key:0".to_string();
+        let b = (1..)
+            .map(|i| format!("key:{}", i))
+            .find(|k| db.shard_index(k) != db.shard_index(&a))
+            .unwrap();
+        (a, b)
+    }
+
+    #[test]
+    fn lock_multiple_shards() {
+        let db = Db::new(4);
+        let (a, b) = keys_on_two_shards(&db);
+
+        let mut guard = db.lock([&a, &b, &a]);
+        assert_eq!(guard.shards.len(), 2);
+        guard.insert(a.clone(), Bytes::from("1"));
+        guard.insert(b.clone(), Bytes::from("2"));
+        assert_eq!(guard.remove(&a), Some(Value::String(Bytes::from("1"))));
+        drop(guard);
+
+        assert!(db.lock([&b]).contains_key(&b));
+        assert!(!db.lock([&a]).contains_key(&a));
+    }
+
+    #[test]
+    fn opposite_order_does_not_deadlock() {
+        let db = Db::new(4);
+        let (a, b) = keys_on_two_shards(&db);
+
+        // 两个线程以相反的顺序传入key, 加锁顺序相同, 不会死锁
+        let (tx, rx) = mpsc::channel();
+        for keys in [[a.clone(), b.clone()], [b, a]] {
+            let db = db.clone();
+            let tx = tx.clone();
+            thread::spawn(move || {
+                for _ in 0..1000 {
+                    let mut guard = db.lock(&keys);
+                    guard.insert(keys[0].clone(), Bytes::new());
+                }
+                tx.send(()).unwrap();
+            });
+        }
+        for _ in 0..2 {
+            rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        }
+    }
+
+    #[test]
+    fn typed_access() {
+        let db = Db::new(4);
+        let mut guard = db.lock(["s", "l", "missing"]);
+        guard.insert("s".to_string(), Bytes::from("v"));
+        guard.list_or_default("l").unwrap().push_back(Bytes::from("a"));
+
+        assert_eq!(guard.get("l"), Err(WrongType));
+        assert_eq!(guard.list("s"), Err(WrongType));
+        assert_eq!(guard.list_or_default("s"), Err(WrongType));
+        assert_eq!(guard.value("s").map(Value::type_name), Some("string"));
+        assert_eq!(guard.value("l").map(Value::type_name), Some("list"));
+        assert_eq!(guard.list("missing"), Ok(None));
+    }
+
+    #[test]
+    fn copy_shares_collections_until_written() {
+        let db = Db::new(4);
+        let mut guard = db.lock_all();
+        guard.list_or_default("l").unwrap().push_back(Bytes::from("a"));
+
+        let copy = guard.copy();
+        let copied = match &copy[0].1 {
+            Value::List(list) => list.clone(),
+            value => panic!("expected a list, got {:?}", value),
+        };
+        assert!(matches!(guard.value("l"), Some(Value::List(list)) if Arc::ptr_eq(list, &copied)));
+
+        // 修改时复制一份, 之前的副本不变
+        guard.list_mut("l").unwrap().unwrap().push_back(Bytes::from("b"));
+        assert_eq!(guard.list("l").unwrap().unwrap().len(), 2);
+        assert_eq!(copied.len(), 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn access_unlocked_key() {
+        let db = Db::new(4);
+        let (a, b) = keys_on_two_shards(&db);
+        db.lock([&a]).value(&b);
+    }
+
+    #[tokio::test]
+    async fn purge_expired_keys() {
+        let db = Db::new(2);
+        db.spawn_purge_tasks();
+
+        let (a, b) = keys_on_two_shards(&db);
+        {
+            let mut guard = db.lock([&a, &b]);
+            guard.insert(a.clone(), Bytes::from("1"));
+            guard.insert(b.clone(), Bytes::from("2"));
+            guard.set_expiry(&a, Some(Instant::now() + Duration::from_secs(60)));
+            // 比清理task正在等的时间更早, 要把它叫醒
+            guard.set_expiry(&a, Some(Instant::now() + Duration::from_millis(20)));
+            guard.set_expiry(&b, Some(Instant::now() + Duration::from_millis(40)));
+        }
+
+        // 没有人访问, 清理task也会把它们从分片里真正删掉
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        for key in [&a, &b] {
+            let index = db.shard_index(key);
+            let shard = db.shared.shards[index].lock().unwrap();
+            assert!(shard.entries.is_empty());
+            assert!(shard.expirations.is_empty());
+        }
+    }
+}
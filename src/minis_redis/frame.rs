@@ -0,0 +1,863 @@
+//! Provides a type representing a Redis protocol frame as well as utilities for
+//! parsing frames from a byte array.
+//!
+//! 除了RESP2的类型之外, 还支持RESP3新增的类型(map, set, double, boolean,
+//! big number, verbatim string, attribute, push)。解析时两种协议的类型都接受,
+//! 编码时由连接协商好的协议版本决定(见 `Protocol`)。
+
+use bytes::{Buf, Bytes};
+use std::convert::TryInto;
+use std::fmt;
+use std::io::{self, Cursor};
+use std::num::TryFromIntError;
+use std::string::FromUtf8Error;
+
+/// A frame in the Redis protocol.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Frame {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Bytes),
+    Null,
+    Array(Vec<Frame>),
+    // ---- RESP3 ----
+    Map(Vec<(Frame, Frame)>),
+    Set(Vec<Frame>),
+    Double(f64),
+    Boolean(bool),
+    /// 任意精度整数, 以十进制字符串保存
+    BigNumber(String),
+    /// 带格式的字符串, `format` 是三个字节的格式名, 例如 `txt`、`mkd`
+    Verbatim { format: [u8; 3], data: Bytes },
+    /// 附加在另一个frame上的属性, 线上格式中属性紧挨在被修饰的frame之前
+    Attribute {
+        attributes: Vec<(Frame, Frame)>,
+        data: Box<Frame>,
+    },
+    /// 服务端主动推送的数据, 例如pub/sub消息
+    Push(Vec<Frame>),
+}
+
+/// 连接上使用的协议版本, 决定RESP3类型如何编码
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+/// 解析时的各项上限, 防止恶意或者有bug的对端耗尽内存
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// bulk string / verbatim string 的最大长度
+    pub max_bulk_len: usize,
+    /// 聚合类型(array, set, push, map, attribute)的最大元素个数
+    pub max_array_len: usize,
+    /// 读缓冲中最多缓存多少字节还没解析完的数据
+    pub max_buffer: usize,
+    /// 聚合类型的最大嵌套层数
+    pub max_depth: usize,
+    /// inline命令一行的最大长度
+    pub max_inline_len: usize,
+}
+
+/// 读写frame时可能发生的错误
+#[derive(Debug)]
+pub enum Error {
+    /// Not enough data is available to parse a message
+    Incomplete,
+
+    /// 数据不符合RESP协议
+    ProtocolViolation(String),
+
+    /// frame超过了 `Limits` 中的某项上限
+    FrameTooLarge(String),
+
+    /// 对端在一个frame传输到一半的时候断开了连接
+    ConnectionReset,
+
+    /// 没有在 `Timeouts` 规定的时间内读完或写完
+    Timeout(TimeoutKind),
+
+    /// 底层io错误
+    Io(io::Error),
+}
+
+/// 超时的是哪一种等待, 见 `connection::Timeouts`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeoutKind {
+    Idle,
+    Read,
+    Write,
+}
+
+impl Default for Limits {
+    /// 与redis的默认配置保持一致: bulk最大512MB, 查询缓冲最大1GB
+    fn default() -> Limits {
+        Limits {
+            max_bulk_len: 512 * 1024 * 1024,
+            max_array_len: 1024 * 1024,
+            max_buffer: 1024 * 1024 * 1024,
+            max_depth: 64,
+            max_inline_len: 64 * 1024,
+        }
+    }
+}
+
+impl Protocol {
+    /// 由 `HELLO` 命令中的协议版本号得到协议, 不支持的版本返回None
+    pub fn from_version(version: i64) -> Option<Protocol> {
+        match version {
+            2 => Some(Protocol::Resp2),
+            3 => Some(Protocol::Resp3),
+            _ => None,
+        }
+    }
+
+    pub fn version(self) -> i64 {
+        match self {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        }
+    }
+}
+
+impl Frame {
+    /// Returns an empty array
+    pub(crate) fn array() -> Frame {
+        Frame::Array(vec![])
+    }
+
+    /// Push a "bulk" frame into the array. `self` must be an Array frame.
+    ///
+    /// # Panics
+    ///
+    /// panics if `self` is not an array
+    pub(crate) fn push_bulk(&mut self, bytes: Bytes) {
+        match self {
+            Frame::Array(vec) => {
+                vec.push(Frame::Bulk(bytes));
+            }
+            _ => panic!("not an array frame"),
+        }
+    }
+
+    /// Converts the frame to an "unexpected frame" error
+    pub(crate) fn to_error(&self) -> crate::minis_redis::Error {
+        format!("unexpected frame: {}", self).into()
+    }
+
+    /// Checks if an entire message can be decoded from `src` without
+    /// exceeding `limits`.
+    ///
+    /// 长度在读到头部时就检查, 所以对端声明一个4GB的bulk string会立即返回
+    /// `FrameTooLarge`, 而不是等到数据真正到达
+    ///
+    /// 第一个字节不是RESP的类型前缀时按inline命令处理, 见 `parse_inline`
+    pub fn check(src: &mut Cursor<&[u8]>, limits: &Limits) -> Result<(), Error> {
+        if is_inline(peek_u8(src)?) {
+            return check_inline(src, limits);
+        }
+        check_nested(src, limits, 0)
+    }
+
+    /// The message has already been validated with `check`.
+    ///
+    /// bulk string 的内容会从 `src` 中复制出来。如果数据已经在一个 `Bytes`
+    /// 中, 用 `parse_shared` 可以避免复制
+    pub fn parse(src: &mut Cursor<&[u8]>) -> Result<Frame, Error> {
+        if is_inline(peek_u8(src)?) {
+            return parse_inline(src);
+        }
+        parse_from(src, None)
+    }
+
+    /// The message has already been validated with `check`.
+    ///
+    /// 与 `parse` 不同, bulk string 直接引用 `src` 的内存而不复制。通常 `src`
+    /// 是从读缓冲中 `split_to(..).freeze()` 得到的, 这样大的value从socket
+    /// 读进来之后就不会再被复制一次; 代价是只要还有frame在引用, 这块缓冲就
+    /// 不会被释放
+    pub fn parse_shared(src: &Bytes) -> Result<Frame, Error> {
+        let mut cursor = Cursor::new(&src[..]);
+        if is_inline(peek_u8(&mut cursor)?) {
+            return parse_inline(&mut cursor);
+        }
+        parse_from(&mut cursor, Some(src))
+    }
+}
+
+/// 读取bulk string的头部 `$<len>\r\n`, null bulk string返回None。
+///
+/// 用于流式读取很大的bulk string, 内容不进入内存, 所以不检查 `max_bulk_len`
+pub(crate) fn get_bulk_header(src: &mut Cursor<&[u8]>) -> Result<Option<u64>, Error> {
+    match get_u8(src)? {
+        b'$' => {}
+        actual => {
+            return Err(format!("protocol error; expected bulk string, got frame type byte `{}`", actual).into())
+        }
+    }
+    match get_decimal(src)? {
+        -1 => Ok(None),
+        len => Ok(Some(len.try_into()?)),
+    }
+}
+
+/// 给telnet、nc这类手工输入的客户端用的inline命令: 一行文本按空白分割成参数,
+/// 得到和客户端发送RESP数组一样的 `Frame::Array`, 每个参数是一个bulk string。
+///
+/// 分割规则和redis的 `sdssplitargs` 一样:
+///
+/// * 双引号中支持 `\n` `\r` `\t` `\b` `\a` `\xHH` 等转义, 用于输入空白和二进制数据
+/// * 单引号中只有 `\'` 是转义, 其他字符原样保留
+/// * 引号结束之后必须是空白或者行尾, 否则是协议错误
+///
+/// 行以 `\n` 结尾, 前面的 `\r` 可有可无。
+fn parse_inline(src: &mut Cursor<&[u8]>) -> Result<Frame, Error> {
+    let line = get_inline_line(src)?;
+    let args = split_inline_args(line)?;
+    Ok(Frame::Array(args.into_iter().map(|arg| Frame::Bulk(Bytes::from(arg))).collect()))
+}
+
+fn check_inline(src: &mut Cursor<&[u8]>, limits: &Limits) -> Result<(), Error> {
+    let start = src.position() as usize;
+    let line = match get_inline_line(src) {
+        Err(Error::Incomplete) if src.get_ref().len() - start > limits.max_inline_len => {
+            return Err(Error::FrameTooLarge(format!(
+                "inline request exceeds limit {}",
+                limits.max_inline_len
+            )))
+        }
+        res => res?,
+    };
+    if line.len() > limits.max_inline_len {
+        return Err(Error::FrameTooLarge(format!(
+            "inline request exceeds limit {}",
+            limits.max_inline_len
+        )));
+    }
+    // 引号不匹配这类错误在check阶段就报告
+    split_inline_args(line)?;
+    Ok(())
+}
+
+/// inline命令中一行空白的长度(包括换行), 不是空白行返回None。
+///
+/// redis会直接丢弃这样的行, 例如在telnet里直接按回车
+pub(crate) fn blank_inline_line(buf: &[u8]) -> Option<usize> {
+    if !is_inline(*buf.first()?) {
+        return None;
+    }
+    let end = buf.iter().position(|&b| b == b'\n')?;
+    if buf[..end].iter().all(u8::is_ascii_whitespace) {
+        Some(end + 1)
+    } else {
+        None
+    }
+}
+
+/// 不是任何RESP类型前缀的字节都当作inline命令的开始
+fn is_inline(first: u8) -> bool {
+    !matches!(
+        first,
+        b'+' | b'-' | b':' | b'$' | b'*' | b'_' | b',' | b'#' | b'(' | b'=' | b'~' | b'>' | b'%' | b'|'
+    )
+}
+
+/// 读取以 `\n` 结尾的一行, 去掉结尾的 `\r`
+fn get_inline_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
+    let start = src.position() as usize;
+    let buf = *src.get_ref();
+    let end = match buf[start..].iter().position(|&b| b == b'\n') {
+        Some(n) => start + n,
+        None => return Err(Error::Incomplete),
+    };
+    src.set_position((end + 1) as u64);
+
+    let line = &buf[start..end];
+    Ok(line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+fn split_inline_args(line: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+    let unbalanced = || Error::from("protocol error; unbalanced quotes in inline request");
+    let mut args = Vec::new();
+    let mut i = 0;
+
+    loop {
+        // 跳过参数之间的空白
+        while i < line.len() && line[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == line.len() {
+            return Ok(args);
+        }
+
+        let mut arg = Vec::new();
+        // 当前在哪种引号中, 0表示不在引号中
+        let mut quote = 0u8;
+        loop {
+            let Some(&c) = line.get(i) else {
+                // 引号没有闭合就到了行尾
+                if quote != 0 {
+                    return Err(unbalanced());
+                }
+                break;
+            };
+
+            if quote == b'"' {
+                if c == b'\\' && i + 3 < line.len() && line[i + 1] == b'x' {
+                    if let Some(byte) = hex_byte(line[i + 2], line[i + 3]) {
+                        arg.push(byte);
+                        i += 4;
+                        continue;
+                    }
+                }
+                if c == b'\\' && i + 1 < line.len() {
+                    arg.push(match line[i + 1] {
+                        b'n' => b'\n',
+                        b'r' => b'\r',
+                        b't' => b'\t',
+                        b'b' => 0x08,
+                        b'a' => 0x07,
+                        other => other,
+                    });
+                    i += 2;
+                    continue;
+                }
+            } else if quote == b'\'' && c == b'\\' && line.get(i + 1) == Some(&b'\'') {
+                arg.push(b'\'');
+                i += 2;
+                continue;
+            }
+
+            if quote != 0 && c == quote {
+                // 闭合的引号后面必须是空白或者行尾
+                if line.get(i + 1).is_some_and(|b| !b.is_ascii_whitespace()) {
+                    return Err(unbalanced());
+                }
+                i += 1;
+                break;
+            }
+            if quote == 0 {
+                if c.is_ascii_whitespace() {
+                    break;
+                }
+                if c == b'"' || c == b'\'' {
+                    quote = c;
+                    i += 1;
+                    continue;
+                }
+            }
+            arg.push(c);
+            i += 1;
+        }
+        args.push(arg);
+    }
+}
+
+fn hex_byte(hi: u8, lo: u8) -> Option<u8> {
+    let hi = (hi as char).to_digit(16)?;
+    let lo = (lo as char).to_digit(16)?;
+    Some((hi * 16 + lo) as u8)
+}
+
+/// `owner` 是 `src` 底层数据所在的 `Bytes`, 有的话bulk string直接切片引用
+fn parse_from(src: &mut Cursor<&[u8]>, owner: Option<&Bytes>) -> Result<Frame, Error> {
+    match get_u8(src)? {
+        b'+' => {
+            // Read the line and convert it to `Vec<u8>`
+            let line = get_line(src)?.to_vec();
+
+            // Convert the line to a String
+            let string = String::from_utf8(line)?;
+
+            Ok(Frame::Simple(string))
+        }
+        b'-' => {
+            // Read the line and convert it to `Vec<u8>`
+            let line = get_line(src)?.to_vec();
+
+            // Convert the line to a String
+            let string = String::from_utf8(line)?;
+
+            Ok(Frame::Error(string))
+        }
+        b':' => {
+            let val = get_decimal(src)?;
+            Ok(Frame::Integer(val))
+        }
+        b'_' => {
+            if !get_line(src)?.is_empty() {
+                return Err("protocol error; invalid frame format".into());
+            }
+            Ok(Frame::Null)
+        }
+        b',' => {
+            let line = get_line(src)?;
+            let val = std::str::from_utf8(line)
+                .ok()
+                .and_then(|s| s.parse::<f64>().ok())
+                .ok_or("protocol error; invalid double")?;
+            Ok(Frame::Double(val))
+        }
+        b'#' => match get_line(src)? {
+            b"t" => Ok(Frame::Boolean(true)),
+            b"f" => Ok(Frame::Boolean(false)),
+            _ => Err("protocol error; invalid boolean".into()),
+        },
+        b'(' => {
+            let line = get_line(src)?;
+            let digits = line.strip_prefix(b"-").unwrap_or(line);
+            if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+                return Err("protocol error; invalid big number".into());
+            }
+            Ok(Frame::BigNumber(String::from_utf8(line.to_vec())?))
+        }
+        b'$' => {
+            if b'-' == peek_u8(src)? {
+                let line = get_line(src)?;
+
+                if line != b"-1" {
+                    return Err("protocol error; invalid frame format".into());
+                }
+
+                Ok(Frame::Null)
+            } else {
+                Ok(Frame::Bulk(get_blob(src, owner)?))
+            }
+        }
+        b'=' => {
+            let data = get_blob(src, owner)?;
+            // 格式为 `xxx:<data>`
+            if data.len() < 4 || data[3] != b':' {
+                return Err("protocol error; invalid verbatim string".into());
+            }
+            let mut format = [0u8; 3];
+            format.copy_from_slice(&data[..3]);
+            Ok(Frame::Verbatim {
+                format,
+                data: data.slice(4..),
+            })
+        }
+        b'*' => {
+            if b'-' == peek_u8(src)? {
+                let line = get_line(src)?;
+
+                if line != b"-1" {
+                    return Err("protocol error; invalid frame format".into());
+                }
+
+                // RESP2 的 null array
+                return Ok(Frame::Null);
+            }
+            Ok(Frame::Array(parse_list(src, owner)?))
+        }
+        b'~' => Ok(Frame::Set(parse_list(src, owner)?)),
+        b'>' => Ok(Frame::Push(parse_list(src, owner)?)),
+        b'%' => Ok(Frame::Map(parse_pairs(src, owner)?)),
+        b'|' => {
+            let attributes = parse_pairs(src, owner)?;
+            let data = Box::new(parse_from(src, owner)?);
+            Ok(Frame::Attribute { attributes, data })
+        }
+        actual => Err(format!("protocol error; invalid frame type byte `{}`", actual).into()),
+    }
+}
+
+impl PartialEq<&str> for Frame {
+    fn eq(&self, other: &&str) -> bool {
+        match self {
+            Frame::Simple(s) => s.eq(other),
+            Frame::Bulk(s) => s.eq(other),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use std::str;
+
+        match self {
+            Frame::Simple(response) => response.fmt(fmt),
+            Frame::Error(msg) => write!(fmt, "error: {}", msg),
+            Frame::Integer(num) => num.fmt(fmt),
+            Frame::Bulk(msg) | Frame::Verbatim { data: msg, .. } => match str::from_utf8(msg) {
+                Ok(string) => string.fmt(fmt),
+                Err(_) => write!(fmt, "{:?}", msg),
+            },
+            Frame::Null => "(nil)".fmt(fmt),
+            Frame::Double(val) => format_double(*val).fmt(fmt),
+            Frame::Boolean(val) => val.fmt(fmt),
+            Frame::BigNumber(val) => val.fmt(fmt),
+            Frame::Array(parts) | Frame::Set(parts) | Frame::Push(parts) => {
+                for (i, part) in parts.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, " ")?;
+                    }
+                    part.fmt(fmt)?;
+                }
+
+                Ok(())
+            }
+            Frame::Map(pairs) => {
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, " ")?;
+                    }
+                    write!(fmt, "{} {}", key, value)?;
+                }
+
+                Ok(())
+            }
+            Frame::Attribute { data, .. } => data.fmt(fmt),
+        }
+    }
+}
+
+/// 把double格式化为RESP3中的文本形式
+pub(crate) fn format_double(val: f64) -> String {
+    if val.is_nan() {
+        "nan".to_string()
+    } else if val.is_infinite() {
+        if val > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        val.to_string()
+    }
+}
+
+fn check_nested(src: &mut Cursor<&[u8]>, limits: &Limits, depth: usize) -> Result<(), Error> {
+    match get_u8(src)? {
+        b'+' | b'-' | b'_' | b',' | b'#' | b'(' => {
+            get_line(src)?;
+            Ok(())
+        }
+        b':' => {
+            let _ = get_decimal(src)?;
+            Ok(())
+        }
+        // Skip '-1\r\n', RESP2 的 null bulk string / null array
+        b'$' | b'*' if b'-' == peek_u8(src)? => skip(src, 4),
+        b'$' | b'=' => {
+            // Read the bulk string
+            let len = get_len(src, limits.max_bulk_len, "bulk")?;
+
+            // skip that number of bytes + 2 (\r\n).
+            skip(src, len + 2)
+        }
+        b'*' | b'~' | b'>' => {
+            let len = get_aggregate_len(src, limits, depth)?;
+
+            for _ in 0..len {
+                check_nested(src, limits, depth + 1)?;
+            }
+
+            Ok(())
+        }
+        b'%' => check_pairs(src, limits, depth),
+        b'|' => {
+            check_pairs(src, limits, depth)?;
+            // 属性后面紧跟着被修饰的frame
+            check_nested(src, limits, depth)
+        }
+        actual => Err(format!("protocol error; invalid frame type byte `{}`", actual).into()),
+    }
+}
+
+fn check_pairs(src: &mut Cursor<&[u8]>, limits: &Limits, depth: usize) -> Result<(), Error> {
+    let len = get_aggregate_len(src, limits, depth)?;
+
+    for _ in 0..len {
+        check_nested(src, limits, depth + 1)?;
+        check_nested(src, limits, depth + 1)?;
+    }
+
+    Ok(())
+}
+
+/// 读取聚合类型的元素个数, 同时检查个数和嵌套层数
+fn get_aggregate_len(src: &mut Cursor<&[u8]>, limits: &Limits, depth: usize) -> Result<usize, Error> {
+    if depth >= limits.max_depth {
+        return Err(Error::FrameTooLarge(format!(
+            "nesting depth exceeds limit {}",
+            limits.max_depth
+        )));
+    }
+    get_len(src, limits.max_array_len, "aggregate")
+}
+
+/// 读取一个非负的长度, 超过 `max` 返回 `FrameTooLarge`
+fn get_len(src: &mut Cursor<&[u8]>, max: usize, what: &str) -> Result<usize, Error> {
+    let len = get_decimal(src)?;
+    let len: usize = len
+        .try_into()
+        .map_err(|_| format!("protocol error; invalid {} length {}", what, len))?;
+    if len > max {
+        return Err(Error::FrameTooLarge(format!(
+            "{} length {} exceeds limit {}",
+            what, len, max
+        )));
+    }
+    Ok(len)
+}
+
+fn parse_list(src: &mut Cursor<&[u8]>, owner: Option<&Bytes>) -> Result<Vec<Frame>, Error> {
+    let len = get_decimal(src)?.try_into()?;
+    let mut out = Vec::with_capacity(capacity(src, len));
+
+    for _ in 0..len {
+        out.push(parse_from(src, owner)?);
+    }
+
+    Ok(out)
+}
+
+fn parse_pairs(src: &mut Cursor<&[u8]>, owner: Option<&Bytes>) -> Result<Vec<(Frame, Frame)>, Error> {
+    let len = get_decimal(src)?.try_into()?;
+    let mut out = Vec::with_capacity(capacity(src, len));
+
+    for _ in 0..len {
+        let key = parse_from(src, owner)?;
+        let value = parse_from(src, owner)?;
+        out.push((key, value));
+    }
+
+    Ok(out)
+}
+
+/// 为声明了 `len` 个元素的聚合类型预先分配的容量。
+///
+/// `parse` 是公开的, 调用者不一定先用 `check` 检查过长度, 每个元素至少占一个字节,
+/// 按 `src` 中剩下的字节数封顶, 免得一个伪造的长度就分配出巨大的内存
+fn capacity(src: &Cursor<&[u8]>, len: usize) -> usize {
+    let remaining = src.get_ref().len().saturating_sub(src.position() as usize);
+    len.min(remaining)
+}
+
+/// 读取 `<len>\r\n<data>\r\n` 形式的二进制安全字符串
+fn get_blob(src: &mut Cursor<&[u8]>, owner: Option<&Bytes>) -> Result<Bytes, Error> {
+    let len = get_decimal(src)?.try_into()?;
+    let n = len + 2;
+
+    if src.remaining() < n {
+        return Err(Error::Incomplete);
+    }
+
+    let start = src.position() as usize;
+    let data = match owner {
+        Some(owner) => owner.slice(start..start + len),
+        None => Bytes::copy_from_slice(&src.chunk()[..len]),
+    };
+
+    // skip that number of bytes + 2 (\r\n).
+    skip(src, n)?;
+
+    Ok(data)
+}
+
+fn peek_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
+    if !src.has_remaining() {
+        return Err(Error::Incomplete);
+    }
+
+    Ok(src.chunk()[0])
+}
+
+fn get_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
+    if !src.has_remaining() {
+        return Err(Error::Incomplete);
+    }
+
+    Ok(src.get_u8())
+}
+
+fn skip(src: &mut Cursor<&[u8]>, n: usize) -> Result<(), Error> {
+    if src.remaining() < n {
+        return Err(Error::Incomplete);
+    }
+
+    src.advance(n);
+    Ok(())
+}
+
+/// Read a new-line terminated decimal
+fn get_decimal(src: &mut Cursor<&[u8]>) -> Result<i64, Error> {
+    let line = get_line(src)?;
+
+    std::str::from_utf8(line)
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .ok_or_else(|| "protocol error; invalid frame format".into())
+}
+
+/// Find a line
+fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
+    // Scan the bytes directly
+    let start = src.position() as usize;
+    // Scan to the second to last byte
+    let end = src.get_ref().len().saturating_sub(1);
+
+    for i in start..end {
+        if src.get_ref()[i] == b'\r' && src.get_ref()[i + 1] == b'\n' {
+            // We found a line, update the position to be *after* the \n
+            src.set_position((i + 2) as u64);
+
+            // Return the line
+            return Ok(&src.get_ref()[start..i]);
+        }
+    }
+
+    Err(Error::Incomplete)
+}
+
+impl From<String> for Error {
+    fn from(src: String) -> Error {
+        Error::ProtocolViolation(src)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(src: &str) -> Error {
+        src.to_string().into()
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(_src: FromUtf8Error) -> Error {
+        "protocol error; invalid frame format".into()
+    }
+}
+
+impl From<TryFromIntError> for Error {
+    fn from(_src: TryFromIntError) -> Error {
+        "protocol error; invalid frame format".into()
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(src: io::Error) -> Error {
+        Error::Io(src)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Incomplete => "stream ended early".fmt(fmt),
+            Error::ProtocolViolation(msg) => msg.fmt(fmt),
+            Error::FrameTooLarge(msg) => write!(fmt, "frame too large; {}", msg),
+            Error::ConnectionReset => "connection reset by peer".fmt(fmt),
+            Error::Timeout(kind) => write!(fmt, "{} timeout", kind),
+            Error::Io(err) => err.fmt(fmt),
+        }
+    }
+}
+
+impl fmt::Display for TimeoutKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimeoutKind::Idle => "idle".fmt(fmt),
+            TimeoutKind::Read => "read".fmt(fmt),
+            TimeoutKind::Write => "write".fmt(fmt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use bytes::{BufMut, Bytes, BytesMut};
+
+    use super::{Error, Frame, Limits};
+
+    fn parse_inline(line: &[u8]) -> Result<Frame, Error> {
+        let mut cursor = Cursor::new(line);
+        Frame::check(&mut cursor, &Limits::default())?;
+        assert_eq!(cursor.position() as usize, line.len());
+        Frame::parse(&mut Cursor::new(line))
+    }
+
+    fn args(parts: &[&str]) -> Frame {
+        Frame::Array(parts.iter().map(|p| Frame::Bulk(Bytes::copy_from_slice(p.as_bytes()))).collect())
+    }
+
+    #[test]
+    fn inline_commands() {
+        assert_eq!(parse_inline(b"PING\r\n").unwrap(), args(&["PING"]));
+        assert_eq!(parse_inline(b"  SET a   1\n").unwrap(), args(&["SET", "a", "1"]));
+        assert_eq!(
+            parse_inline(b"SET \"hello world\" 'it''s'\r\n").unwrap_err().to_string(),
+            "protocol error; unbalanced quotes in inline request"
+        );
+        assert_eq!(
+            parse_inline(b"SET \"a\\tb\\x41\\\"\" 'it\\'s' \"\"\r\n").unwrap(),
+            args(&["SET", "a\tbA\"", "it's", ""])
+        );
+        assert_eq!(parse_inline(b"GET k\"ey 1\"\n").unwrap(), args(&["GET", "key 1"]));
+        assert!(matches!(parse_inline(b"GET \"key\n"), Err(Error::ProtocolViolation(_))));
+        assert!(matches!(parse_inline(b"GET \"a\"b\n"), Err(Error::ProtocolViolation(_))));
+
+        // 没有换行说明还没读完
+        let mut cursor = Cursor::new(&b"SET a"[..]);
+        assert!(matches!(Frame::check(&mut cursor, &Limits::default()), Err(Error::Incomplete)));
+
+        let limits = Limits {
+            max_inline_len: 8,
+            ..Limits::default()
+        };
+        for raw in [&b"SET key value\r\n"[..], b"SET key value"] {
+            let mut cursor = Cursor::new(raw);
+            assert!(matches!(Frame::check(&mut cursor, &limits), Err(Error::FrameTooLarge(_))));
+        }
+    }
+
+    #[test]
+    fn parse_unchecked_huge_length() {
+        // 没有先 `check`, 伪造的长度不能让 `parse` 预先分配巨大的内存
+        for raw in [&b"*1152921504606846975\r\n:1\r\n"[..], b"%1152921504606846975\r\n"] {
+            assert!(matches!(Frame::parse(&mut Cursor::new(raw)), Err(Error::Incomplete)));
+        }
+    }
+
+    #[test]
+    fn parse_shared_references_source() {
+        let mut buf = BytesMut::new();
+        buf.put_slice(b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$5\r\nhello\r\n=8\r\ntxt:abcd\r\n");
+
+        let mut cursor = Cursor::new(&buf[..]);
+        Frame::check(&mut cursor, &Limits::default()).unwrap();
+        let len = cursor.position() as usize;
+
+        let data = buf.split_to(len).freeze();
+        let copied = Frame::parse(&mut Cursor::new(&data[..])).unwrap();
+        let shared = Frame::parse_shared(&data).unwrap();
+        assert_eq!(copied, shared);
+
+        // bulk string 的内容应该落在原始缓冲的地址范围内
+        let range = data.as_ptr() as usize..data.as_ptr() as usize + data.len();
+        match shared {
+            Frame::Array(parts) => match &parts[2] {
+                Frame::Bulk(value) => {
+                    assert_eq!(value, &Bytes::from("hello"));
+                    assert!(range.contains(&(value.as_ptr() as usize)));
+                }
+                frame => panic!("unexpected frame {:?}", frame),
+            },
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+
+        // 剩下的数据是下一个frame
+        assert_eq!(&buf[..], b"=8\r\ntxt:abcd\r\n");
+    }
+}
@@ -1 +1,27 @@
-mod connection;
\ No newline at end of file
+pub mod aof;
+pub mod blocked;
+pub mod blocking;
+pub mod client;
+pub mod cmd;
+pub mod connection;
+pub mod db;
+pub mod frame;
+mod parse;
+pub mod pool;
+pub mod pubsub;
+pub mod snapshot;
+pub mod sorted_set;
+pub mod stats;
+#[cfg(test)]
+mod test_util;
+pub mod transaction;
+
+pub use cmd::Command;
+pub use connection::Connection;
+pub use frame::Frame;
+
+/// Error returned by most functions.
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// A specialized `Result` type for minis_redis operations.
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,207 @@
+//! 连接读缓冲的共享池。
+//!
+//! 每个连接的读缓冲都从池里借, 连接关闭时还回去给新连接复用。缓冲被一个很大的
+//! value撑大之后, 连接空闲超过 `shrink_after` 就把它换回标准大小的缓冲, 避免一次
+//! 大的SET让这个连接一直占着大块内存。池同时统计所有连接的读缓冲一共占了多少字节。
+
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::BytesMut;
+
+/// `BufferPool` 的配置
+#[derive(Clone, Copy, Debug)]
+pub struct PoolConfig {
+    /// 标准缓冲的大小, 新连接拿到的缓冲就是这么大
+    pub buffer_size: usize,
+    /// 池里最多留多少个空闲缓冲, 多出来的直接释放
+    pub max_pooled: usize,
+    /// 缓冲超过标准大小并且连接空闲了这么久, 就换回标准大小的缓冲; None表示不收缩
+    pub shrink_after: Option<Duration>,
+}
+
+impl Default for PoolConfig {
+    /// 和redis一样, 查询缓冲空闲2秒之后收缩
+    fn default() -> PoolConfig {
+        PoolConfig {
+            buffer_size: 4 * 1024,
+            max_pooled: 1024,
+            shrink_after: Some(Duration::from_secs(2)),
+        }
+    }
+}
+
+/// 读缓冲池, clone之后共享同一个池
+#[derive(Clone, Debug, Default)]
+pub struct BufferPool {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    config: PoolConfig,
+    free: Mutex<Vec<BytesMut>>,
+    /// 所有借出去的缓冲的容量之和
+    buffered: AtomicUsize,
+}
+
+/// 从 `BufferPool` 借来的读缓冲, drop时自动还回池里
+#[derive(Debug)]
+pub(crate) struct PooledBuffer {
+    buf: BytesMut,
+    pool: BufferPool,
+    /// 已经计入 `Inner::buffered` 的容量
+    counted: usize,
+}
+
+impl BufferPool {
+    pub fn new(config: PoolConfig) -> BufferPool {
+        BufferPool {
+            inner: Arc::new(Inner {
+                config,
+                free: Mutex::new(Vec::new()),
+                buffered: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    pub fn config(&self) -> &PoolConfig {
+        &self.inner.config
+    }
+
+    /// 所有连接的读缓冲当前一共占用的字节数(按 `BytesMut` 的容量计算)。
+    ///
+    /// 解析出的frame直接引用读缓冲的内存, 已经切给frame的部分不计算在内
+    pub fn buffered_bytes(&self) -> usize {
+        self.inner.buffered.load(Ordering::Relaxed)
+    }
+
+    /// 池里空闲的缓冲个数
+    pub fn pooled(&self) -> usize {
+        self.inner.free.lock().unwrap().len()
+    }
+
+    pub(crate) fn get(&self) -> PooledBuffer {
+        let mut buf = PooledBuffer {
+            buf: self.take(),
+            pool: self.clone(),
+            counted: 0,
+        };
+        buf.sync();
+        buf
+    }
+
+    fn take(&self) -> BytesMut {
+        match self.inner.free.lock().unwrap().pop() {
+            Some(buf) => buf,
+            None => BytesMut::with_capacity(self.inner.config.buffer_size),
+        }
+    }
+
+    fn put(&self, mut buf: BytesMut) {
+        let size = self.inner.config.buffer_size;
+        // 解析frame时前面的部分被切走了, 数据都不再被引用时reserve可以收回整块内存
+        buf.clear();
+        buf.reserve(size);
+        // 被撑大的缓冲不放回池里, 直接释放
+        if buf.capacity() > size * 2 {
+            return;
+        }
+        let mut free = self.inner.free.lock().unwrap();
+        if free.len() < self.inner.config.max_pooled {
+            free.push(buf);
+        }
+    }
+}
+
+impl PooledBuffer {
+    /// 缓冲的容量可能已经变化, 更新池中的统计
+    pub(crate) fn sync(&mut self) {
+        let capacity = self.buf.capacity();
+        let buffered = &self.pool.inner.buffered;
+        if capacity > self.counted {
+            buffered.fetch_add(capacity - self.counted, Ordering::Relaxed);
+        } else {
+            buffered.fetch_sub(self.counted - capacity, Ordering::Relaxed);
+        }
+        self.counted = capacity;
+    }
+
+    /// 缓冲为空且比标准大小大时, 返回需要空闲多久之后再收缩
+    pub(crate) fn shrink_after(&self) -> Option<Duration> {
+        let config = &self.pool.inner.config;
+        if self.buf.is_empty() && self.buf.capacity() > config.buffer_size {
+            config.shrink_after
+        } else {
+            None
+        }
+    }
+
+    /// 换成池里标准大小的缓冲
+    pub(crate) fn shrink(&mut self) {
+        let old = std::mem::replace(&mut self.buf, self.pool.take());
+        self.pool.put(old);
+        self.sync();
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = BytesMut;
+
+    fn deref(&self) -> &BytesMut {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut BytesMut {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        self.pool.inner.buffered.fetch_sub(self.counted, Ordering::Relaxed);
+        self.pool.put(std::mem::take(&mut self.buf));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{BufferPool, PoolConfig};
+
+    #[test]
+    fn reuse_and_shrink() {
+        let pool = BufferPool::new(PoolConfig {
+            buffer_size: 1024,
+            max_pooled: 1,
+            shrink_after: Some(Duration::from_secs(1)),
+        });
+
+        let mut a = pool.get();
+        let b = pool.get();
+        assert_eq!(pool.buffered_bytes(), 2048);
+        assert_eq!(a.shrink_after(), None);
+
+        // 被撑大之后才需要收缩, 收缩后回到标准大小
+        a.reserve(1024 * 1024);
+        a.sync();
+        assert!(pool.buffered_bytes() > 1024 * 1024);
+        assert_eq!(a.shrink_after(), Some(Duration::from_secs(1)));
+        a.shrink();
+        assert_eq!(a.capacity(), 1024);
+        assert_eq!(pool.buffered_bytes(), 2048);
+
+        // 归还之后池里最多留 max_pooled 个
+        drop(a);
+        drop(b);
+        assert_eq!(pool.buffered_bytes(), 0);
+        assert_eq!(pool.pooled(), 1);
+        let _c = pool.get();
+        assert_eq!(pool.pooled(), 0);
+    }
+}
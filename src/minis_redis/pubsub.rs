@@ -0,0 +1,481 @@
+//! 服务端的发布/订阅。
+//!
+//! 每个频道(以及每个PSUBSCRIBE的模式)对应一个 `tokio::sync::broadcast` channel,
+//! 有人订阅时创建, 最后一个订阅者退订时删除。PUBLISH把消息发给频道的订阅者,
+//! 再发给所有匹配这个频道名的模式的订阅者。
+//!
+//! 每个连接的订阅状态是一个 `Subscriptions`, 用 `StreamMap` 把它订阅的所有
+//! broadcast receiver合成一个消息流。
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use futures::stream::{self, Stream};
+use tokio::sync::broadcast;
+use tokio_stream::{StreamExt, StreamMap};
+use tracing::warn;
+
+use super::frame::Frame;
+
+/// 每个频道最多缓存多少条还没被所有订阅者收走的消息, 慢的订阅者会丢掉更早的消息
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// 所有频道和模式的登记表, clone之后共享同一份
+#[derive(Clone, Debug, Default)]
+pub struct PubSub {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    channels: HashMap<String, broadcast::Sender<Bytes>>,
+    /// 发给模式订阅者的是 (频道名, 消息)
+    patterns: HashMap<String, broadcast::Sender<(String, Bytes)>>,
+}
+
+/// 订阅推送给客户端的消息, 已经是编码好的frame
+type Messages = Pin<Box<dyn Stream<Item = Frame> + Send>>;
+
+/// 一个连接的订阅状态, drop时退订所有的频道和模式
+pub struct Subscriptions {
+    pubsub: PubSub,
+    channels: StreamMap<String, Messages>,
+    patterns: StreamMap<String, Messages>,
+}
+
+impl PubSub {
+    pub fn new() -> PubSub {
+        PubSub::default()
+    }
+
+    /// 发布消息, 返回收到消息的订阅数(频道的订阅者加上每个匹配的模式的订阅者)
+    pub fn publish(&self, channel: &str, message: Bytes) -> usize {
+        let inner = self.inner.lock().unwrap();
+
+        let mut receivers = match inner.channels.get(channel) {
+            // 没有订阅者时send返回错误, 不需要处理
+            Some(tx) => tx.send(message.clone()).unwrap_or(0),
+            None => 0,
+        };
+        for (pattern, tx) in &inner.patterns {
+            if glob_match(pattern.as_bytes(), channel.as_bytes()) {
+                receivers += tx.send((channel.to_string(), message.clone())).unwrap_or(0);
+            }
+        }
+        receivers
+    }
+
+    /// 当前有订阅者的频道数
+    pub fn num_channels(&self) -> usize {
+        self.inner.lock().unwrap().channels.len()
+    }
+
+    /// 当前有订阅者的模式数
+    pub fn num_patterns(&self) -> usize {
+        self.inner.lock().unwrap().patterns.len()
+    }
+
+    fn subscribe(&self, channel: &str) -> broadcast::Receiver<Bytes> {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.channels.get(channel) {
+            Some(tx) => tx.subscribe(),
+            None => {
+                let (tx, rx) = broadcast::channel(CHANNEL_CAPACITY);
+                inner.channels.insert(channel.to_string(), tx);
+                rx
+            }
+        }
+    }
+
+    fn psubscribe(&self, pattern: &str) -> broadcast::Receiver<(String, Bytes)> {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.patterns.get(pattern) {
+            Some(tx) => tx.subscribe(),
+            None => {
+                let (tx, rx) = broadcast::channel(CHANNEL_CAPACITY);
+                inner.patterns.insert(pattern.to_string(), tx);
+                rx
+            }
+        }
+    }
+
+    /// 订阅者的receiver已经drop, 频道没人订阅了就删掉
+    fn release(&self, channel: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.channels.get(channel).is_some_and(|tx| tx.receiver_count() == 0) {
+            inner.channels.remove(channel);
+        }
+    }
+
+    fn prelease(&self, pattern: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.patterns.get(pattern).is_some_and(|tx| tx.receiver_count() == 0) {
+            inner.patterns.remove(pattern);
+        }
+    }
+}
+
+impl Subscriptions {
+    pub fn new(pubsub: PubSub) -> Subscriptions {
+        Subscriptions {
+            pubsub,
+            channels: StreamMap::new(),
+            patterns: StreamMap::new(),
+        }
+    }
+
+    /// 订阅的频道数加上模式数
+    pub fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+
+    /// 什么都没有订阅, 连接应该回到普通模式
+    pub fn is_empty(&self) -> bool {
+        self.count() == 0
+    }
+
+    /// 订阅频道, 返回每个频道的确认消息
+    pub fn subscribe(&mut self, channels: &[String]) -> Vec<Frame> {
+        let mut replies = vec![];
+        for channel in channels {
+            if !self.channels.contains_key(channel) {
+                let rx = self.pubsub.subscribe(channel);
+                let name = channel.clone();
+                let messages =
+                    receive(rx).map(move |message| push(vec![bulk("message"), bulk(&name), Frame::Bulk(message)]));
+                self.channels.insert(channel.clone(), Box::pin(messages));
+            }
+            replies.push(self.reply("subscribe", Some(channel)));
+        }
+        replies
+    }
+
+    /// 订阅模式, 返回每个模式的确认消息
+    pub fn psubscribe(&mut self, patterns: &[String]) -> Vec<Frame> {
+        let mut replies = vec![];
+        for pattern in patterns {
+            if !self.patterns.contains_key(pattern) {
+                let rx = self.pubsub.psubscribe(pattern);
+                let name = pattern.clone();
+                let messages = receive(rx).map(move |(channel, message)| {
+                    push(vec![
+                        bulk("pmessage"),
+                        bulk(&name),
+                        bulk(&channel),
+                        Frame::Bulk(message),
+                    ])
+                });
+                self.patterns.insert(pattern.clone(), Box::pin(messages));
+            }
+            replies.push(self.reply("psubscribe", Some(pattern)));
+        }
+        replies
+    }
+
+    /// 退订频道, `channels` 为空表示退订所有的频道
+    pub fn unsubscribe(&mut self, channels: &[String]) -> Vec<Frame> {
+        let channels = match channels {
+            [] => self.channels.keys().cloned().collect(),
+            channels => channels.to_vec(),
+        };
+        if channels.is_empty() {
+            return vec![self.reply("unsubscribe", None)];
+        }
+
+        let mut replies = vec![];
+        for channel in channels {
+            if self.channels.remove(&channel).is_some() {
+                self.pubsub.release(&channel);
+            }
+            replies.push(self.reply("unsubscribe", Some(&channel)));
+        }
+        replies
+    }
+
+    /// 退订模式, `patterns` 为空表示退订所有的模式
+    pub fn punsubscribe(&mut self, patterns: &[String]) -> Vec<Frame> {
+        let patterns = match patterns {
+            [] => self.patterns.keys().cloned().collect(),
+            patterns => patterns.to_vec(),
+        };
+        if patterns.is_empty() {
+            return vec![self.reply("punsubscribe", None)];
+        }
+
+        let mut replies = vec![];
+        for pattern in patterns {
+            if self.patterns.remove(&pattern).is_some() {
+                self.pubsub.prelease(&pattern);
+            }
+            replies.push(self.reply("punsubscribe", Some(&pattern)));
+        }
+        replies
+    }
+
+    /// 等待下一条推送给这个连接的消息, 什么都没有订阅时返回 `None`
+    pub async fn next_message(&mut self) -> Option<Frame> {
+        tokio::select! {
+            Some((_, message)) = self.channels.next() => Some(message),
+            Some((_, message)) = self.patterns.next() => Some(message),
+            else => None,
+        }
+    }
+
+    /// (P)SUBSCRIBE/(P)UNSUBSCRIBE的确认: [kind, 频道或模式, 当前订阅数]
+    fn reply(&self, kind: &str, name: Option<&str>) -> Frame {
+        let name = name.map_or(Frame::Null, bulk);
+        push(vec![bulk(kind), name, Frame::Integer(self.count() as i64)])
+    }
+}
+
+impl Drop for Subscriptions {
+    fn drop(&mut self) {
+        let channels: Vec<String> = self.channels.keys().cloned().collect();
+        let patterns: Vec<String> = self.patterns.keys().cloned().collect();
+        // 先drop receiver, 再检查频道是不是没人订阅了
+        self.channels.clear();
+        self.patterns.clear();
+        for channel in channels {
+            self.pubsub.release(&channel);
+        }
+        for pattern in patterns {
+            self.pubsub.prelease(&pattern);
+        }
+    }
+}
+
+/// 把broadcast receiver变成消息流, 跟不上被丢掉的消息直接跳过
+fn receive<T: Clone + Send + 'static>(rx: broadcast::Receiver<T>) -> impl Stream<Item = T> + Send {
+    stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(message) => return Some((message, rx)),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!(skipped, "subscriber lagged behind, messages dropped");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+fn push(parts: Vec<Frame>) -> Frame {
+    Frame::Push(parts)
+}
+
+fn bulk(s: &str) -> Frame {
+    Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))
+}
+
+/// glob风格的模式匹配, 和redis的 `stringmatchlen` 规则一样:
+///
+/// * `*` 匹配任意多个字符, `?` 匹配一个字符
+/// * `[abc]`、`[a-z]` 匹配其中的一个字符, `[^abc]` 匹配不在其中的字符
+/// * `\` 转义下一个字符
+///
+/// 模式是客户端给的, 每次PUBLISH都要和所有的模式匹配一遍。除了 `*` 每一项都只匹配一个
+/// 字符, 所以只需要记住最近的一个 `*`: 后面匹配失败时让它多匹配一个字符再试, 之前的 `*`
+/// 不用再回头, 最坏 O(模式长度 * 字符串长度), 不会像递归那样指数爆炸
+pub fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // 最近的 `*` 之后的模式位置, 以及这个 `*` 匹配到了字符串的哪里
+    let mut star: Option<(usize, usize)> = None;
+
+    while s < string.len() {
+        if pattern.get(p) == Some(&b'*') {
+            p += 1;
+            star = Some((p, s));
+            continue;
+        }
+        if let Some(len) = match_one(&pattern[p..], string[s]) {
+            p += len;
+            s += 1;
+            continue;
+        }
+        // 匹配失败, 让最近的 `*` 多匹配一个字符
+        match star {
+            Some((star_p, star_s)) => {
+                star = Some((star_p, star_s + 1));
+                p = star_p;
+                s = star_s + 1;
+            }
+            None => return false,
+        }
+    }
+    // 字符串用完了, 剩下的模式只能是 `*`
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// 模式开头的一项(不是 `*`)是否匹配字符 `ch`, 匹配时返回这一项的长度
+fn match_one(p: &[u8], ch: u8) -> Option<usize> {
+    match p {
+        [] => None,
+        [b'?', ..] => Some(1),
+        [b'[', class @ ..] => {
+            let (matched, rest) = match_class(class, ch);
+            matched.then_some(p.len() - rest.len())
+        }
+        [b'\\', c, ..] => (*c == ch).then_some(2),
+        [c, ..] => (*c == ch).then_some(1),
+    }
+}
+
+/// 匹配 `[...]` 中的内容(`p` 从 `[` 之后开始), 返回是否匹配和 `]` 之后的模式。
+/// 没有 `]` 时到模式结尾为止
+fn match_class(mut p: &[u8], ch: u8) -> (bool, &[u8]) {
+    let negate = p.first() == Some(&b'^');
+    if negate {
+        p = &p[1..];
+    }
+
+    let mut matched = false;
+    loop {
+        match p {
+            [] => break,
+            [b']', rest @ ..] => {
+                p = rest;
+                break;
+            }
+            [b'\\', c, rest @ ..] => {
+                matched |= *c == ch;
+                p = rest;
+            }
+            [lo, b'-', hi, rest @ ..] if *hi != b']' => {
+                let (lo, hi) = if lo <= hi { (*lo, *hi) } else { (*hi, *lo) };
+                matched |= (lo..=hi).contains(&ch);
+                p = rest;
+            }
+            [c, rest @ ..] => {
+                matched |= *c == ch;
+                p = rest;
+            }
+        }
+    }
+    (matched != negate, p)
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use super::{glob_match, PubSub, Subscriptions};
+    use crate::minis_redis::frame::Frame;
+
+    fn bulk(s: &'static str) -> Frame {
+        Frame::Bulk(Bytes::from(s))
+    }
+
+    #[test]
+    fn glob_patterns() {
+        let cases: &[(&str, &str, bool)] = &[
+            ("news.*", "news.sport", true),
+            ("news.*", "news", false),
+            ("*", "", true),
+            ("h?llo", "hello", true),
+            ("h?llo", "hllo", false),
+            ("h*llo", "heeeello", true),
+            ("h[ae]llo", "hallo", true),
+            ("h[ae]llo", "hillo", false),
+            ("h[^e]llo", "hallo", true),
+            ("h[^e]llo", "hello", false),
+            ("h[a-b]llo", "hbllo", true),
+            ("h[b-a]llo", "hallo", true),
+            ("h\\*llo", "h*llo", true),
+            ("h\\*llo", "hello", false),
+            ("a*b*c", "axxbyyc", true),
+            ("a*b*c", "axxbyy", false),
+            ("**a", "ba", true),
+            ("*?", "", false),
+            ("a*[0-9]", "ab1c2", true),
+            ("a*[0-9]", "ab1c", false),
+        ];
+        for (pattern, string, expected) in cases {
+            assert_eq!(
+                glob_match(pattern.as_bytes(), string.as_bytes()),
+                *expected,
+                "{} {}",
+                pattern,
+                string
+            );
+        }
+    }
+
+    #[test]
+    fn glob_many_stars() {
+        // 递归回溯时是指数级的, 要很久才能算完
+        let pattern = "*a".repeat(30) + "b";
+        let string = "a".repeat(1000);
+        assert!(!glob_match(pattern.as_bytes(), string.as_bytes()));
+        assert!(glob_match(pattern.as_bytes(), (string + "b").as_bytes()));
+    }
+
+    #[tokio::test]
+    async fn publish_to_channels_and_patterns() {
+        let pubsub = PubSub::new();
+        let mut subs = Subscriptions::new(pubsub.clone());
+
+        let channels = vec!["news.sport".to_string()];
+        let patterns = vec!["news.*".to_string()];
+        assert_eq!(
+            subs.subscribe(&channels),
+            vec![Frame::Push(vec![
+                bulk("subscribe"),
+                bulk("news.sport"),
+                Frame::Integer(1)
+            ])]
+        );
+        subs.psubscribe(&patterns);
+        assert_eq!(subs.count(), 2);
+
+        // 频道的订阅者和匹配的模式各收到一次
+        assert_eq!(pubsub.publish("news.sport", Bytes::from("goal")), 2);
+        assert_eq!(pubsub.publish("news.tech", Bytes::from("rust")), 1);
+        assert_eq!(pubsub.publish("weather", Bytes::from("rain")), 0);
+
+        let mut received = vec![];
+        for _ in 0..3 {
+            received.push(subs.next_message().await.unwrap());
+        }
+        assert!(received.contains(&Frame::Push(vec![bulk("message"), bulk("news.sport"), bulk("goal")])));
+        assert!(received.contains(&Frame::Push(vec![
+            bulk("pmessage"),
+            bulk("news.*"),
+            bulk("news.sport"),
+            bulk("goal"),
+        ])));
+        assert!(received.contains(&Frame::Push(vec![
+            bulk("pmessage"),
+            bulk("news.*"),
+            bulk("news.tech"),
+            bulk("rust"),
+        ])));
+
+        // 全部退订之后频道被删掉
+        subs.unsubscribe(&[]);
+        assert_eq!(
+            subs.punsubscribe(&[]),
+            vec![Frame::Push(vec![
+                bulk("punsubscribe"),
+                bulk("news.*"),
+                Frame::Integer(0)
+            ])]
+        );
+        assert!(subs.is_empty());
+        assert_eq!(subs.next_message().await, None);
+        assert_eq!((pubsub.num_channels(), pubsub.num_patterns()), (0, 0));
+        assert_eq!(
+            subs.unsubscribe(&[]),
+            vec![Frame::Push(vec![bulk("unsubscribe"), Frame::Null, Frame::Integer(0)])]
+        );
+
+        // drop时也会退订
+        let mut subs = Subscriptions::new(pubsub.clone());
+        subs.subscribe(&channels);
+        assert_eq!(pubsub.num_channels(), 1);
+        drop(subs);
+        assert_eq!(pubsub.num_channels(), 0);
+    }
+}
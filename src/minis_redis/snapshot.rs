@@ -0,0 +1,617 @@
+//! 快照(SAVE / BGSAVE)持久化。
+//!
+//! 和AOF记录每一条命令不同, 快照把某一时刻的全部数据写成一个紧凑的二进制文件,
+//! 恢复时直接读进来, 不用重新执行命令。文件格式参考redis的RDB:
+//!
+//! ```text
+//! "MINISRDB" | version(u16) | entry* | 0xFF | crc32(u32)
+//!
+//! entry = [0xFC expire_at(i64, unix毫秒)] type key value
+//! type  = 0x00 string | 0x01 list | 0x02 hash | 0x03 set | 0x04 zset
+//! key / string = len(u32) bytes
+//! list / set = len(u32) string*
+//! hash = len(u32) (string string)*
+//! zset = len(u32) (string score(f64))*
+//! ```
+//!
+//! 整数都是小端序, crc32覆盖它前面的所有字节。
+//!
+//! 写快照时先按顺序锁住所有的分片(`Db::lock_all`), 复制出全部数据就放开锁。复制的
+//! 时间和key的个数成正比: 每个key复制一次key字符串, 值(`Bytes` 和 `Arc` 里的集合)
+//! 只增加引用计数, 和集合有多少元素无关; 之后有命令修改快照里的集合时才由
+//! `Arc::make_mut` 复制一份。写文件时不持有任何锁, 其他连接照常读写。快照是
+//! 同一时刻的数据, 跨分片的多key命令和EXEC执行的事务要么全在快照里, 要么都不在。
+//!
+//! 快照先写到临时文件, fsync之后再rename成 `dump-<unix毫秒>.rdb`, 目录里不会出现
+//! 写了一半的快照。rename之后还要fsync目录, 掉电之后新的快照才一定还在, 然后才删掉
+//! 旧的快照。最多保留 `KEEP` 个, 启动时从最新的开始加载, 校验失败就退回上一个。
+
+use std::convert::{TryFrom, TryInto};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::iter::FromIterator;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{error, info, warn};
+
+use super::db::{self, Db, Value};
+use super::sorted_set::SortedSet;
+
+const MAGIC: &[u8] = b"MINISRDB";
+/// 版本1只有string, 版本2加了list, 版本3加了hash、set和zset; 旧版本的快照都能读
+const VERSION: u16 = 3;
+
+const TYPE_STRING: u8 = 0x00;
+const TYPE_LIST: u8 = 0x01;
+const TYPE_HASH: u8 = 0x02;
+const TYPE_SET: u8 = 0x03;
+const TYPE_ZSET: u8 = 0x04;
+const OPCODE_EXPIRE_MS: u8 = 0xFC;
+const OPCODE_EOF: u8 = 0xFF;
+
+/// 目录里最多保留的快照个数
+const KEEP: usize = 2;
+
+/// 一个目录下的快照, clone之后共享同一个"正在保存"的状态
+#[derive(Clone, Debug)]
+pub struct Snapshots {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    dir: PathBuf,
+    /// 同一时间只能有一个SAVE或者BGSAVE
+    saving: AtomicBool,
+    /// 最近一次BGSAVE的线程, 关闭时要等它写完
+    background: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// 保存结束时把 `saving` 复位, 保存的线程panic了也一样
+struct Saving(Arc<Inner>);
+
+impl Snapshots {
+    /// 快照保存在 `dir` 目录下
+    pub fn new(dir: impl Into<PathBuf>) -> Snapshots {
+        Snapshots {
+            inner: Arc::new(Inner {
+                dir: dir.into(),
+                saving: AtomicBool::new(false),
+                background: Mutex::new(None),
+            }),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.inner.dir
+    }
+
+    /// 是否有SAVE或者BGSAVE正在进行
+    pub fn is_saving(&self) -> bool {
+        self.inner.saving.load(Ordering::Acquire)
+    }
+
+    /// 在当前线程写一个快照, 返回快照的路径
+    pub fn save(&self, db: &Db) -> crate::minis_redis::Result<PathBuf> {
+        let _saving = self.start()?;
+        self.write(&db.lock_all().copy())
+    }
+
+    /// 在当前线程把已经复制出的数据写成一个快照, 返回快照的路径
+    pub fn save_copy(&self, entries: &[(String, Value, Option<Instant>)]) -> crate::minis_redis::Result<PathBuf> {
+        let _saving = self.start()?;
+        self.write(entries)
+    }
+
+    /// 在后台线程写快照, 必须在tokio runtime中调用
+    pub fn spawn_save(&self, db: Db) -> crate::minis_redis::Result<()> {
+        let saving = self.start()?;
+        let snapshots = self.clone();
+        let handle = tokio::task::spawn_blocking(move || {
+            let _saving = saving;
+            match snapshots.write(&db.lock_all().copy()) {
+                Ok(path) => info!(path = %path.display(), "background saving finished"),
+                Err(err) => error!(%err, "background saving failed"),
+            }
+        });
+        *self.inner.background.lock().unwrap() = Some(handle);
+        Ok(())
+    }
+
+    /// 等待正在进行的BGSAVE写完, 没有时马上返回
+    pub async fn wait_background(&self) {
+        let handle = self.inner.background.lock().unwrap().take();
+        if let Some(handle) = handle {
+            if let Err(err) = handle.await {
+                error!(%err, "background saving panicked");
+            }
+        }
+    }
+
+    /// 从最新的快照开始尝试加载, 返回加载成功的快照和key的数量; 一个快照都没有时返回 `None`。
+    ///
+    /// `db` 应该是空的
+    pub fn load_newest(&self, db: &Db) -> crate::minis_redis::Result<Option<(PathBuf, usize)>> {
+        let snapshots = self.list()?;
+        for path in snapshots.iter().rev() {
+            match fs::read(path).map_err(Into::into).and_then(|data| load(&data, db)) {
+                Ok(keys) => return Ok(Some((path.clone(), keys))),
+                // 加载之前已经校验过整个文件, 失败时 `db` 里没有写进任何数据
+                Err(err) => warn!(path = %path.display(), %err, "skipping unreadable snapshot"),
+            }
+        }
+
+        if snapshots.is_empty() {
+            Ok(None)
+        } else {
+            Err(format!("no readable snapshot in {}", self.inner.dir.display()).into())
+        }
+    }
+
+    fn start(&self) -> crate::minis_redis::Result<Saving> {
+        if self.inner.saving.swap(true, Ordering::AcqRel) {
+            return Err("Background save already in progress".into());
+        }
+        Ok(Saving(self.inner.clone()))
+    }
+
+    fn write(&self, entries: &[(String, Value, Option<Instant>)]) -> crate::minis_redis::Result<PathBuf> {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = self.inner.dir.join(format!("dump-{}.rdb", millis));
+        let tmp = self.inner.dir.join(format!("temp-{}.rdb", millis));
+
+        let res = (|| -> io::Result<()> {
+            let mut file = BufWriter::new(File::create(&tmp)?);
+            encode(entries, &mut file)?;
+            file.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+            fs::rename(&tmp, &path)?;
+            // rename只改了目录, 目录也要落盘, 否则掉电之后新的快照可能不见了, 旧的却已经删了
+            sync_dir(&self.inner.dir)
+        })();
+        if let Err(err) = res {
+            let _ = fs::remove_file(&tmp);
+            return Err(err.into());
+        }
+
+        // 清理旧的快照失败不影响这次保存
+        if let Ok(snapshots) = self.list() {
+            for old in &snapshots[..snapshots.len().saturating_sub(KEEP)] {
+                if let Err(err) = fs::remove_file(old) {
+                    warn!(path = %old.display(), %err, "failed to remove old snapshot");
+                }
+            }
+        }
+        Ok(path)
+    }
+
+    /// 目录下所有的快照, 按时间从旧到新排列
+    fn list(&self) -> io::Result<Vec<PathBuf>> {
+        let mut snapshots = Vec::new();
+        let entries = match fs::read_dir(&self.inner.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let millis = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("dump-")?.strip_suffix(".rdb"))
+                .and_then(|millis| millis.parse::<u128>().ok());
+            if let Some(millis) = millis {
+                snapshots.push((millis, path));
+            }
+        }
+        snapshots.sort();
+        Ok(snapshots.into_iter().map(|(_, path)| path).collect())
+    }
+}
+
+impl Drop for Saving {
+    fn drop(&mut self) {
+        self.0.saving.store(false, Ordering::Release);
+    }
+}
+
+/// 把 `db` 此刻的数据按快照格式写进 `dst`, 返回写入的key的数量。
+///
+/// 只在复制数据时锁住所有的分片, 写 `dst` 的时候不持有任何锁
+pub fn dump(db: &Db, dst: &mut impl Write) -> io::Result<usize> {
+    let entries = db.lock_all().copy();
+    encode(&entries, dst)
+}
+
+/// 把复制出来的数据按快照格式写进 `dst`, 返回写入的key的数量
+fn encode(entries: &[(String, Value, Option<Instant>)], dst: &mut impl Write) -> io::Result<usize> {
+    let mut dst = Checksummed {
+        inner: dst,
+        hasher: crc32fast::Hasher::new(),
+    };
+    dst.write_all(MAGIC)?;
+    dst.write_all(&VERSION.to_le_bytes())?;
+
+    for (key, value, expires_at) in entries {
+        if let Some(when) = *expires_at {
+            dst.write_all(&[OPCODE_EXPIRE_MS])?;
+            dst.write_all(&db::unix_millis(when).to_le_bytes())?;
+        }
+        write_value(&mut dst, key, value)?;
+    }
+
+    dst.write_all(&[OPCODE_EOF])?;
+    let crc = dst.hasher.clone().finalize();
+    dst.inner.write_all(&crc.to_le_bytes())?;
+    Ok(entries.len())
+}
+
+/// fsync目录, 让目录中新建和rename的文件落盘
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    // 只有unix可以打开目录再fsync
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+/// 把快照 `data` 加载进 `db`, 返回加载的key的数量。
+///
+/// 先校验整个文件再写 `db`, 出错时 `db` 不会被修改
+pub fn load(data: &[u8], db: &Db) -> crate::minis_redis::Result<usize> {
+    let header = MAGIC.len() + 2;
+    if data.len() < header + 1 + 4 || !data.starts_with(MAGIC) {
+        return Err("not a snapshot file".into());
+    }
+    let version = u16::from_le_bytes([data[MAGIC.len()], data[MAGIC.len() + 1]]);
+    if !(1..=VERSION).contains(&version) {
+        return Err(format!("unsupported snapshot version {}", version).into());
+    }
+    let (body, crc) = data.split_at(data.len() - 4);
+    if crc32fast::hash(body) != u32::from_le_bytes(crc.try_into().unwrap()) {
+        return Err("snapshot checksum mismatch".into());
+    }
+
+    let mut entries = Vec::new();
+    let mut src = Reader { data: &body[header..] };
+    let mut expires_at = None;
+    loop {
+        match src.u8()? {
+            OPCODE_EXPIRE_MS => expires_at = Some(i64::from_le_bytes(src.take(8)?.try_into().unwrap())),
+            OPCODE_EOF if src.data.is_empty() => break,
+            OPCODE_EOF => return Err("trailing data after the end of snapshot".into()),
+            kind => {
+                let key = src.key()?;
+                let value = src.value(kind, version)?;
+                entries.push((key, value, expires_at.take()));
+            }
+        }
+    }
+
+    let keys = entries.len();
+    for (key, value, expires_at) in entries {
+        let mut guard = db.lock([&key]);
+        guard.insert(key.clone(), value);
+        // 保存之后已经过期的key, `set_expiry` 会直接删掉
+        if let Some(when) = expires_at.and_then(db::instant_at) {
+            guard.set_expiry(&key, Some(when));
+        }
+    }
+    Ok(keys)
+}
+
+fn write_value(dst: &mut impl Write, key: &str, value: &Value) -> io::Result<()> {
+    let kind = match value {
+        Value::String(_) => TYPE_STRING,
+        Value::List(_) => TYPE_LIST,
+        Value::Hash(_) => TYPE_HASH,
+        Value::Set(_) => TYPE_SET,
+        Value::ZSet(_) => TYPE_ZSET,
+    };
+    dst.write_all(&[kind])?;
+    write_bytes(dst, key.as_bytes())?;
+
+    match value {
+        Value::String(value) => write_bytes(dst, value),
+        Value::List(list) => {
+            write_len(dst, list.len())?;
+            list.iter().try_for_each(|item| write_bytes(dst, item))
+        }
+        Value::Hash(hash) => {
+            write_len(dst, hash.len())?;
+            hash.iter().try_for_each(|(field, value)| {
+                write_bytes(dst, field)?;
+                write_bytes(dst, value)
+            })
+        }
+        Value::Set(set) => {
+            write_len(dst, set.len())?;
+            set.iter().try_for_each(|member| write_bytes(dst, member))
+        }
+        Value::ZSet(zset) => {
+            write_len(dst, zset.len())?;
+            zset.iter().try_for_each(|(member, score)| {
+                write_bytes(dst, member)?;
+                dst.write_all(&score.to_le_bytes())
+            })
+        }
+    }
+}
+
+fn write_len(dst: &mut impl Write, len: usize) -> io::Result<()> {
+    let len = u32::try_from(len).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "value too large"))?;
+    dst.write_all(&len.to_le_bytes())
+}
+
+fn write_bytes(dst: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    write_len(dst, bytes.len())?;
+    dst.write_all(bytes)
+}
+
+/// 写入的同时计算crc32
+struct Checksummed<'a, W> {
+    inner: &'a mut W,
+    hasher: crc32fast::Hasher,
+}
+
+impl<W: Write> Write for Checksummed<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> crate::minis_redis::Result<&'a [u8]> {
+        if self.data.len() < n {
+            return Err("snapshot ends in the middle of an entry".into());
+        }
+        let (head, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> crate::minis_redis::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn len(&mut self) -> crate::minis_redis::Result<usize> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize)
+    }
+
+    fn bytes(&mut self) -> crate::minis_redis::Result<&'a [u8]> {
+        let len = self.len()?;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> crate::minis_redis::Result<Bytes> {
+        Ok(Bytes::copy_from_slice(self.bytes()?))
+    }
+
+    fn score(&mut self) -> crate::minis_redis::Result<f64> {
+        match f64::from_le_bytes(self.take(8)?.try_into().unwrap()) {
+            score if score.is_nan() => Err("snapshot contains a NaN score".into()),
+            score => Ok(score),
+        }
+    }
+
+    /// 长度加上 `len` 个元素
+    fn collect<T, C: FromIterator<T>>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> crate::minis_redis::Result<T>,
+    ) -> crate::minis_redis::Result<C> {
+        let len = self.len()?;
+        (0..len).map(|_| item(self)).collect()
+    }
+
+    /// `kind` 类型的值, 不认识的类型以及比 `version` 新的类型都是错误
+    fn value(&mut self, kind: u8, version: u16) -> crate::minis_redis::Result<Value> {
+        let value = match kind {
+            TYPE_STRING => Value::String(self.string()?),
+            TYPE_LIST if version >= 2 => Value::List(Arc::new(self.collect(Reader::string)?)),
+            TYPE_HASH if version >= 3 => Value::Hash(Arc::new(self.collect(|src| Ok((src.string()?, src.string()?)))?)),
+            TYPE_SET if version >= 3 => Value::Set(Arc::new(self.collect(Reader::string)?)),
+            TYPE_ZSET if version >= 3 => {
+                let mut zset = SortedSet::new();
+                let members: Vec<_> = self.collect(|src| Ok((src.string()?, src.score()?)))?;
+                for (member, score) in members {
+                    zset.insert(member, score);
+                }
+                Value::ZSet(Arc::new(zset))
+            }
+            kind => return Err(format!("unknown snapshot entry type {:#04x}", kind).into()),
+        };
+        Ok(value)
+    }
+
+    fn key(&mut self) -> crate::minis_redis::Result<String> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| "snapshot key is not valid utf-8".into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashMap, HashSet, VecDeque};
+    use std::fs;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use tokio::time::Instant;
+
+    use super::{dump, load, Snapshots};
+    use crate::minis_redis::db::{Db, Value};
+    use crate::minis_redis::sorted_set::SortedSet;
+
+    fn sample() -> Db {
+        let db = Db::new(4);
+        let mut guard = db.lock(["a", "b", "c", "l", "h", "s", "z"]);
+        guard.insert("a".to_string(), Bytes::from("1"));
+        guard.insert("b".to_string(), Bytes::from(vec![0u8, 255, b'\r', b'\n']));
+        guard.insert("c".to_string(), Bytes::from("3"));
+        guard.set_expiry("c", Some(Instant::now() + Duration::from_secs(100)));
+        guard.insert(
+            "l".to_string(),
+            Value::List(Arc::new(VecDeque::from(vec![Bytes::from("x"), Bytes::new()]))),
+        );
+        guard.insert("h".to_string(), Value::Hash(Arc::new(HashMap::from([(Bytes::from("f"), Bytes::from("v"))]))));
+        guard.insert("s".to_string(), Value::Set(Arc::new(HashSet::from([Bytes::from("m"), Bytes::from("n")]))));
+        let mut zset = SortedSet::new();
+        zset.insert(Bytes::from("a"), 1.5);
+        zset.insert(Bytes::from("b"), f64::NEG_INFINITY);
+        guard.insert("z".to_string(), Value::ZSet(Arc::new(zset)));
+        drop(guard);
+        db
+    }
+
+    #[test]
+    fn dump_and_load() {
+        let mut data = Vec::new();
+        let sample = sample();
+        assert_eq!(dump(&sample, &mut data).unwrap(), 7);
+
+        let db = Db::new(2);
+        assert_eq!(load(&data, &db).unwrap(), 7);
+        let guard = db.lock(["a", "b", "c", "l", "h", "s", "z"]);
+        assert_eq!(guard.get("a"), Ok(Some(&Bytes::from("1"))));
+        assert_eq!(guard.get("b"), Ok(Some(&Bytes::from(vec![0u8, 255, b'\r', b'\n']))));
+        assert_eq!(guard.list("l").unwrap().unwrap(), &[Bytes::from("x"), Bytes::new()]);
+        let expected = sample.lock(["h", "s", "z"]);
+        for key in ["h", "s", "z"] {
+            assert_eq!(guard.value(key), expected.value(key));
+        }
+        assert_eq!(guard.expires_at("a"), Some(None));
+        let ttl = guard.expires_at("c").unwrap().unwrap() - Instant::now();
+        assert!(ttl > Duration::from_secs(99) && ttl <= Duration::from_secs(100));
+    }
+
+    #[test]
+    fn dump_is_point_in_time() {
+        let db = Db::new(4);
+        let a = "a".to_string();
+        let b = (0..).map(|i| format!("b{}", i)).find(|k| db.shard_index(k) != db.shard_index(&a)).unwrap();
+        let writer = {
+            let (db, a, b) = (db.clone(), a.clone(), b.clone());
+            std::thread::spawn(move || {
+                // 一次同时改两个分片上的key, 和跨分片的MSET一样
+                for i in 0..2000 {
+                    let mut guard = db.lock([&a, &b]);
+                    guard.insert(a.clone(), Bytes::from(i.to_string()));
+                    guard.insert(b.clone(), Bytes::from(i.to_string()));
+                }
+            })
+        };
+        while !writer.is_finished() {
+            let mut data = Vec::new();
+            dump(&db, &mut data).unwrap();
+            let restored = Db::new(4);
+            load(&data, &restored).unwrap();
+            let guard = restored.lock([&a, &b]);
+            assert_eq!(guard.get(&a), guard.get(&b));
+        }
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn reject_corrupted_snapshot() {
+        let mut data = Vec::new();
+        dump(&sample(), &mut data).unwrap();
+
+        for corrupt in [
+            // 翻转一个字节
+            {
+                let mut data = data.clone();
+                data[20] ^= 1;
+                data
+            },
+            // 截断
+            data[..data.len() - 1].to_vec(),
+            b"garbage".to_vec(),
+        ] {
+            let db = Db::new(2);
+            assert!(load(&corrupt, &db).is_err());
+            assert!(!db.lock(["a"]).contains_key("a"));
+        }
+    }
+
+    #[test]
+    fn load_version_1() {
+        let snapshot = |entry: &[u8]| {
+            let mut data = b"MINISRDB\x01\x00".to_vec();
+            data.extend_from_slice(entry);
+            data.push(0xFF);
+            data.extend_from_slice(&crc32fast::hash(&data).to_le_bytes());
+            data
+        };
+
+        let db = Db::new(2);
+        let string = snapshot(b"\x00\x01\x00\x00\x00a\x01\x00\x00\x001");
+        assert_eq!(load(&string, &db).unwrap(), 1);
+        assert_eq!(db.lock(["a"]).get("a"), Ok(Some(&Bytes::from("1"))));
+
+        // 版本1还没有list
+        let list = snapshot(b"\x01\x01\x00\x00\x00l\x00\x00\x00\x00");
+        assert!(load(&list, &Db::new(2)).is_err());
+    }
+
+    #[tokio::test]
+    async fn load_newest_readable_snapshot() {
+        let dir = std::env::temp_dir().join(format!("minis-redis-snapshots-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let snapshots = Snapshots::new(&dir);
+        assert_eq!(snapshots.load_newest(&Db::new(2)).unwrap(), None);
+
+        let db = sample();
+        let older = snapshots.save(&db).unwrap();
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        db.lock(["a"]).insert("a".to_string(), Bytes::from("2"));
+        let newer = snapshots.save(&db).unwrap();
+
+        let restored = Db::new(2);
+        assert_eq!(snapshots.load_newest(&restored).unwrap(), Some((newer.clone(), 7)));
+        assert_eq!(restored.lock(["a"]).get("a"), Ok(Some(&Bytes::from("2"))));
+
+        // 最新的快照坏了, 退回上一个
+        fs::write(&newer, b"MINISRDB broken").unwrap();
+        let restored = Db::new(2);
+        assert_eq!(snapshots.load_newest(&restored).unwrap(), Some((older, 7)));
+        assert_eq!(restored.lock(["a"]).get("a"), Ok(Some(&Bytes::from("1"))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn wait_background_save() {
+        let dir = std::env::temp_dir().join(format!("minis-redis-bgsave-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let snapshots = Snapshots::new(&dir);
+        snapshots.wait_background().await;
+
+        snapshots.spawn_save(sample()).unwrap();
+        snapshots.wait_background().await;
+        assert!(!snapshots.is_saving());
+        assert_eq!(snapshots.list().unwrap().len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
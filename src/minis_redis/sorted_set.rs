@@ -0,0 +1,416 @@
+//! 有序集合(ZSET)。
+//!
+//! 每个成员有一个分数, 成员按 (分数, 成员) 排序。和redis一样, 排序的索引是一个跳表,
+//! 另外用 `HashMap` 记录每个成员的分数。跳表的每个指针都记录了它跨过了几个节点(span),
+//! 沿着指针往下走的同时把span加起来, 就知道了节点的排名:
+//!
+//! * 按成员查分数: `HashMap` O(1)
+//! * 增删成员、按成员查排名(ZRANK): O(log n)
+//! * 按分数或者排名的范围查询(ZRANGEBYSCORE、ZRANGE): O(log n) 找到起点, 之后顺序遍历, O(log n + k)
+//!
+//! 跳表的节点放在一个 `Vec` 里, 指针就是下标, 删掉的节点的位置留给之后插入的节点。
+//!
+//! 分数不会是NaN, 命令在解析和计算时就把NaN挡在外面了。
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ops::Bound;
+
+use bytes::Bytes;
+use rand::Rng;
+
+#[derive(Clone, Debug, Default)]
+pub struct SortedSet {
+    scores: HashMap<Bytes, f64>,
+    index: SkipList,
+}
+
+/// 跳表最多的层数, 每个节点有 1/4 的概率多一层, 32层足够放下 4^32 个节点
+const MAX_LEVEL: usize = 32;
+
+/// 表头在 `nodes` 中的位置
+const HEAD: usize = 0;
+
+/// 按 (分数, 成员) 排序的跳表
+#[derive(Clone, Debug)]
+struct SkipList {
+    /// 第一个是表头, 它不是成员, 有 `MAX_LEVEL` 层
+    nodes: Vec<Node>,
+    /// 删掉的节点的位置
+    free: Vec<usize>,
+    /// 现在用到了几层
+    level: usize,
+    len: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    score: f64,
+    member: Bytes,
+    /// 每一层指向的下一个节点, 从最下面一层开始
+    levels: Vec<Link>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Link {
+    next: Option<usize>,
+    /// 从这个节点走到 `next` 排名增加了多少; `next` 是 `None` 时, 是到最后一个节点的距离
+    span: usize,
+}
+
+impl SortedSet {
+    pub fn new() -> SortedSet {
+        SortedSet::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// 加入 `member` 或者修改它的分数, 新加入的成员返回true
+    pub fn insert(&mut self, member: Bytes, score: f64) -> bool {
+        assert!(!score.is_nan(), "score of a sorted set member can not be NaN");
+        // `total_cmp` 认为 -0.0 < 0.0, 统一成0.0, 否则按分数查找时会漏掉
+        let score = score + 0.0;
+        let old = self.scores.insert(member.clone(), score);
+        if let Some(old) = old {
+            self.index.remove(old, &member);
+        }
+        self.index.insert(score, member);
+        old.is_none()
+    }
+
+    /// 删除 `member`, 成员存在时返回true
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => {
+                self.index.remove(score, member);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// `member` 按分数从小到大的排名, 从0开始
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.score(member)?;
+        Some(self.index.rank(score, member))
+    }
+
+    /// 按分数从小到大遍历所有成员
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, f64)> {
+        self.index.iter_from(self.index.first())
+    }
+
+    /// 排名在 `start..=stop` 之间的成员
+    pub fn range_by_rank(&self, start: usize, stop: usize) -> impl Iterator<Item = (&Bytes, f64)> {
+        self.index
+            .iter_from(self.index.by_rank(start))
+            .take(stop.saturating_add(1).saturating_sub(start))
+    }
+
+    /// 分数在 `min` 和 `max` 之间的成员, 按分数从小到大
+    pub fn range_by_score(&self, min: Bound<f64>, max: Bound<f64>) -> impl Iterator<Item = (&Bytes, f64)> {
+        // 空的成员排在同样分数的最前面, 从它开始找就不会漏掉分数等于 `min` 的成员
+        let start = match min {
+            Bound::Included(score) | Bound::Excluded(score) => self.index.first_not_before(score, b""),
+            Bound::Unbounded => self.index.first(),
+        };
+        self.index
+            .iter_from(start)
+            .skip_while(move |&(_, score)| matches!(min, Bound::Excluded(min) if score == min))
+            .take_while(move |&(_, score)| match max {
+                Bound::Included(max) => score <= max,
+                Bound::Excluded(max) => score < max,
+                Bound::Unbounded => true,
+            })
+    }
+}
+
+impl PartialEq for SortedSet {
+    fn eq(&self, other: &SortedSet) -> bool {
+        self.scores == other.scores
+    }
+}
+
+// 分数不会是NaN, 相等关系是完整的
+impl Eq for SortedSet {}
+
+impl Default for SkipList {
+    fn default() -> SkipList {
+        let head = Node {
+            score: 0.0,
+            member: Bytes::new(),
+            levels: vec![Link { next: None, span: 0 }; MAX_LEVEL],
+        };
+        SkipList {
+            nodes: vec![head],
+            free: Vec::new(),
+            level: 1,
+            len: 0,
+        }
+    }
+}
+
+impl SkipList {
+    /// 第一个节点
+    fn first(&self) -> Option<usize> {
+        self.nodes[HEAD].levels[0].next
+    }
+
+    /// 节点 `node` 和 (`score`, `member`) 比较
+    fn cmp(&self, node: usize, score: f64, member: &[u8]) -> Ordering {
+        let node = &self.nodes[node];
+        node.score
+            .total_cmp(&score)
+            .then_with(|| node.member[..].cmp(member))
+    }
+
+    /// 从上往下找到每一层中排在 (`score`, `member`) 前面的最后一个节点, 以及这些节点的排名。
+    ///
+    /// 表头的排名是0, 第一个节点是1
+    fn find(&self, score: f64, member: &[u8]) -> ([usize; MAX_LEVEL], [usize; MAX_LEVEL]) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEAD;
+        let mut traversed = 0;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].next {
+                if self.cmp(next, score, member) != Ordering::Less {
+                    break;
+                }
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+            rank[i] = traversed;
+        }
+        (update, rank)
+    }
+
+    /// 第一个不排在 (`score`, `member`) 前面的节点
+    fn first_not_before(&self, score: f64, member: &[u8]) -> Option<usize> {
+        let (update, _) = self.find(score, member);
+        self.nodes[update[0]].levels[0].next
+    }
+
+    /// 插入一个新的成员, 调用者保证它不在跳表里
+    fn insert(&mut self, score: f64, member: Bytes) {
+        let (update, rank) = self.find(score, &member);
+
+        let level = random_level();
+        if level > self.level {
+            // 表头新用到的层直接指到结尾
+            for i in self.level..level {
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let mut levels = Vec::with_capacity(level);
+        for i in 0..level {
+            let prev = &mut self.nodes[update[i]].levels[i];
+            // 新节点排在 `prev` 后面 `rank[0] - rank[i] + 1` 的位置
+            let distance = rank[0] - rank[i];
+            levels.push(Link {
+                next: prev.next,
+                span: prev.span - distance,
+            });
+            prev.span = distance + 1;
+        }
+        let node = Node { score, member, levels };
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        for (i, &prev) in update.iter().enumerate().take(level) {
+            self.nodes[prev].levels[i].next = Some(id);
+        }
+        // 更高的层跨过了新节点
+        for (i, &prev) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[prev].levels[i].span += 1;
+        }
+        self.len += 1;
+    }
+
+    /// 删除 (`score`, `member`), 调用者保证它在跳表里
+    fn remove(&mut self, score: f64, member: &[u8]) {
+        let (update, _) = self.find(score, member);
+        let id = self.nodes[update[0]].levels[0].next.unwrap();
+        debug_assert_eq!(self.cmp(id, score, member), Ordering::Equal);
+
+        for (i, &prev) in update.iter().enumerate().take(self.level) {
+            if self.nodes[prev].levels[i].next == Some(id) {
+                let removed = self.nodes[id].levels[i];
+                let prev = &mut self.nodes[prev].levels[i];
+                prev.span += removed.span;
+                prev.span -= 1;
+                prev.next = removed.next;
+            } else {
+                self.nodes[prev].levels[i].span -= 1;
+            }
+        }
+        while self.level > 1 && self.nodes[HEAD].levels[self.level - 1].next.is_none() {
+            self.nodes[HEAD].levels[self.level - 1].span = 0;
+            self.level -= 1;
+        }
+
+        // 释放成员的内存, 位置留给之后插入的节点
+        let node = &mut self.nodes[id];
+        node.member = Bytes::new();
+        node.levels = Vec::new();
+        self.free.push(id);
+        self.len -= 1;
+    }
+
+    /// (`score`, `member`) 的排名, 从0开始, 调用者保证它在跳表里
+    fn rank(&self, score: f64, member: &[u8]) -> usize {
+        // 排在它前面的节点的个数就是它的排名
+        let (_, rank) = self.find(score, member);
+        rank[0]
+    }
+
+    /// 排名是 `rank` 的节点, 从0开始
+    fn by_rank(&self, rank: usize) -> Option<usize> {
+        if rank >= self.len {
+            return None;
+        }
+        // 表头的排名是0, 要找的节点是 `rank + 1`
+        let target = rank + 1;
+        let mut x = HEAD;
+        let mut traversed = 0;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].next {
+                let span = self.nodes[x].levels[i].span;
+                if traversed + span > target {
+                    break;
+                }
+                traversed += span;
+                x = next;
+            }
+            if traversed == target {
+                return Some(x);
+            }
+        }
+        unreachable!("rank {} is out of {} nodes", rank, self.len)
+    }
+
+    /// 从节点 `start` 开始按顺序遍历
+    fn iter_from(&self, start: Option<usize>) -> impl Iterator<Item = (&Bytes, f64)> {
+        std::iter::successors(start, move |&node| self.nodes[node].levels[0].next).map(move |node| {
+            let node = &self.nodes[node];
+            (&node.member, node.score)
+        })
+    }
+}
+
+/// 新节点的层数, 每多一层的概率是 1/4
+fn random_level() -> usize {
+    let mut rng = rand::thread_rng();
+    let mut level = 1;
+    while level < MAX_LEVEL && rng.gen_ratio(1, 4) {
+        level += 1;
+    }
+    level
+}
+
+#[cfg(test)]
+mod test {
+    use std::ops::Bound;
+
+    use bytes::Bytes;
+
+    use super::SortedSet;
+
+    fn members<'a>(iter: impl Iterator<Item = (&'a Bytes, f64)>) -> Vec<(&'a str, f64)> {
+        iter.map(|(member, score)| (std::str::from_utf8(member).unwrap(), score))
+            .collect()
+    }
+
+    #[test]
+    fn order_and_rank() {
+        let mut zset = SortedSet::new();
+        assert!(zset.insert(Bytes::from("b"), 2.0));
+        assert!(zset.insert(Bytes::from("a"), 2.0));
+        assert!(zset.insert(Bytes::from("c"), 1.0));
+        assert!(zset.insert(Bytes::from("d"), f64::NEG_INFINITY));
+        // 修改分数
+        assert!(!zset.insert(Bytes::from("d"), 3.0));
+
+        assert_eq!(zset.len(), 4);
+        assert_eq!(members(zset.iter()), [("c", 1.0), ("a", 2.0), ("b", 2.0), ("d", 3.0)]);
+        assert_eq!(zset.rank(b"c"), Some(0));
+        assert_eq!(zset.rank(b"b"), Some(2));
+        assert_eq!(zset.rank(b"x"), None);
+        assert_eq!(members(zset.range_by_rank(1, 2)), [("a", 2.0), ("b", 2.0)]);
+        assert_eq!(members(zset.range_by_rank(3, 100)), [("d", 3.0)]);
+
+        assert!(zset.remove(b"a"));
+        assert!(!zset.remove(b"a"));
+        assert_eq!(zset.score(b"a"), None);
+        assert_eq!(zset.rank(b"b"), Some(1));
+    }
+
+    #[test]
+    fn rank_and_range_of_large_set() {
+        const N: usize = 100_000;
+        let mut zset = SortedSet::new();
+        // 倒着插入, 每个新成员都排在最前面
+        for i in (0..N).rev() {
+            zset.insert(Bytes::from(format!("m{}", i)), i as f64);
+        }
+        // 删掉偶数的成员, 剩下的排名减半
+        for i in (0..N).step_by(2) {
+            assert!(zset.remove(format!("m{}", i).as_bytes()));
+        }
+        assert_eq!(zset.len(), N / 2);
+
+        for i in [1, N / 2 + 1, N - 3, N - 1] {
+            assert_eq!(zset.rank(format!("m{}", i).as_bytes()), Some(i / 2));
+        }
+        let tail = members(zset.range_by_rank(N / 2 - 2, N));
+        assert_eq!(tail, [("m99997", 99997.0), ("m99999", 99999.0)]);
+        assert!(zset.range_by_rank(N / 2, N).next().is_none());
+
+        // 修改分数, 把第一个成员挪到最后
+        zset.insert(Bytes::from("m1"), N as f64);
+        assert_eq!(zset.rank(b"m1"), Some(N / 2 - 1));
+        assert_eq!(zset.rank(b"m3"), Some(0));
+        assert_eq!(members(zset.range_by_rank(N / 2 - 1, N / 2 - 1)), [("m1", N as f64)]);
+    }
+
+    #[test]
+    fn range_by_score() {
+        let mut zset = SortedSet::new();
+        for (member, score) in [("a", 1.0), ("b", 2.0), ("c", 2.0), ("d", 3.0)] {
+            zset.insert(Bytes::from(member), score);
+        }
+
+        let range = |min, max| members(zset.range_by_score(min, max));
+        assert_eq!(
+            range(Bound::Included(2.0), Bound::Included(3.0)),
+            [("b", 2.0), ("c", 2.0), ("d", 3.0)]
+        );
+        assert_eq!(range(Bound::Excluded(2.0), Bound::Unbounded), [("d", 3.0)]);
+        assert_eq!(range(Bound::Unbounded, Bound::Excluded(2.0)), [("a", 1.0)]);
+        assert_eq!(
+            range(Bound::Included(f64::NEG_INFINITY), Bound::Included(f64::INFINITY)).len(),
+            4
+        );
+        assert!(range(Bound::Included(3.5), Bound::Unbounded).is_empty());
+    }
+}
@@ -0,0 +1,112 @@
+//! 连接的流量统计。
+//!
+//! 计数器都是原子变量, 放在 `Arc` 里由 `Connection`(以及拆分出来的读写半边)
+//! 和需要汇总统计的地方共享, 读取时不需要和连接所在的task同步。
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// 一个连接的流量计数器
+#[derive(Debug)]
+pub struct Stats {
+    created: Instant,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    frames_read: AtomicU64,
+    frames_written: AtomicU64,
+    parse_errors: AtomicU64,
+    /// 最后一次读写距离 `created` 的毫秒数
+    last_activity: AtomicU64,
+}
+
+/// `Stats` 在某一时刻的快照
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    /// 从socket读到的字节数
+    pub bytes_read: u64,
+    /// 编码写出的字节数
+    pub bytes_written: u64,
+    /// 解析出的frame个数
+    pub frames_read: u64,
+    /// 编码写出的frame个数
+    pub frames_written: u64,
+    /// 协议错误和超限错误的次数
+    pub parse_errors: u64,
+    /// 连接建立了多久
+    pub age: Duration,
+    /// 距离最后一次读写过了多久
+    pub idle: Duration,
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats {
+            created: Instant::now(),
+            bytes_read: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            frames_read: AtomicU64::new(0),
+            frames_written: AtomicU64::new(0),
+            parse_errors: AtomicU64::new(0),
+            last_activity: AtomicU64::new(0),
+        }
+    }
+
+    /// 读取当前的计数。各个计数器分别读取, 不保证彼此之间严格一致
+    pub fn snapshot(&self) -> Snapshot {
+        let age = self.created.elapsed();
+        let last_activity = Duration::from_millis(self.last_activity.load(Ordering::Relaxed));
+        Snapshot {
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            frames_read: self.frames_read.load(Ordering::Relaxed),
+            frames_written: self.frames_written.load(Ordering::Relaxed),
+            parse_errors: self.parse_errors.load(Ordering::Relaxed),
+            age,
+            idle: age.saturating_sub(last_activity),
+        }
+    }
+
+    pub(crate) fn add_read(&self, n: usize) {
+        self.bytes_read.fetch_add(n as u64, Ordering::Relaxed);
+        self.touch();
+    }
+
+    pub(crate) fn add_written(&self, n: usize) {
+        self.bytes_written.fetch_add(n as u64, Ordering::Relaxed);
+        self.touch();
+    }
+
+    pub(crate) fn frame_read(&self) {
+        self.frames_read.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn frame_written(&self) {
+        self.frames_written.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn parse_error(&self) {
+        self.parse_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn touch(&self) {
+        let now = self.created.elapsed().as_millis() as u64;
+        self.last_activity.fetch_max(now, Ordering::Relaxed);
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Stats {
+        Stats::new()
+    }
+}
+
+impl std::ops::AddAssign for Snapshot {
+    /// 汇总多个连接的计数; `age` 和 `idle` 没有可加的意义, 保持不变
+    fn add_assign(&mut self, other: Snapshot) {
+        self.bytes_read += other.bytes_read;
+        self.bytes_written += other.bytes_written;
+        self.frames_read += other.frames_read;
+        self.frames_written += other.frames_written;
+        self.parse_errors += other.parse_errors;
+    }
+}
@@ -0,0 +1,83 @@
+//! 各个模块的测试共用的小工具
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bytes::Bytes;
+
+use super::aof::{Aof, FsyncPolicy};
+use super::cmd::{Command, Session};
+use super::db::Db;
+use super::frame::Frame;
+
+/// 由命令名和参数组成的请求
+pub fn command(parts: &[&str]) -> Frame {
+    Frame::Array(parts.iter().map(|part| Frame::Bulk(Bytes::copy_from_slice(part.as_bytes()))).collect())
+}
+
+/// 在 `db` 上执行一条访问key的命令, 和server一样先锁住命令的key所在的分片
+pub fn run(db: &Db, parts: &[&str]) -> Frame {
+    let cmd = Command::from_frame(command(parts)).unwrap();
+    let keys: Vec<String> = match cmd.keys() {
+        Some(keys) => keys.into_iter().map(str::to_string).collect(),
+        None => panic!("not a db command {:?}", cmd),
+    };
+    let mut guard = db.lock(&keys);
+    cmd.execute(&mut guard, &mut NoSession)
+}
+
+/// 测试里没有连接和server, 不访问key的命令不该走到这里
+pub struct NoSession;
+
+impl Session for NoSession {
+    fn apply(&mut self, cmd: Command) -> Frame {
+        panic!("not a db command {:?}", cmd)
+    }
+}
+
+/// HGETALL 和集合命令的回复没有固定的顺序, 排序之后再比较
+pub fn sorted(frame: Frame) -> Vec<String> {
+    let mut items: Vec<String> = match frame {
+        Frame::Map(pairs) => pairs.iter().map(|(field, value)| format!("{}={}", field, value)).collect(),
+        Frame::Set(members) => members.iter().map(|member| member.to_string()).collect(),
+        frame => panic!("unexpected frame {:?}", frame),
+    };
+    items.sort();
+    items
+}
+
+/// 临时目录下的一个AOF文件, drop时删掉, 测试panic了也一样
+pub struct TempAof {
+    path: PathBuf,
+}
+
+impl TempAof {
+    /// 每次调用都是一个新的文件, 同时运行的测试不会用到同一个文件
+    pub fn new(name: &str) -> TempAof {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "minis-redis-{}-{}-{}.aof",
+            name,
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_file(&path);
+        TempAof { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn open(&self, policy: FsyncPolicy) -> io::Result<Aof> {
+        Aof::open(&self.path, policy)
+    }
+}
+
+impl Drop for TempAof {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
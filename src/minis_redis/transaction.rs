@@ -0,0 +1,483 @@
+//! MULTI/EXEC事务和WATCH。
+//!
+//! MULTI之后连接发来的命令先放进队列, EXEC时一起执行。WATCH是乐观的, 和
+//! `notes/rocksdb/transaction.md` 中的OptimisticTransactionDB一样: WATCH时不加锁, 只记下
+//! key的版本号(见 `db`), EXEC时再检查, 有一个key被修改过就放弃整个事务, 回复nil。
+//!
+//! EXEC用一次 `Db::lock` 锁住队列中所有命令的key和WATCH的key所在的分片, 和其他多key命令
+//! 一样按分片下标从小到大加锁, 不会死锁。检查版本号和执行命令都在这些锁下完成, 其他连接
+//! 看不到执行了一半的事务。事务的AOF记录也是一起写进去的。
+//!
+//! 不访问key的命令(PUBLISH、INFO、CLIENT等)也可以排队, EXEC时交给server实现的
+//! `Session` 执行。SAVE要同一时刻的全部数据, 队列中有SAVE时EXEC锁住所有的分片, 执行到
+//! SAVE时只复制数据(`PendingSave`), 由server放开锁之后再写文件, 写完才回复EXEC。
+//!
+//! 和redis一样: 排队时出错(命令不存在、参数不对、不能在事务中执行)的事务在EXEC时直接
+//! 放弃; 执行时出错(比如WRONGTYPE)只影响出错的那条命令, 其他命令照常执行, 不会回滚。
+
+use super::cmd::{Command, Session};
+use super::db::{Db, Value};
+use super::frame::Frame;
+use super::snapshot::Snapshots;
+
+use tokio::time::Instant;
+
+/// 一个连接的事务状态, drop时取消所有的WATCH
+#[derive(Debug)]
+pub struct Transaction {
+    db: Db,
+    /// MULTI之后排队的命令, 不在事务中时是None
+    queued: Option<Vec<Command>>,
+    /// 排队时有命令出错, EXEC时放弃事务
+    failed: bool,
+    /// WATCH的key, WATCH时的版本号, 以及当时key是否存在
+    watched: Vec<(String, u64, bool)>,
+    /// 上一次EXEC中的SAVE复制出的数据, 还没有写文件
+    saves: Vec<PendingSave>,
+}
+
+/// EXEC中的一个SAVE: 在事务的锁下复制出的数据, 放开锁之后再写文件
+#[derive(Debug)]
+pub struct PendingSave {
+    /// SAVE的回复在EXEC回复中的位置
+    index: usize,
+    entries: Vec<(String, Value, Option<Instant>)>,
+}
+
+impl PendingSave {
+    /// SAVE的回复在EXEC回复中的位置, 写文件之前那里是nil
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// 把复制出的数据写成快照, 返回SAVE的回复。会阻塞到文件写完
+    pub fn write(self, snapshots: &Snapshots) -> Frame {
+        match snapshots.save_copy(&self.entries) {
+            Ok(_) => Frame::Simple("OK".to_string()),
+            Err(err) => Frame::Error(format!("ERR {}", err)),
+        }
+    }
+}
+
+impl Transaction {
+    pub fn new(db: Db) -> Transaction {
+        Transaction {
+            db,
+            queued: None,
+            failed: false,
+            watched: Vec::new(),
+            saves: Vec::new(),
+        }
+    }
+
+    /// 是否在MULTI之后、EXEC或者DISCARD之前
+    pub fn is_active(&self) -> bool {
+        self.queued.is_some()
+    }
+
+    /// MULTI
+    pub fn multi(&mut self) -> Frame {
+        if self.is_active() {
+            return Frame::Error("ERR MULTI calls can not be nested".to_string());
+        }
+        self.queued = Some(Vec::new());
+        Frame::Simple("OK".to_string())
+    }
+
+    /// 事务中收到的命令放进队列。不能在事务中执行的命令回复错误, 整个事务在EXEC时被放弃
+    pub fn queue(&mut self, cmd: Command) -> Frame {
+        let queued = self.queued.as_mut().expect("not in a transaction");
+        if cmd.keys().is_none() {
+            self.failed = true;
+            return match cmd {
+                Command::Unknown(cmd) => cmd.response(),
+                cmd => Frame::Error(format!("ERR '{}' is not allowed in a transaction", cmd.get_name())),
+            };
+        }
+        queued.push(cmd);
+        Frame::Simple("QUEUED".to_string())
+    }
+
+    /// 事务中收到的命令解析失败, 整个事务在EXEC时被放弃
+    pub fn fail(&mut self) {
+        if self.is_active() {
+            self.failed = true;
+        }
+    }
+
+    /// DISCARD
+    pub fn discard(&mut self) -> Frame {
+        if self.queued.take().is_none() {
+            return Frame::Error("ERR DISCARD without MULTI".to_string());
+        }
+        self.failed = false;
+        self.unwatch();
+        Frame::Simple("OK".to_string())
+    }
+
+    /// WATCH, 记下每个key现在的版本号。
+    ///
+    /// 事务中的WATCH和其他不能排队的命令一样, 让整个事务在EXEC时被放弃
+    pub fn watch(&mut self, keys: &[String]) -> Frame {
+        if self.is_active() {
+            self.failed = true;
+            return Frame::Error("ERR WATCH inside MULTI is not allowed".to_string());
+        }
+        let mut guard = self.db.lock(keys);
+        for key in keys {
+            let version = guard.watch(key);
+            self.watched.push((key.clone(), version, guard.contains_key(key)));
+        }
+        Frame::Simple("OK".to_string())
+    }
+
+    /// UNWATCH
+    pub fn unwatch(&mut self) {
+        if self.watched.is_empty() {
+            return;
+        }
+        let mut guard = self.db.lock(self.watched.iter().map(|(key, ..)| key));
+        for (key, ..) in self.watched.drain(..) {
+            guard.unwatch(&key);
+        }
+    }
+
+    /// EXEC: 锁住所有相关的分片, 检查WATCH的key, 然后执行队列中的命令, 不访问key的命令
+    /// 交给 `session`。
+    ///
+    /// 回复每条命令的回复组成的array; 有WATCH的key被修改过时回复nil。队列中有SAVE时,
+    /// 调用者要用 `take_saves` 取出复制的数据写文件, 再把回复填进array
+    pub fn exec(&mut self, session: &mut dyn Session) -> Frame {
+        let Some(queued) = self.queued.take() else {
+            return Frame::Error("ERR EXEC without MULTI".to_string());
+        };
+        if std::mem::take(&mut self.failed) {
+            self.unwatch();
+            return Frame::Error("EXECABORT Transaction discarded because of previous errors.".to_string());
+        }
+
+        let keys: Vec<String> = self
+            .watched
+            .iter()
+            .map(|(key, ..)| key.clone())
+            .chain(queued.iter().flat_map(|cmd| cmd.keys().unwrap()).map(str::to_string))
+            .collect();
+        let mut guard = if queued.iter().any(|cmd| matches!(cmd, Command::Save(_))) {
+            self.db.lock_all()
+        } else {
+            self.db.lock(&keys)
+        };
+
+        // 修改过, 或者在WATCH之后过期了。惰性过期不改版本号(见 `db`), 过期了还没被清理
+        // 掉的key只有比较是否存在才能发现, 这个检查不能省
+        let conflict = self.watched.iter().any(|(key, version, existed)| {
+            guard.version(key) != Some(*version) || guard.contains_key(key) != *existed
+        });
+        let reply = if conflict {
+            Frame::Null
+        } else {
+            guard.begin_batch();
+            let mut replies = Vec::with_capacity(queued.len());
+            for cmd in queued {
+                let reply = match cmd {
+                    Command::Save(_) => {
+                        self.saves.push(PendingSave {
+                            index: replies.len(),
+                            entries: guard.copy(),
+                        });
+                        Frame::Null
+                    }
+                    cmd => cmd.execute(&mut guard, session),
+                };
+                replies.push(reply);
+            }
+            guard.commit_batch();
+            Frame::Array(replies)
+        };
+
+        // 事务结束之后取消所有的WATCH, 还在同一把锁下
+        for (key, ..) in self.watched.drain(..) {
+            guard.unwatch(&key);
+        }
+        reply
+    }
+
+    /// 取出上一次EXEC中SAVE复制的数据, 要在放开锁之后调用 `PendingSave::write`
+    pub fn take_saves(&mut self) -> Vec<PendingSave> {
+        std::mem::take(&mut self.saves)
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        self.unwatch();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::Transaction;
+    use crate::minis_redis::aof::{self, FsyncPolicy};
+    use crate::minis_redis::cmd::{Command, Get, Incr, PExpire, Session, Set};
+    use crate::minis_redis::db::Db;
+    use crate::minis_redis::frame::Frame;
+    use crate::minis_redis::snapshot::Snapshots;
+    use crate::minis_redis::test_util::{NoSession, TempAof};
+
+    fn command(parts: &[&'static str]) -> Command {
+        let mut frame = Frame::array();
+        for part in parts {
+            frame.push_bulk(Bytes::from(*part));
+        }
+        Command::from_frame(frame).unwrap()
+    }
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    fn ok() -> Frame {
+        Frame::Simple("OK".to_string())
+    }
+
+    fn queued() -> Frame {
+        Frame::Simple("QUEUED".to_string())
+    }
+
+    fn get(db: &Db, key: &str) -> Frame {
+        Get::new(key).apply(db)
+    }
+
+    /// 记下交给它的命令, 回复命令的名字
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl Session for Recorder {
+        fn apply(&mut self, cmd: Command) -> Frame {
+            self.0.push(cmd.get_name().to_string());
+            Frame::Simple(cmd.get_name().to_string())
+        }
+    }
+
+    #[test]
+    fn queue_and_exec() {
+        let db = Db::new(4);
+        let mut tx = Transaction::new(db.clone());
+        assert!(matches!(tx.exec(&mut NoSession), Frame::Error(msg) if msg == "ERR EXEC without MULTI"));
+        assert!(matches!(tx.discard(), Frame::Error(_)));
+
+        assert_eq!(tx.multi(), ok());
+        assert!(matches!(tx.multi(), Frame::Error(_)));
+        for parts in [&["set", "a", "1"][..], &["incr", "a"], &["lpush", "a", "x"], &["mget", "a", "b"]] {
+            assert_eq!(tx.queue(command(parts)), queued());
+        }
+        // 还没有执行
+        assert_eq!(get(&db, "a"), Frame::Null);
+
+        let reply = tx.exec(&mut NoSession);
+        let Frame::Array(replies) = reply else {
+            panic!("unexpected reply {:?}", reply);
+        };
+        assert_eq!(replies[0], ok());
+        assert_eq!(replies[1], Frame::Integer(2));
+        // 执行时的错误不影响其他命令
+        assert!(matches!(&replies[2], Frame::Error(msg) if msg.starts_with("WRONGTYPE")));
+        assert_eq!(replies[3], Frame::Array(vec![Frame::Bulk(Bytes::from("2")), Frame::Null]));
+        assert!(!tx.is_active());
+
+        // DISCARD丢掉排队的命令
+        tx.multi();
+        tx.queue(command(&["set", "a", "3"]));
+        assert_eq!(tx.discard(), ok());
+        assert_eq!(get(&db, "a"), Frame::Bulk(Bytes::from("2")));
+    }
+
+    #[test]
+    fn errors_while_queueing_abort() {
+        let db = Db::new(4);
+        let mut tx = Transaction::new(db.clone());
+        tx.multi();
+        tx.queue(command(&["set", "a", "1"]));
+        assert!(matches!(tx.queue(command(&["flushall"])), Frame::Error(_)));
+        assert!(matches!(tx.queue(command(&["subscribe", "c"])), Frame::Error(_)));
+        assert!(
+            matches!(tx.exec(&mut NoSession), Frame::Error(msg) if msg == "EXECABORT Transaction discarded because of previous errors.")
+        );
+        assert_eq!(get(&db, "a"), Frame::Null);
+
+        // 解析失败的命令也一样, 下一个事务不受影响
+        tx.multi();
+        tx.fail();
+        assert!(matches!(tx.exec(&mut NoSession), Frame::Error(_)));
+        tx.multi();
+        tx.queue(command(&["set", "a", "1"]));
+        assert!(matches!(tx.exec(&mut NoSession), Frame::Array(_)));
+    }
+
+    #[test]
+    fn watch_inside_multi_aborts() {
+        let db = Db::new(4);
+        let mut tx = Transaction::new(db.clone());
+        tx.multi();
+        tx.queue(command(&["set", "a", "1"]));
+        assert!(matches!(tx.watch(&keys(&["a"])), Frame::Error(msg) if msg == "ERR WATCH inside MULTI is not allowed"));
+        assert!(matches!(tx.exec(&mut NoSession), Frame::Error(msg) if msg.starts_with("EXECABORT")));
+        assert_eq!(get(&db, "a"), Frame::Null);
+        // 没有留下WATCH
+        assert_eq!(db.lock(["a"]).version("a"), None);
+    }
+
+    #[test]
+    fn keyless_commands_run_on_session() {
+        let db = Db::new(4);
+        let mut tx = Transaction::new(db.clone());
+        tx.multi();
+        for parts in [
+            &["publish", "c", "m"][..],
+            &["set", "a", "1"],
+            &["info"],
+            &["client", "id"],
+            &["ping"],
+            &["unsubscribe"],
+            &["bgsave"],
+        ] {
+            assert_eq!(tx.queue(command(parts)), queued());
+        }
+        let mut session = Recorder::default();
+        let reply = tx.exec(&mut session);
+        assert_eq!(session.0, ["publish", "info", "client", "unsubscribe", "bgsave"]);
+        let simple = |s: &str| Frame::Simple(s.to_string());
+        assert_eq!(
+            reply,
+            Frame::Array(vec![
+                simple("publish"),
+                ok(),
+                simple("info"),
+                simple("client"),
+                simple("PONG"),
+                simple("unsubscribe"),
+                simple("bgsave"),
+            ])
+        );
+    }
+
+    #[test]
+    fn save_copies_under_exec_locks() {
+        let dir = std::env::temp_dir().join(format!("minis-redis-exec-save-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let snapshots = Snapshots::new(&dir);
+
+        let db = Db::new(4);
+        let mut tx = Transaction::new(db.clone());
+        tx.multi();
+        tx.queue(command(&["set", "a", "1"]));
+        tx.queue(command(&["save"]));
+        tx.queue(command(&["set", "a", "2"]));
+        let reply = tx.exec(&mut NoSession);
+        assert_eq!(reply, Frame::Array(vec![ok(), Frame::Null, ok()]));
+
+        // 快照是SAVE在事务中执行时的数据
+        let saves = tx.take_saves();
+        assert_eq!(saves.len(), 1);
+        assert_eq!(saves[0].index(), 1);
+        assert_eq!(saves.into_iter().next().unwrap().write(&snapshots), ok());
+        assert!(tx.take_saves().is_empty());
+        let restored = Db::new(4);
+        snapshots.load_newest(&restored).unwrap();
+        assert_eq!(get(&restored, "a"), Frame::Bulk(Bytes::from("1")));
+        assert_eq!(get(&db, "a"), Frame::Bulk(Bytes::from("2")));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn watch_conflicts() {
+        let db = Db::new(4);
+        Set::new("a", Bytes::from("1")).apply(&db);
+        let mut tx = Transaction::new(db.clone());
+
+        // 没有修改, 正常执行; 读不算修改
+        tx.watch(&keys(&["a", "missing"]));
+        get(&db, "a");
+        tx.multi();
+        tx.queue(command(&["incr", "a"]));
+        assert_eq!(tx.exec(&mut NoSession), Frame::Array(vec![Frame::Integer(2)]));
+
+        // 其他连接修改了WATCH的key
+        tx.watch(&keys(&["a"]));
+        Incr::new("a").apply(&db);
+        tx.multi();
+        tx.queue(command(&["incr", "a"]));
+        assert_eq!(tx.exec(&mut NoSession), Frame::Null);
+        assert_eq!(get(&db, "a"), Frame::Bulk(Bytes::from("3")));
+
+        // EXEC之后已经不再WATCH了
+        tx.multi();
+        tx.queue(command(&["incr", "a"]));
+        Incr::new("a").apply(&db);
+        assert_eq!(tx.exec(&mut NoSession), Frame::Array(vec![Frame::Integer(5)]));
+
+        // 不存在的key被创建, 以及UNWATCH
+        tx.watch(&keys(&["b"]));
+        Set::new("b", Bytes::from("1")).apply(&db);
+        tx.unwatch();
+        tx.multi();
+        assert_eq!(tx.exec(&mut NoSession), Frame::Array(vec![]));
+        tx.watch(&keys(&["c"]));
+        Set::new("c", Bytes::from("1")).apply(&db);
+        tx.multi();
+        assert_eq!(tx.exec(&mut NoSession), Frame::Null);
+
+        // WATCH之后过期了。没有清理task, key还在分片里, 版本号也没变
+        Set::new("d", Bytes::from("1")).apply(&db);
+        PExpire::new("d", 20).apply(&db);
+        tx.watch(&keys(&["d"]));
+        let version = db.lock(["d"]).version("d");
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(db.lock(["d"]).version("d"), version);
+        tx.multi();
+        assert_eq!(tx.exec(&mut NoSession), Frame::Null);
+
+        // 所有连接都不再WATCH之后, 分片里不留版本号
+        drop(tx);
+        for key in ["a", "b", "c", "d", "missing"] {
+            assert_eq!(db.lock([key]).version(key), None);
+        }
+    }
+
+    #[test]
+    fn exec_is_logged_atomically() {
+        let temp = TempAof::new("transaction");
+        let db = Db::new(4).with_aof(temp.open(FsyncPolicy::Always).unwrap());
+
+        let mut tx = Transaction::new(db.clone());
+        tx.multi();
+        tx.queue(command(&["set", "a", "1"]));
+        tx.queue(command(&["rpush", "l", "x", "y"]));
+        tx.queue(command(&["get", "a"]));
+        tx.exec(&mut NoSession);
+        Set::new("b", Bytes::from("2")).apply(&db);
+
+        let complete = std::fs::read(temp.path()).unwrap();
+        let restored = Db::new(4);
+        assert_eq!(aof::replay(temp.path(), &restored).unwrap().commands, 3);
+        assert_eq!(get(&restored, "a"), Frame::Bulk(Bytes::from("1")));
+
+        // 写了一半的事务整个被截掉
+        let mut data = complete.clone();
+        data.extend_from_slice(b"*1\r\n$5\r\nmulti\r\n*3\r\n$3\r\nset\r\n$1\r\nc\r\n$1\r\n3\r\n");
+        std::fs::write(temp.path(), &data).unwrap();
+        let restored = Db::new(4);
+        let replay = aof::replay(temp.path(), &restored).unwrap();
+        assert_eq!(replay.commands, 3);
+        assert_eq!(replay.truncated, (data.len() - complete.len()) as u64);
+        assert_eq!(get(&restored, "c"), Frame::Null);
+        assert_eq!(std::fs::read(temp.path()).unwrap(), complete);
+    }
+}